@@ -0,0 +1,244 @@
+// a language server for tests, speaking enough of the protocol
+// over stdio to exercise the client without a real one:
+//
+//   - diagnostics: a warning on every TODO, an error on XXX
+//   - hover: the word under the cursor and how often it's used
+//   - definition: the word's first use, references: every use
+//   - completion: words in the document starting like the one
+//     before the cursor
+//   - rename: every use of the word
+//   - code actions: TODO to DONE for the diagnostics asked about
+//
+// positions are utf-16 and documents are synced incrementally
+
+use std::{
+    collections::HashMap,
+    io::{
+        self,
+        BufReader,
+    },
+    process,
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+
+use editor::lsp::{
+    Encoding,
+    read_message,
+    write_message,
+};
+
+
+struct Server {
+    docs: HashMap<String, String>,
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut r = BufReader::new(stdin.lock());
+    let mut server = Server{
+        docs: HashMap::new(),
+    };
+
+    while let Ok(Some(msg)) = read_message(&mut r) {
+        for out in server.handle(&msg) {
+            write_message(&mut io::stdout(), &out).unwrap();
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the byte offset in text of a utf-16 position
+fn offset(text: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap() as usize;
+    let ch = pos["character"].as_u64().unwrap() as usize;
+
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+    start + Encoding::Utf16.byte(&text[start..end], ch)
+}
+
+// the utf-16 position of a byte offset in text
+fn position(text: &str, i: usize) -> Value {
+    let start = text[..i].rfind('\n').map_or(0, |j| j + 1);
+    let line = text[..start].matches('\n').count();
+    json!({"line": line, "character": Encoding::Utf16.character(&text[start..], i - start)})
+}
+
+fn range(text: &str, a: usize, b: usize) -> Value {
+    json!({"start": position(text, a), "end": position(text, b)})
+}
+
+// the start and end of the word around byte i
+fn word_at(text: &str, i: usize) -> (usize, usize) {
+    let start = text[..i].char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(i, |(j, _)| j);
+    let end = text[i..].char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(text.len(), |(j, _)| i + j);
+    (start, end)
+}
+
+// where word is used as a whole word
+fn uses(text: &str, word: &str) -> Vec<usize> {
+    text.match_indices(word)
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            let before = text[..i].chars().next_back().is_none_or(|c| !is_word(c));
+            let after = text[i + word.len()..].chars().next().is_none_or(|c| !is_word(c));
+            before && after
+        })
+        .collect()
+}
+
+impl Server {
+    fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("").to_string();
+
+        let result = match msg["method"].as_str().unwrap_or("") {
+            "initialize" => json!({
+                "capabilities": {
+                    "positionEncoding": "utf-16",
+                    "textDocumentSync": {"openClose": true, "change": 2},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "completionProvider": {},
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                },
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap().to_string();
+                self.docs.insert(uri.clone(), text);
+                return vec![self.diagnostics(&uri)];
+            },
+            "textDocument/didChange" => {
+                let text = self.docs.get_mut(&uri).unwrap();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let new = change["text"].as_str().unwrap();
+                    if change.get("range").is_none() {
+                        *text = new.to_string();
+                        continue;
+                    }
+                    let a = offset(text, &change["range"]["start"]);
+                    let b = offset(text, &change["range"]["end"]);
+                    text.replace_range(a..b, new);
+                }
+                return vec![self.diagnostics(&uri)];
+            },
+            "textDocument/hover" => {
+                let (text, word) = self.word(&uri, &params["position"]);
+                json!({"contents": {
+                    "kind": "markdown",
+                    "value": format!("`{}` used {} times", word, uses(text, word).len()),
+                }})
+            },
+            "textDocument/definition" => {
+                let (text, word) = self.word(&uri, &params["position"]);
+                match uses(text, word).first() {
+                    Some(&i) => json!({"uri": uri, "range": range(text, i, i + word.len())}),
+                    None => Value::Null,
+                }
+            },
+            "textDocument/references" => {
+                let (text, word) = self.word(&uri, &params["position"]);
+                uses(text, word).iter()
+                    .map(|&i| json!({"uri": uri, "range": range(text, i, i + word.len())}))
+                    .collect()
+            },
+            "textDocument/completion" => {
+                let text = &self.docs[&uri];
+                let i = offset(text, &params["position"]);
+                let (start, _) = word_at(text, i);
+                let prefix = &text[start..i];
+
+                let mut words: Vec<&str> = text.split(|c| !is_word(c))
+                    .filter(|w| w.starts_with(prefix) && *w != prefix)
+                    .collect();
+                words.sort_unstable();
+                words.dedup();
+                let items: Vec<Value> = words.iter()
+                    .map(|w| json!({"label": w, "detail": "word"}))
+                    .collect();
+                json!({"isIncomplete": false, "items": items})
+            },
+            "textDocument/rename" => {
+                let (text, word) = self.word(&uri, &params["position"]);
+                let name = params["newName"].as_str().unwrap();
+                let edits: Vec<Value> = uses(text, word).iter()
+                    .map(|&i| json!({"range": range(text, i, i + word.len()), "newText": name}))
+                    .collect();
+                json!({"changes": {uri: edits}})
+            },
+            "textDocument/codeAction" => {
+                params["context"]["diagnostics"].as_array().unwrap().iter()
+                    .filter(|d| d["code"] == "todo")
+                    .map(|d| json!({
+                        "title": "Mark done",
+                        "kind": "quickfix",
+                        "edit": {"changes": {uri.clone(): [
+                            {"range": d["range"], "newText": "DONE"},
+                        ]}},
+                    }))
+                    .collect()
+            },
+            "shutdown" => Value::Null,
+            "exit" => process::exit(0),
+            _ => return Vec::new(),
+        };
+
+        match msg.get("id") {
+            Some(id) => vec![json!({"jsonrpc": "2.0", "id": id, "result": result})],
+            None => Vec::new(),
+        }
+    }
+
+    // the document's text and the word at a position in it
+    fn word(&self, uri: &str, pos: &Value) -> (&str, &str) {
+        let text = &self.docs[uri];
+        let (start, end) = word_at(text, offset(text, pos));
+        (text, &text[start..end])
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = &self.docs[uri];
+        let mut diagnostics = Vec::new();
+
+        for (word, severity, code, message) in [
+            ("TODO", 2, "todo", "TODO left in"),
+            ("XXX", 1, "xxx", "XXX left in"),
+        ] {
+            for i in uses(text, word) {
+                diagnostics.push(json!({
+                    "range": range(text, i, i + word.len()),
+                    "severity": severity,
+                    "code": code,
+                    "message": message,
+                }));
+            }
+        }
+
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics},
+        })
+    }
+}
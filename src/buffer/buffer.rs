@@ -2,6 +2,8 @@ use std::{
     io::{
         self,
     },
+    fs,
+    path::Path,
     collections::LinkedList,
     default::Default,
     convert::From,
@@ -9,31 +11,80 @@ use std::{
 
 use crate::{
     Result,
+    LineConfig,
+    FileConfig,
+    filetype,
 };
 
 
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Buffer {
     pub(super) lines: LinkedList<String>,
+    pub(super) lcfg: LineConfig,
+    pub(super) fcfg: FileConfig,
+    // changed since it was read or written
+    pub(super) modified: bool,
 }
 
 impl Buffer {
-    pub fn collate<W : io::Write> (&self, w: &mut W) -> Result<()> {
-        for line in self.lines.iter() {
-            // TODO pick line ending
-            write!(w, "{}{}", line, "\n")?;
+    // reads the file at path, a file which doesn't exist yet
+    // gives an empty buffer that will be written to path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Buffer> {
+        let path = path.as_ref();
+        let mut ret = Buffer::default();
+        ret.fcfg.path = Some(path.to_path_buf());
+
+        match fs::read(path) {
+            Ok(b) => {
+                ret.lines = ret.fcfg.decode(&b).into_iter().collect();
+                ret.fcfg.read_only = fs::metadata(path)
+                    .map(|m| m.permissions().readonly())
+                    .unwrap_or(false);
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into()),
         }
 
-        Ok(())
+        Ok(ret)
     }
-}
 
-impl Default for Buffer {
-    fn default() -> Self {
-        Buffer{
-            lines: LinkedList::new(),
-        }
+    // guesses the filetype from the file name and the first
+    // and last lines, rules are the user's filetype and glob
+    // pairs
+    pub fn detect_filetype(&mut self, rules: &[(String, String)]) {
+        let n = self.lines.len();
+        let lines: Vec<&str> = self.lines.iter()
+            .enumerate()
+            .filter(|(i, _)| *i < 5 || *i + 5 >= n)
+            .map(|(_, l)| l.as_str())
+            .collect();
+
+        self.fcfg.filetype = filetype::detect(rules, self.fcfg.path.as_deref(), &lines);
+    }
+
+    pub fn collate<W : io::Write> (&self, w: &mut W) -> Result<()> {
+        self.fcfg.collate(w, self.lines.iter())
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn file_config(&self) -> &FileConfig {
+        &self.fcfg
+    }
+
+    pub fn file_config_mut(&mut self) -> &mut FileConfig {
+        &mut self.fcfg
+    }
+
+    pub fn line_config(&self) -> &LineConfig {
+        &self.lcfg
+    }
+
+    pub fn line_config_mut(&mut self) -> &mut LineConfig {
+        &mut self.lcfg
     }
 }
 
@@ -47,4 +98,3 @@ impl From<&str> for Buffer {
         ret
     }
 }
-
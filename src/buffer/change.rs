@@ -0,0 +1,58 @@
+// the lines start..old_end of some earlier text which are now
+// start..new_end, edits after it are merged in so one change
+// covers everything since then
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct LineChange {
+    pub start: usize,
+    pub old_end: usize,
+    pub new_end: usize,
+}
+
+impl LineChange {
+    // old lines starting at line were replaced with new ones
+    pub fn new(line: usize, old: usize, new: usize) -> LineChange {
+        LineChange{
+            start: line,
+            old_end: line + old,
+            new_end: line + new,
+        }
+    }
+
+    // this change followed by old lines at line being replaced
+    // with new ones
+    pub fn then(self, line: usize, old: usize, new: usize) -> LineChange {
+        // lines after new_end are the same as after old_end
+        let end = self.new_end.max(line + old);
+        LineChange{
+            start: self.start.min(line),
+            old_end: self.old_end + end - self.new_end,
+            new_end: end + new - old,
+        }
+    }
+
+    // adds an edit to what changed so far
+    pub fn merge(change: Option<LineChange>, line: usize, old: usize, new: usize) ->
+        LineChange {
+
+        match change {
+            None => LineChange::new(line, old, new),
+            Some(c) => c.then(line, old, new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_edits() {
+        // a line inserted after an edited one
+        let c = LineChange::merge(Some(LineChange::new(3, 1, 1)), 4, 0, 1);
+        assert_eq!(c, LineChange{ start: 3, old_end: 4, new_end: 5 });
+
+        // two lines removed before it
+        let c = LineChange::merge(Some(c), 1, 2, 0);
+        assert_eq!(c, LineChange{ start: 1, old_end: 4, new_end: 3 });
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    io,
+    path::PathBuf,
+};
+
+use crate::{
+    Result,
+};
+
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn from_name(s: &str) -> Option<LineEnding> {
+        match s {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+            LineEnding::Cr => "cr",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    // picks the ending of the first line, files with
+    // no line breaks are lf
+    fn detect(s: &str) -> LineEnding {
+        match s.find(['\n', '\r']) {
+            Some(idx) if s[idx..].starts_with("\r\n") => LineEnding::CrLf,
+            Some(idx) if s[idx..].starts_with('\r') => LineEnding::Cr,
+            _ => LineEnding::Lf,
+        }
+    }
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum Charset {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Charset {
+    pub fn from_name(s: &str) -> Option<Charset> {
+        match s {
+            "utf-8" => Some(Charset::Utf8),
+            "utf-8-bom" => Some(Charset::Utf8Bom),
+            "latin1" => Some(Charset::Latin1),
+            "utf-16le" => Some(Charset::Utf16Le),
+            "utf-16be" => Some(Charset::Utf16Be),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Utf8 => "utf-8",
+            Charset::Utf8Bom => "utf-8-bom",
+            Charset::Latin1 => "latin1",
+            Charset::Utf16Le => "utf-16le",
+            Charset::Utf16Be => "utf-16be",
+        }
+    }
+
+    // decodes the bytes, guessing the charset from the byte
+    // order mark or falling back to latin1 if the bytes are
+    // not valid utf-8
+    fn decode(b: &[u8]) -> (Charset, String) {
+        let utf16 = |b: &[u8], f: fn([u8;2]) -> u16| {
+            let units: Vec<u16> = b.chunks(2)
+                .map(|c| f([c[0], *c.get(1).unwrap_or(&0)]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        };
+
+        if let Some(rest) = b.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+            let s = String::from_utf8_lossy(rest).into_owned();
+            return (Charset::Utf8Bom, s);
+        }
+        if let Some(rest) = b.strip_prefix(&[0xff, 0xfe]) {
+            return (Charset::Utf16Le, utf16(rest, u16::from_le_bytes));
+        }
+        if let Some(rest) = b.strip_prefix(&[0xfe, 0xff]) {
+            return (Charset::Utf16Be, utf16(rest, u16::from_be_bytes));
+        }
+
+        match std::str::from_utf8(b) {
+            Ok(s) => (Charset::Utf8, s.into()),
+            Err(_) => (Charset::Latin1, b.iter().map(|c| *c as char).collect()),
+        }
+    }
+
+    fn encode<W: io::Write>(&self, w: &mut W, s: &str) -> io::Result<()> {
+        match self {
+            Charset::Utf8 | Charset::Utf8Bom => w.write_all(s.as_bytes()),
+            Charset::Latin1 => {
+                let b: Vec<u8> = s.chars()
+                    .map(|c| if (c as u32) < 256 { c as u8 } else { b'?' })
+                    .collect();
+                w.write_all(&b)
+            },
+            Charset::Utf16Le => {
+                for u in s.encode_utf16() {
+                    w.write_all(&u.to_le_bytes())?;
+                }
+                Ok(())
+            },
+            Charset::Utf16Be => {
+                for u in s.encode_utf16() {
+                    w.write_all(&u.to_be_bytes())?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    fn bom(&self) -> &'static str {
+        match self {
+            Charset::Utf8 | Charset::Latin1 => "",
+            _ => "\u{feff}",
+        }
+    }
+}
+
+// how the buffer is read from and written to disk
+#[derive(Debug,Clone)]
+pub struct FileConfig {
+    pub path: Option<PathBuf>,
+    pub line_ending: LineEnding,
+    pub charset: Charset,
+    pub trim_trailing_whitespace: bool,
+    pub final_newline: bool,
+    // the file can't be written
+    pub read_only: bool,
+    // what kind of file it is, like `rust`
+    pub filetype: Option<String>,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        FileConfig{
+            path: None,
+            line_ending: LineEnding::Lf,
+            charset: Charset::Utf8,
+            trim_trailing_whitespace: false,
+            final_newline: true,
+            read_only: false,
+            filetype: None,
+        }
+    }
+}
+
+impl FileConfig {
+    // where a copy of unsaved changes goes when the editor
+    // can't exit normally, next to the file or in the temp
+    // directory for buffers without one
+    pub fn recovery_path(&self) -> PathBuf {
+        match &self.path {
+            Some(p) => {
+                let mut name = p.file_name().unwrap_or_default().to_os_string();
+                name.push(".recover");
+                p.with_file_name(name)
+            },
+            None => std::env::temp_dir()
+                .join(format!("editor-{}.recover", std::process::id())),
+        }
+    }
+
+    // splits the file contents into lines, setting the line
+    // ending, charset and final newline to match
+    pub(super) fn decode(&mut self, b: &[u8]) -> Vec<String> {
+        let (charset, s) = Charset::decode(b);
+        self.charset = charset;
+        self.line_ending = LineEnding::detect(&s);
+
+        let le = self.line_ending.as_str();
+        let s = match s.strip_suffix(le) {
+            Some(s) => {
+                self.final_newline = true;
+                s
+            },
+            None => {
+                self.final_newline = s.is_empty();
+                s.as_str()
+            },
+        };
+
+        s.split(le).map(String::from).collect()
+    }
+
+    pub fn collate<'a, W, I>(&self, w: &mut W, lines: I) -> Result<()>
+    where
+        W: io::Write,
+        I: Iterator<Item = &'a String>,
+    {
+        let le = self.line_ending.as_str();
+        let mut lines = lines.peekable();
+
+        self.charset.encode(w, self.charset.bom())?;
+
+        while let Some(line) = lines.next() {
+            let line = if self.trim_trailing_whitespace {
+                line.trim_end_matches([' ', '\t'])
+            } else {
+                line.as_str()
+            };
+
+            self.charset.encode(w, line)?;
+            if lines.peek().is_some() || self.final_newline {
+                self.charset.encode(w, le)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(b: &[u8]) -> (FileConfig, Vec<String>) {
+        let mut fcfg = FileConfig::default();
+        let lines = fcfg.decode(b);
+        (fcfg, lines)
+    }
+
+    fn collate(fcfg: &FileConfig, lines: &[&str]) -> Vec<u8> {
+        let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        let mut ret = Vec::new();
+        fcfg.collate(&mut ret, lines.iter()).unwrap();
+        ret
+    }
+
+    #[test]
+    fn decodes_charsets() {
+        let (fcfg, lines) = decode(b"\xef\xbb\xbfa\xc3\xa9\n");
+        assert_eq!(fcfg.charset, Charset::Utf8Bom);
+        assert_eq!(lines, vec!["aé"]);
+
+        let (fcfg, lines) = decode(b"\xff\xfea\x00\xe9\x00\n\x00");
+        assert_eq!(fcfg.charset, Charset::Utf16Le);
+        assert_eq!(lines, vec!["aé"]);
+
+        let (fcfg, lines) = decode(b"\xfe\xff\x00a\x00\xe9\x00\n");
+        assert_eq!(fcfg.charset, Charset::Utf16Be);
+        assert_eq!(lines, vec!["aé"]);
+
+        let (fcfg, lines) = decode(b"a\xe9\n");
+        assert_eq!(fcfg.charset, Charset::Latin1);
+        assert_eq!(lines, vec!["aé"]);
+    }
+
+    #[test]
+    fn decodes_line_endings_and_final_newline() {
+        let (fcfg, lines) = decode(b"a\r\nb\r\n");
+        assert_eq!(fcfg.line_ending, LineEnding::CrLf);
+        assert!(fcfg.final_newline);
+        assert_eq!(lines, vec!["a", "b"]);
+
+        let (fcfg, lines) = decode(b"a\rb");
+        assert_eq!(fcfg.line_ending, LineEnding::Cr);
+        assert!(!fcfg.final_newline);
+        assert_eq!(lines, vec!["a", "b"]);
+
+        let (fcfg, lines) = decode(b"");
+        assert!(fcfg.final_newline);
+        assert_eq!(lines, vec![""]);
+    }
+
+    #[test]
+    fn collates_what_was_decoded() {
+        for b in [&b"\xef\xbb\xbfa\r\nb\r\n"[..], b"\xff\xfea\x00\n\x00", b"a\xe9\rb"] {
+            let (fcfg, lines) = decode(b);
+            let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+            assert_eq!(collate(&fcfg, &lines), b);
+        }
+    }
+
+    #[test]
+    fn collate_trims_and_ends_lines() {
+        let mut fcfg = FileConfig{
+            line_ending: LineEnding::CrLf,
+            trim_trailing_whitespace: true,
+            ..FileConfig::default()
+        };
+        assert_eq!(collate(&fcfg, &["a \t", " b", ""]), b"a\r\n b\r\n\r\n");
+
+        fcfg.trim_trailing_whitespace = false;
+        fcfg.final_newline = false;
+        assert_eq!(collate(&fcfg, &["a ", "b"]), b"a \r\nb");
+
+        // characters latin1 doesn't have
+        fcfg.charset = Charset::Latin1;
+        assert_eq!(collate(&fcfg, &["é€"]), b"\xe9?");
+    }
+}
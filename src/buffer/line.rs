@@ -7,10 +7,57 @@ use unicode_width::{
     UnicodeWidthStr,
 };
 
+use crate::renderer::escape_controls;
 
-#[derive(Debug)]
+
+#[derive(Debug, Clone)]
 pub struct LineConfig {
     pub tab_width: u8,
+    // columns per indent level when indenting with spaces
+    pub indent_size: u8,
+    // indent with spaces instead of tabs
+    pub expand_tab: bool,
+    // the bracket pairs lines are indented a level between,
+    // set by the filetype
+    pub indent_brackets: &'static str,
+}
+
+impl Default for LineConfig {
+    fn default() -> Self {
+        LineConfig{
+            tab_width: 8,
+            indent_size: 8,
+            expand_tab: false,
+            indent_brackets: "",
+        }
+    }
+}
+
+impl LineConfig {
+    // the columns of a level of indent
+    pub fn indent_width(&self) -> usize {
+        let w = if self.expand_tab { self.indent_size } else { self.tab_width };
+        w.max(1) as usize
+    }
+
+    // white space taking cols columns, tabs first unless
+    // indenting with spaces
+    pub fn indent_string(&self, cols: usize) -> String {
+        if self.expand_tab {
+            return " ".repeat(cols);
+        }
+        let tw = self.tab_width.max(1) as usize;
+        "\t".repeat(cols / tw) + &" ".repeat(cols % tw)
+    }
+}
+
+// a screen row of a wrapped line, the columns start..end
+// drawn after indent columns of padding
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub struct Row {
+    pub start: usize,
+    pub end: usize,
+    pub indent: usize,
 }
 
 #[derive(Debug)]
@@ -20,11 +67,11 @@ pub struct Line<'a, 'b> {
 }
 
 impl<'a,'b> Line <'a, 'b> {
-   fn col2idx(&self, col: usize) -> usize {
+    pub(super) fn col2idx(&self, col: usize) -> usize {
         let mut width = 0;
         let mut idx;
         for (gidx, g)  in self.s.grapheme_indices(true) {
-            width += UnicodeWidthStr::width(g);
+            width += self.grapheme_cols(g);
             idx = gidx;
 
             if width > col {
@@ -39,26 +86,162 @@ impl<'a,'b> Line <'a, 'b> {
     pub fn cols(&self) -> usize {
         let mut ret = 0;
         for g in UnicodeSegmentation::graphemes(self.s, true) {
-            ret += match g {
-                "\t" => self.cfg.tab_width as usize,
-                _ => UnicodeWidthStr::width(g),
-            };
+            ret += self.grapheme_cols(g);
         }
 
         ret
     }
 
+    // the text between the display columns start and start+width
+    // with tabs expanded to spaces. the flags are set when there
+    // is text hidden to the left or right of the range. a wide
+    // grapheme cut by the left edge is replaced by spaces and one
+    // cut by the right edge is left out
+    pub fn clip_cols(&self, start: usize, width: usize) -> (String, bool, bool) {
+        let end = start + width;
+        let mut ret = String::new();
+        let mut col = 0;
+        let mut left = false;
+        let mut right = false;
+
+        for g in UnicodeSegmentation::graphemes(self.s, true) {
+            let w = self.grapheme_cols(g);
+
+            if col < start {
+                left = true;
+                if col + w > start {
+                    // cut by the left edge
+                    ret.extend(std::iter::repeat_n(' ', (col + w - start).min(width)));
+                }
+            } else if col + w > end || (w == 0 && col == end) {
+                right = true;
+                break;
+            } else if g == "\t" {
+                ret.extend(std::iter::repeat_n(' ', w));
+            } else {
+                ret.push_str(&escape_controls(g));
+            }
+
+            col += w;
+        }
+
+        (ret, left, right)
+    }
+
+    // control characters other than tab take the columns of
+    // how they're drawn
+    fn grapheme_cols(&self, g: &str) -> usize {
+        match g {
+            "\t" => self.cfg.tab_width as usize,
+            _ => UnicodeWidthStr::width(escape_controls(g).as_ref()),
+        }
+    }
+
+    // columns taken by the leading white space
+    pub fn indent_cols(&self) -> usize {
+        UnicodeSegmentation::graphemes(self.s, true)
+            .take_while(|g| CharClass::from_str(g) == CharClass::WhiteSpace)
+            .map(|g| self.grapheme_cols(g))
+            .sum()
+    }
+
+    // splits the line into rows of at most width columns. with
+    // line_break rows end after white space or punctuation when
+    // possible, with break_indent rows after the first are
+    // indented as much as the line
+    pub fn wrap_rows(&self, width: usize, line_break: bool, break_indent: bool) -> Vec<Row> {
+        let width = width.max(1);
+        let indent = if break_indent {
+            let indent = self.indent_cols();
+            // keep some room for the text
+            if indent + (width / 2).min(20) > width { 0 } else { indent }
+        } else {
+            0
+        };
+
+        let mut ret = Vec::new();
+        let mut row = Row{
+            start: 0,
+            end: 0,
+            indent: 0,
+        };
+        // the end of the last grapheme a row can break after
+        let mut brk = None;
+        let mut col = 0;
+
+        for g in UnicodeSegmentation::graphemes(self.s, true) {
+            let w = self.grapheme_cols(g);
+
+            while col + w - row.start > width - row.indent && col > row.start {
+                let end = match brk {
+                    Some(b) if line_break && b > row.start => b,
+                    _ => col,
+                };
+                row.end = end;
+                ret.push(row);
+
+                row = Row{
+                    start: end,
+                    end,
+                    indent,
+                };
+                brk = None;
+            }
+
+            col += w;
+
+            let cl = CharClass::from_str(g);
+            if cl == CharClass::WhiteSpace || cl == CharClass::Punctuation {
+                brk = Some(col);
+            }
+        }
+
+        row.end = col;
+        ret.push(row);
+
+        ret
+    }
+
     pub fn first_non_white_space(&self) -> usize {
         let mut ret = 0;
         for g in UnicodeSegmentation::graphemes(self.s, true) {
-            if CharClass::from_str(g) == CharClass::WhiteSpace {
+            if CharClass::from_str(g) != CharClass::WhiteSpace {
                 return ret
             }
-            ret += UnicodeWidthStr::width(g);
+            ret += self.grapheme_cols(g);
         }
         ret
     }
 
+    // the levels the line after this one is indented more for
+    // the brackets this one leaves open, or less for the ones it
+    // closes. only the pairs in brackets count, and a closing
+    // one starting the line has taken its own indent already
+    pub fn indent_change(&self, brackets: &str) -> isize {
+        let mut open = 0;
+        let mut closed = 0;
+        for (i, c) in self.s.trim_start().char_indices() {
+            if !brackets.contains(c) {
+                continue;
+            }
+            match CharClass::from_char(c) {
+                CharClass::LBracket => open += 1,
+                CharClass::RBracket if open > 0 => open -= 1,
+                CharClass::RBracket if i > 0 => closed += 1,
+                _ => {},
+            }
+        }
+        (open - closed).clamp(-1, 1)
+    }
+
+    // whether the line's text starts with one of the closing
+    // brackets in brackets
+    pub fn starts_with_closer(&self, brackets: &str) -> bool {
+        self.s.trim_start().chars().next().is_some_and(|c| {
+            brackets.contains(c) && CharClass::from_char(c) == CharClass::RBracket
+        })
+    }
+
     // returns the column of the next word
     // it will get stck in the column after the last
     // grapheme
@@ -113,6 +296,16 @@ impl<'a,'b> Line <'a, 'b> {
         ret
     }
 
+    // the column where the word ending at col starts, col
+    // itself when there's no word before it
+    pub fn word_start_col(&self, col: usize) -> usize {
+        let idx = self.col2idx(col);
+        match self.s[..idx].chars().next_back() {
+            Some(c) if CharClass::from_char(c) == CharClass::Other => self.prev_word_col(col),
+            _ => col,
+        }
+    }
+
     // returns the column of the next word
     // it will get stck in the column after the last
     // grapheme
@@ -191,7 +384,7 @@ impl<'a,'b> LineMut<'a, 'b> {
         match gc.next_boundary(s, 0).unwrap() {
             Some(idx_end) => {
                 // trim from the middle
-                let w = UnicodeWidthStr::width(&s[idx..idx_end]);
+                let w = Line{s: &s[idx..idx_end], cfg: self.cfg}.cols();
                 unsafe {
                     s.as_bytes_mut().copy_within(idx_end.., idx);
                 };
@@ -201,7 +394,7 @@ impl<'a,'b> LineMut<'a, 'b> {
             },
             None => {
                 // trim from the end
-                let w = UnicodeWidthStr::width(&s[idx..]);
+                let w = Line{s: &s[idx..], cfg: self.cfg}.cols();
                 self.s.truncate(idx);
 
                 w
@@ -209,14 +402,17 @@ impl<'a,'b> LineMut<'a, 'b> {
         }
     }
 
+    // cuts the line at col, returning the part after it
+    pub fn split_off_col(&mut self, col: usize) -> String {
+        let idx = self.as_line().col2idx(col);
+        self.s.split_off(idx)
+    }
+
     pub fn insert_str_col(&mut self, col: usize, s : &str) -> usize {
         let idx = self.as_line().col2idx(col);
         self.s.insert_str(idx, s);
-        let ntabs = s.chars()
-                .filter(|c| *c == '\t')
-                .count();
 
-        UnicodeWidthStr::width(s) + (self.cfg.tab_width as usize * ntabs)
+        Line{s, cfg: self.cfg}.cols()
     }
 }
 
@@ -233,7 +429,7 @@ enum CharClass {
 
 impl CharClass {
     fn from_str(s: &str) -> CharClass {
-        s.chars().next().map_or(Self::Empty, |c| Self::from_char(c))
+        s.chars().next().map_or(Self::Empty, Self::from_char)
     }
 
     fn from_char(c: char) -> CharClass {
@@ -1,6 +1,16 @@
 mod view;
-pub use view::View;
+pub use view::{
+    View,
+    ViewConfig,
+    Sign,
+    SignColumn,
+    VirtualText,
+};
+
+mod change;
+pub use change::LineChange;
 
+#[allow(clippy::module_inception)]
 mod buffer;
 pub use buffer::Buffer;
 
@@ -11,3 +21,10 @@ pub use line::{
     LineConfig,
 };
 
+
+mod file;
+pub use file::{
+    FileConfig,
+    LineEnding,
+    Charset,
+};
@@ -1,101 +1,1222 @@
 
+use unicode_segmentation::{
+    UnicodeSegmentation,
+};
+
+use unicode_width::{
+    UnicodeWidthStr,
+};
+
 use std::{
-    collections::linked_list::CursorMut,
+    io,
+    fs,
+    path::Path,
+    collections::{
+        BTreeMap,
+        linked_list::{
+            Cursor,
+            CursorMut,
+        },
+    },
+};
+
+use super::{
+    line::Row,
 };
 
 use crate::{
+    Error,
     Line,
     LineMut,
     LineConfig,
+    FileConfig,
     Buffer,
     Renderer,
     Result,
+    Style,
+    Highlighter,
+    LineChange,
+    Theme,
 };
 
+#[cfg(feature = "tree-sitter")]
+use crate::SyntaxTree;
+
+#[derive(Debug, Clone, Default)]
+pub struct ViewConfig {
+    // break long lines into several rows instead of scrolling
+    // horizontally
+    pub wrap: bool,
+    // wrap after white space or punctuation instead of at the
+    // last grapheme that fits
+    pub line_break: bool,
+    // indent wrapped rows as much as the start of the line
+    pub break_indent: bool,
+    // rows kept between the cursor and the top or bottom of
+    // the screen
+    pub scroll_off: usize,
+    // line numbers in the gutter, with both set the current
+    // line has its number and the others are relative
+    pub number: bool,
+    pub relative_number: bool,
+    pub sign_column: SignColumn,
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Default)]
+pub enum SignColumn {
+    // only when a line has a sign
+    #[default]
+    Auto,
+    Yes,
+    No,
+}
+
+impl SignColumn {
+    pub fn from_name(s: &str) -> Option<SignColumn> {
+        match s {
+            "auto" => Some(SignColumn::Auto),
+            "yes" => Some(SignColumn::Yes),
+            "no" => Some(SignColumn::No),
+            _ => None,
+        }
+    }
+}
+
+// a marker in the sign column, like a diagnostic or a
+// breakpoint. the group is who put it there, text is one or
+// two columns wide and drawn in the highlight group
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Sign {
+    pub group: String,
+    pub text: String,
+    pub highlight: String,
+    // the highest one is shown when a line has several
+    pub priority: i32,
+}
+
+// text drawn after the end of a line which isn't part of it,
+// like a diagnostic's message
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct VirtualText {
+    pub group: String,
+    pub text: String,
+    pub highlight: String,
+}
+
+// the columns of the sign column
+const SIGN_WIDTH: usize = 2;
+
+// the most groups of edits which can be undone
+const UNDO_LEVELS: usize = 1000;
+
+// an edit as undo needs it, the first line, the lines which
+// were there and how many lines replaced them
+#[derive(Debug,Clone)]
+struct Edit {
+    idx: usize,
+    old: Vec<String>,
+    new: usize,
+}
+
+// edits undone together and the cursor before them
+#[derive(Debug,Clone)]
+struct UndoGroup {
+    cursor: (usize, usize),
+    edits: Vec<Edit>,
+}
+
+// a row of the screen as render lays it out
+#[derive(Clone)]
+struct ScreenRow<'a> {
+    gutter: String,
+    // of the line number
+    number: Style,
+    sign: Option<&'a Sign>,
+    text: String,
+    runs: Vec<(usize, usize, Style)>,
+}
+
 pub struct View<'buf> {
     cur : CursorMut<'buf, String>,
-    lcfg : &'buf LineConfig,
+    lcfg : &'buf mut LineConfig,
+    fcfg : &'buf mut FileConfig,
+    modified : &'buf mut bool,
     cx : usize, // x position in current line
+    left_col : usize, // first column shown, for horizontal scrolling
+    top : usize, // first line shown
+    width : usize, // width of the last render
+    height : usize, // height of the last render
+    vcfg : ViewConfig,
+    // the other end of the selection, as line and column
+    anchor : Option<(usize, usize)>,
+    // signs and virtual text by line
+    signs : BTreeMap<usize, Vec<Sign>>,
+    virtual_text : BTreeMap<usize, Vec<VirtualText>>,
+    // the lines changed since take_changes
+    changes : Option<LineChange>,
+    // the edits which can be undone, the last group is still
+    // open while undo_open is set
+    undo : Vec<UndoGroup>,
+    undo_open : bool,
+    gutter : usize, // gutter width of the last render
+    highlight : Option<Highlighter>,
+    #[cfg(feature = "tree-sitter")]
+    syntax : Option<SyntaxTree>,
+    theme : Theme,
 }
 
 impl<'buf> View <'buf> {
     pub fn new(buf: &'buf mut Buffer) -> Self {
-        if buf.lines.len() == 0 {
+        if buf.lines.is_empty() {
             buf.lines.push_back("".into());
         }
 
         View{
             cur: buf.lines.cursor_front_mut(),
             cx: 0,
-            lcfg: &LineConfig{
-                tab_width: 8,
-            },
+            left_col: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+            vcfg: ViewConfig::default(),
+            anchor: None,
+            signs: BTreeMap::new(),
+            virtual_text: BTreeMap::new(),
+            changes: None,
+            undo: Vec::new(),
+            undo_open: false,
+            gutter: 0,
+            highlight: None,
+            #[cfg(feature = "tree-sitter")]
+            syntax: None,
+            theme: Theme::default(),
+            lcfg: &mut buf.lcfg,
+            fcfg: &mut buf.fcfg,
+            modified: &mut buf.modified,
         }
     }
 
+    pub fn line_config(&self) -> &LineConfig {
+        self.lcfg
+    }
+
+    pub fn line_config_mut(&mut self) -> &mut LineConfig {
+        self.lcfg
+    }
+
+    pub fn view_config(&self) -> &ViewConfig {
+        &self.vcfg
+    }
+
+    pub fn view_config_mut(&mut self) -> &mut ViewConfig {
+        &mut self.vcfg
+    }
+
+    pub fn file_config(&self) -> &FileConfig {
+        self.fcfg
+    }
+
+    pub fn file_config_mut(&mut self) -> &mut FileConfig {
+        self.fcfg
+    }
+
+    pub fn collate<W : io::Write> (&self, w: &mut W) -> Result<()> {
+        self.fcfg.collate(w, self.cur.as_cursor().as_list().iter())
+    }
+
+    pub fn is_modified(&self) -> bool {
+        *self.modified
+    }
+
+    // writes the buffer to its file
+    pub fn save(&mut self) -> Result<()> {
+        let path = self.fcfg.path.as_ref()
+            .ok_or_else(|| Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no file name")))?;
+
+        self.save_as(path)?;
+        *self.modified = false;
+        Ok(())
+    }
+
+    // writes a copy of the buffer to its recovery path if it
+    // has unsaved changes, returning where
+    pub fn write_recovery(&self) -> Result<Option<std::path::PathBuf>> {
+        if !*self.modified {
+            return Ok(None);
+        }
+
+        let path = self.fcfg.recovery_path();
+        self.save_as(&path)?;
+        Ok(Some(path))
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut w = io::BufWriter::new(fs::File::create(path)?);
+        self.collate(&mut w)?;
+        io::Write::flush(&mut w)?;
+
+        Ok(())
+    }
+
 
-    fn current(&self) -> Line {
+    fn current(&self) -> Line<'_, '_> {
         Line{
             s: self.cur.as_cursor().current().unwrap().as_str(),
             cfg: self.lcfg,
         }
     }
 
-    fn current_mut(&mut self) -> LineMut {
+    // the current line for editing, marks the buffer modified
+    // so it's only for when the line is sure to change
+    fn current_mut(&mut self) -> LineMut<'_, '_> {
+        self.lines_replaced(self.cur.index().unwrap(), 1, 1);
         LineMut{
             s: self.cur.current().unwrap(),
             cfg: self.lcfg,
         }
     }
 
-    pub fn render<R : Renderer> (&self, r: &mut R) -> Result<()> {
-        // half height
-        let hh = r.height() / 2;
+    // scrolls horizontally so the cursor column is visible
+    fn follow_cursor_x(&mut self, width: usize) {
+        let cx = self.cx.min(self.current().cols());
 
-        r.move_y(hh as isize)?;
+        if cx < self.left_col {
+            self.left_col = cx;
+        } else if width > 0 && cx >= self.left_col + width {
+            self.left_col = cx + 1 - width;
+        }
+    }
+
+    // the visible part of the line, marking the edges with `<`
+    // and `>` when text is cut off
+    fn clip_line(&self, s: &str, width: usize) -> String {
+        let line = Line{
+            s,
+            cfg: self.lcfg,
+        };
+
+        let (text, left, right) = line.clip_cols(self.left_col, width);
+        if width < 2 || !(left || right) {
+            return text;
+        }
+
+        let (start, mut width) = if left {
+            (self.left_col + 1, width - 1)
+        } else {
+            (self.left_col, width)
+        };
+        if right {
+            width -= 1;
+        }
+
+        let (mut text, _, _) = line.clip_cols(start, width);
+        if left {
+            text.insert(0, '<');
+        }
+        if right {
+            let pad = width + left as usize - UnicodeWidthStr::width(text.as_str());
+            text.extend(std::iter::repeat_n(' ', pad));
+            text.push('>');
+        }
+
+        text
+    }
+
+    // the screen rows a line takes
+    fn line_rows(&self, s: &str, width: usize) -> Vec<Row> {
+        if !self.vcfg.wrap {
+            return vec![Row{
+                start: self.left_col,
+                end: self.left_col + width,
+                indent: 0,
+            }];
+        }
+
+        let line = Line{
+            s,
+            cfg: self.lcfg,
+        };
+        line.wrap_rows(width, self.vcfg.line_break, self.vcfg.break_indent)
+    }
+
+    fn row_text(&self, s: &str, row: &Row, width: usize) -> String {
+        if !self.vcfg.wrap {
+            return self.clip_line(s, width);
+        }
+
+        let line = Line{
+            s,
+            cfg: self.lcfg,
+        };
+        let (text, _, _) = line.clip_cols(row.start, row.end - row.start);
+
+        let mut ret = " ".repeat(row.indent);
+        ret.push_str(&text);
+        ret
+    }
+
+    // the index of the row with the cursor and the cursor's
+    // screen column in that row
+    fn cursor_row(&self, rows: &[Row]) -> (usize, usize) {
+        self.col_row(rows, self.cx.min(self.current().cols()))
+    }
+
+    // the index of the row with column col and its screen column
+    // in that row
+    fn col_row(&self, rows: &[Row], col: usize) -> (usize, usize) {
+        if !self.vcfg.wrap {
+            return (0, col.saturating_sub(self.left_col));
+        }
 
-        // go up
-        let mut up_lines = 0;
+        let i = rows.iter()
+            .position(|row| col < row.end)
+            .unwrap_or(rows.len() - 1);
+        let row = &rows[i];
+
+        (i, (row.indent + col.saturating_sub(row.start)).min(self.width.max(1) - 1))
+    }
+
+    // the column under the screen column x in a row, the end of
+    // a row is only reachable in the last row of a line
+    fn row_col(row: &Row, x: usize, last: bool) -> usize {
+        let col = row.start + x.saturating_sub(row.indent);
+        if last || row.end == row.start {
+            col.min(row.end)
+        } else {
+            col.min(row.end - 1)
+        }
+    }
+
+    // the screen columns of row covered by the line columns
+    // a..b
+    fn row_cols(&self, row: &Row, sel: (usize, usize), last: bool,
+                     width: usize) -> Option<(usize, usize)> {
+
+        let (a, b) = sel;
+        let (start, end) = if self.vcfg.wrap {
+            (row.start, row.end + last as usize)
+        } else {
+            (self.left_col, self.left_col + width)
+        };
+
+        let (a, b) = (a.max(start), b.min(end));
+        if a >= b {
+            return None;
+        }
+
+        let indent = if self.vcfg.wrap { row.indent } else { 0 };
+        let x = indent + a - start;
+        Some((x.min(width), (x + b - a).min(width)))
+    }
+
+    // a cursor on the line at idx, walking from the current line
+    fn cursor_at(&self, idx: usize) -> Cursor<'_, String> {
         let mut cur = self.cur.as_cursor();
-        for _n in 0..hh {
-            if let Some(line) = cur.current() {
-                r.write(line.as_str())?;
-                r.ret()?;
-                r.move_y(-1)?;
-                up_lines += 1;
-                cur.move_prev();
-            } else {
-                break;
+        let at = cur.index().unwrap();
+
+        for _ in idx..at {
+            cur.move_prev();
+        }
+        for _ in at..idx {
+            cur.move_next();
+        }
+
+        cur
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.cur.as_cursor().as_list().len()
+    }
+
+    // the number of screen rows a line takes
+    fn line_height(&self, s: &str) -> usize {
+        if self.vcfg.wrap && self.width > 0 {
+            self.line_rows(s, self.width).len()
+        } else {
+            1
+        }
+    }
+
+    fn scroll_off(&self) -> usize {
+        self.vcfg.scroll_off.min(self.height.saturating_sub(1) / 2)
+    }
+
+    // the top line which leaves n rows above the current line,
+    // or the first line
+    fn top_above(&self, n: usize) -> usize {
+        let mut top = self.cur.index().unwrap();
+        let mut rows = 0;
+        let mut cur = self.cur.as_cursor();
+
+        while rows < n {
+            cur.move_prev();
+            match cur.current() {
+                Some(line) => {
+                    rows += self.line_height(line);
+                    top -= 1;
+                },
+                None => break,
             }
         }
 
-        // reset
-        r.move_y(up_lines+1)?;
+        top
+    }
+
+    // the first top line which fits the current line and n rows
+    // below it on screen
+    fn top_below(&self, n: usize) -> usize {
+        let mut cur = self.cur.as_cursor();
+        let mut below = 0;
+        while below < n {
+            cur.move_next();
+            match cur.current() {
+                Some(line) => below += self.line_height(line),
+                None => break,
+            }
+        }
+        let below = below.min(n);
 
-        // go down
-        let mut down_lines = 0;
+        let mut top = self.cur.index().unwrap();
+        let mut used = self.line_height(self.current().s);
         let mut cur = self.cur.as_cursor();
-        cur.move_next();
-        down_lines += 1;
-        for _n in 0..hh {
-            if let Some(line) = cur.current() {
-                r.write(line.as_str())?;
-                r.ret()?;
+        loop {
+            cur.move_prev();
+            match cur.current() {
+                Some(line) => {
+                    let h = self.line_height(line);
+                    if used + h + below > self.height {
+                        break;
+                    }
+                    used += h;
+                    top -= 1;
+                },
+                None => break,
+            }
+        }
+
+        top
+    }
+
+    // the last line which fits on screen from the top line
+    fn bottom(&self) -> usize {
+        let mut cur = self.cursor_at(self.top);
+        let mut bottom = self.top;
+        let mut used = 0;
+
+        while let Some(line) = cur.current() {
+            used += self.line_height(line);
+            if used > self.height && bottom > self.top {
+                return bottom - 1;
+            }
+            bottom += 1;
+            cur.move_next();
+        }
+
+        bottom.saturating_sub(1)
+    }
+
+    // scrolls vertically, only when the cursor would be closer
+    // than scroll_off rows to the top or bottom of the screen
+    fn follow_cursor_y(&mut self) {
+        let so = self.scroll_off();
+
+        self.top = self.top.min(self.top_above(so));
+        self.top = self.top.max(self.top_below(so));
+    }
+
+    // moves the cursor back into the screen after scrolling the
+    // view
+    fn cursor_into_view(&mut self) {
+        let so = self.scroll_off();
+        let last = self.line_count() - 1;
+        let cy = self.cur.index().unwrap();
+
+        let hi = match self.bottom() {
+            b if b == last => b,
+            b => b.saturating_sub(so).max(self.top),
+        };
+        let lo = match self.top {
+            0 => 0,
+            t => (t + so).min(hi),
+        };
+
+        if cy < lo {
+            self.scroll_rel((lo - cy) as i32);
+        } else if cy > hi {
+            self.scroll_rel(-((cy - hi) as i32));
+        }
+    }
+
+    // the columns taken by the sign column
+    fn sign_width(&self) -> usize {
+        match self.vcfg.sign_column {
+            SignColumn::Yes => SIGN_WIDTH,
+            SignColumn::Auto if !self.signs.is_empty() => SIGN_WIDTH,
+            _ => 0,
+        }
+    }
+
+    // the columns taken by line numbers, room for 3 digits at
+    // least and a space
+    fn number_width(&self) -> usize {
+        if self.vcfg.number || self.vcfg.relative_number {
+            self.line_count().to_string().len().max(3) + 1
+        } else {
+            0
+        }
+    }
+
+    // the gutter of the first row of the line at idx, signs
+    // are written over it
+    fn gutter_text(&self, idx: usize, at: usize, width: usize) -> String {
+        let n = self.number_width().min(width);
+        let mut ret = " ".repeat(width - n);
+        if n == 0 {
+            return ret;
+        }
+
+        let (number, relative) = (self.vcfg.number, self.vcfg.relative_number);
+        let dist = idx.abs_diff(at);
+        let text = if relative && !(number && idx == at) {
+            format!("{:>1$} ", dist, n - 1)
+        } else if relative {
+            // the current line's number is aligned left in
+            // hybrid mode
+            format!("{:<1$} ", idx + 1, n - 1)
+        } else {
+            format!("{:>1$} ", idx + 1, n - 1)
+        };
+
+        ret.extend(text.chars().take(n));
+        ret
+    }
+
+    pub fn render<R : Renderer> (&mut self, r: &mut R) -> Result<()> {
+        let height = r.height();
+        if height == 0 {
+            return Ok(());
+        }
+
+        // the text area is what the gutter leaves, but never
+        // less than a column
+        let gutter = (self.sign_width() + self.number_width())
+            .min(r.width().saturating_sub(1));
+        let width = r.width() - gutter;
+
+        self.width = width;
+        self.height = height;
+        self.gutter = gutter;
+        if self.vcfg.wrap {
+            self.left_col = 0;
+        } else {
+            self.follow_cursor_x(width);
+        }
+        self.follow_cursor_y();
+        let line_spans = self.spans(height);
+
+        let at = self.cur.index().unwrap();
+        let sel = self.selection();
+        let normal = self.theme.style("Normal");
+        let visual = self.theme.style("Visual").on(normal);
+        let line_nr = self.theme.style("LineNr");
+        let mut rows = Vec::with_capacity(height);
+        let mut cx = 0;
+        let mut cy = 0;
+
+        let mut cur = self.cursor_at(self.top);
+        while rows.len() < height {
+            let idx = match cur.index() {
+                Some(idx) => idx,
+                None => break,
+            };
+            let line = cur.current().unwrap();
+
+            let line_rows = self.line_rows(line, width);
+            if idx == at {
+                let (crow, x) = self.cursor_row(&line_rows);
+                cx = x;
+                cy = rows.len() + crow;
+            }
+
+            let cols = Line{
+                s: line,
+                cfg: self.lcfg,
+            }.cols();
+            let selected = sel.and_then(|((l0, c0), (l1, c1))| {
+                if idx < l0 || idx > l1 {
+                    return None;
+                }
+                let a = if idx == l0 { c0 } else { 0 };
+                let b = if idx == l1 { c1 + 1 } else { cols + 1 };
+                Some((a, b.min(cols + 1)))
+            });
+
+            // highlighted spans in line columns
+            let col = |i| Line{
+                s: &line[..i],
+                cfg: self.lcfg,
+            }.cols();
+            let spans: Vec<(usize, usize, Style)> = line_spans
+                .get(idx - self.top)
+                .map_or(&[][..], |s| s.as_slice())
+                .iter()
+                .map(|&(a, b, style)| (col(a), col(b), style.on(normal)))
+                .collect();
+
+            for (i, row) in line_rows.iter().enumerate() {
+                let last = i + 1 == line_rows.len();
+                let mut runs = normal_run(normal, width);
+                runs.extend(spans.iter()
+                    .filter_map(|&(a, b, style)| {
+                        let (a, b) = self.row_cols(row, (a, b), false, width)?;
+                        Some((a, b, style))
+                    }));
+                if let Some((a, b)) = selected.and_then(|sel| self.row_cols(row, sel, last, width)) {
+                    runs.push((a, b, visual));
+                }
+
+                let (g, sign) = if i == 0 {
+                    (self.gutter_text(idx, at, gutter), self.sign(idx))
+                } else {
+                    (" ".repeat(gutter), None)
+                };
+                let number = if idx == at {
+                    self.theme.style("CursorLineNr")
+                } else {
+                    line_nr
+                };
+                let mut text = self.row_text(line, row, width);
+                if last {
+                    self.append_virtual_text(idx, &mut text, &mut runs, normal, width);
+                }
+                rows.push(ScreenRow{
+                    gutter: g,
+                    number,
+                    sign,
+                    text,
+                    runs,
+                });
+            }
+            cur.move_next();
+        }
+
+        // a current line taller than the screen can push the
+        // cursor off screen
+        if cy >= height {
+            let d = cy + 1 - height;
+            rows.drain(..d);
+            cy -= d;
+        }
+        rows.resize(height, ScreenRow{
+            gutter: " ".repeat(gutter),
+            number: line_nr,
+            sign: None,
+            text: String::new(),
+            runs: normal_run(normal, width),
+        });
+
+        let signs = gutter - self.number_width().min(gutter);
+        let sign_column = self.theme.style("SignColumn");
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
                 r.move_y(1)?;
-                down_lines += 1;
-                cur.move_next();
-            } else {
-                break;
             }
+            r.ret()?;
+
+            let g = &row.gutter;
+            let w = match row.sign {
+                Some(sign) if signs > 0 => {
+                    let (_, text, _) = split_cols(&sign.text, 0, signs);
+                    let style = self.theme.style(&sign.highlight).on(sign_column);
+                    r.write_styled(&text, style)?;
+                    UnicodeWidthStr::width(text.as_str())
+                },
+                _ => 0,
+            };
+            r.write_styled(&g[w..signs], sign_column)?;
+            r.write_styled(&g[signs..], row.number)?;
+
+            write_runs(r, &row.text, &row.runs)?;
         }
 
-        // write set cursor
-        r.move_y(-down_lines)?;
+        // set cursor
+        r.move_y(cy as isize - (height as isize - 1))?;
         r.ret()?;
-        r.move_x(self.cx.min(self.current().cols()) as isize)?;
+        r.move_x((gutter + cx) as isize)?;
         Ok(())
     }
 
+    // adds the virtual text of the line at idx to a row, after
+    // a gap and as much as fits
+    fn append_virtual_text(&self, idx: usize, text: &mut String,
+                           runs: &mut Vec<(usize, usize, Style)>, normal: Style, width: usize) {
+
+        let mut x = UnicodeWidthStr::width(text.as_str());
+        for vt in self.virtual_text(idx) {
+            if x + 2 >= width {
+                break;
+            }
+            let w = UnicodeWidthStr::width(vt.text.as_str()).min(width - x - 2);
+            let (_, s, _) = split_cols(&vt.text, 0, w);
+            text.push_str("  ");
+            text.push_str(&s);
+            runs.push((x + 2, x + 2 + w, self.theme.style(&vt.highlight).on(normal)));
+            x += 2 + w;
+        }
+    }
+
+    // puts a sign on the line at idx
+    pub fn add_sign(&mut self, idx: usize, sign: Sign) {
+        self.signs.entry(idx).or_default().push(sign);
+    }
+
+    // takes away the signs of a group, from every line
+    pub fn remove_signs(&mut self, group: &str) {
+        for signs in self.signs.values_mut() {
+            signs.retain(|s| s.group != group);
+        }
+        self.signs.retain(|_, signs| !signs.is_empty());
+    }
+
+    pub fn signs(&self, idx: usize) -> &[Sign] {
+        self.signs.get(&idx).map_or(&[], |s| s.as_slice())
+    }
+
+    // the sign shown on the line at idx
+    fn sign(&self, idx: usize) -> Option<&Sign> {
+        self.signs(idx).iter().max_by_key(|s| s.priority)
+    }
+
+    // puts virtual text after the line at idx
+    pub fn add_virtual_text(&mut self, idx: usize, text: VirtualText) {
+        self.virtual_text.entry(idx).or_default().push(text);
+    }
+
+    // takes away the virtual text of a group, from every line
+    pub fn remove_virtual_text(&mut self, group: &str) {
+        for texts in self.virtual_text.values_mut() {
+            texts.retain(|t| t.group != group);
+        }
+        self.virtual_text.retain(|_, texts| !texts.is_empty());
+    }
+
+    pub fn virtual_text(&self, idx: usize) -> &[VirtualText] {
+        self.virtual_text.get(&idx).map_or(&[], |t| t.as_slice())
+    }
+
+    // records the old lines at idx for undo before they're
+    // replaced with new ones, then see note_replaced
+    fn lines_replaced(&mut self, idx: usize, old: usize, new: usize) {
+        if !self.undo_open {
+            self.undo.push(UndoGroup{
+                cursor: self.cursor(),
+                edits: Vec::new(),
+            });
+            if self.undo.len() > UNDO_LEVELS {
+                self.undo.remove(0);
+            }
+            self.undo_open = true;
+        }
+
+        // typing on a line keeps replacing it, what it was
+        // before the first time is enough
+        let edits = &self.undo.last().unwrap().edits;
+        let same = matches!(edits.last(), Some(e) if e.idx == idx && e.old.len() == 1 && e.new == 1);
+        if !(same && old == 1 && new == 1) {
+            let mut cur = self.cursor_at(idx);
+            let lines = (0..old)
+                .map(|_| {
+                    let l = cur.current().cloned().unwrap_or_default();
+                    cur.move_next();
+                    l
+                })
+                .collect();
+            self.undo.last_mut().unwrap().edits.push(Edit{
+                idx,
+                old: lines,
+                new,
+            });
+        }
+
+        self.note_replaced(idx, old, new);
+    }
+
+    // marks the buffer modified and keeps signs, highlighting
+    // and the syntax tree in step with old lines at idx being
+    // replaced by new ones
+    fn note_replaced(&mut self, idx: usize, old: usize, new: usize) {
+        *self.modified = true;
+        self.changes = Some(LineChange::merge(self.changes, idx, old, new));
+        shift_lines(&mut self.signs, idx, old, new);
+        shift_lines(&mut self.virtual_text, idx, old, new);
+
+        if let Some(hl) = self.highlight.as_mut() {
+            let kept = old.min(new);
+            for i in idx..idx + kept {
+                hl.edited(i);
+            }
+            if new > old {
+                hl.inserted(idx + kept, new - old);
+            } else if old > new {
+                hl.removed(idx + kept, old - new);
+            }
+        }
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = self.syntax.as_mut() {
+            tree.edited(idx, old, new);
+        }
+    }
+
+    // ends the group of edits undo takes back together, the
+    // next edit starts a new one
+    pub fn commit_undo(&mut self) {
+        self.undo_open = false;
+    }
+
+    // takes back the last group of edits and puts the cursor
+    // where it was before them, false if there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+        self.undo_open = false;
+        let group = match self.undo.pop() {
+            Some(group) => group,
+            None => return false,
+        };
+
+        for e in group.edits.into_iter().rev() {
+            self.note_replaced(e.idx, e.new, e.old.len());
+
+            // the old lines go in before the new ones, which are
+            // then removed, so there's always a line
+            if e.idx < self.line_count() {
+                self.goto(e.idx, 0);
+                for l in e.old {
+                    self.cur.insert_before(l);
+                }
+            } else {
+                self.goto(e.idx, 0);
+                for l in e.old.into_iter().rev() {
+                    self.cur.insert_after(l);
+                }
+                self.cur.move_next();
+            }
+            for _ in 0..e.new {
+                self.cur.remove_current();
+            }
+            if self.cur.current().is_none() {
+                self.cur.move_prev();
+            }
+        }
+
+        self.clear_anchor();
+        let (col, idx) = group.cursor;
+        self.goto(idx, col);
+        true
+    }
+
+    // the lines changed since the last call, for keeping a copy
+    // of the buffer elsewhere up to date
+    pub fn take_changes(&mut self) -> Option<LineChange> {
+        self.changes.take()
+    }
+
+    // draws with the styles of theme from now on
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        if let Some(hl) = self.highlight.as_mut() {
+            hl.set_theme(theme);
+        }
+        #[cfg(feature = "tree-sitter")]
+        if let Some(tree) = self.syntax.as_mut() {
+            tree.set_theme(theme);
+        }
+    }
+
+    pub fn set_highlighter(&mut self, hl: Option<Highlighter>) {
+        self.highlight = hl;
+    }
+
+    pub fn highlighter_mut(&mut self) -> Option<&mut Highlighter> {
+        self.highlight.as_mut()
+    }
+
+    // highlights the lines which changed up to the one at last
+    fn update_highlight(&mut self, last: usize) {
+        let hl = match self.highlight.as_mut() {
+            Some(hl) if hl.first_dirty() <= last => hl,
+            _ => return,
+        };
+
+        let from = hl.first_dirty();
+        let mut cur = self.cur.as_cursor();
+        let at = cur.index().unwrap();
+        for _ in from..at {
+            cur.move_prev();
+        }
+        for _ in at..from {
+            cur.move_next();
+        }
+
+        let lines = std::iter::from_fn(|| {
+            let s = cur.current()?;
+            cur.move_next();
+            Some(s.as_str())
+        });
+        hl.update(lines, last);
+    }
+
+    // the highlighted spans of n lines from the top, in bytes
+    fn spans(&mut self, n: usize) -> Vec<Vec<(usize, usize, Style)>> {
+        #[cfg(feature = "tree-sitter")]
+        if self.syntax.is_some() {
+            self.update_syntax();
+            return self.syntax.as_ref().unwrap().spans(self.top, n);
+        }
+
+        self.update_highlight(self.top + n - 1);
+        let hl = match &self.highlight {
+            Some(hl) => hl,
+            None => return Vec::new(),
+        };
+
+        (self.top..self.top + n)
+            .map(|idx| hl.spans(idx).iter()
+                .map(|span| (span.start, span.end, hl.style(span)))
+                .filter(|(_, _, style)| *style != Style::default())
+                .collect())
+            .collect()
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub fn set_syntax_tree(&mut self, tree: Option<SyntaxTree>) {
+        self.syntax = tree;
+    }
+
+    #[cfg(feature = "tree-sitter")]
+    pub fn syntax_tree_mut(&mut self) -> Option<&mut SyntaxTree> {
+        self.syntax.as_mut()
+    }
+
+    // parses the buffer again if it changed
+    #[cfg(feature = "tree-sitter")]
+    fn update_syntax(&mut self) {
+        let tree = match self.syntax.as_mut() {
+            Some(tree) if tree.is_stale() => tree,
+            _ => return,
+        };
+
+        let list = self.cur.as_cursor().as_list();
+        tree.update(list.iter().map(String::as_str));
+    }
+
+    // the syntax tree, up to date
+    #[cfg(feature = "tree-sitter")]
+    fn syntax(&mut self) -> Option<&SyntaxTree> {
+        self.update_syntax();
+        self.syntax.as_ref()
+    }
+
+    // the column of byte i in the line at idx
+    fn byte_col(&self, idx: usize, i: usize) -> usize {
+        let s = self.cursor_at(idx).current().unwrap();
+        Line{
+            s: &s[..i.min(s.len())],
+            cfg: self.lcfg,
+        }.cols()
+    }
+
+    // the byte at col in the line at idx
+    fn col_byte(&self, idx: usize, col: usize) -> usize {
+        Line{
+            s: self.cursor_at(idx).current().unwrap(),
+            cfg: self.lcfg,
+        }.col2idx(col)
+    }
+
+    // moves to the start of the next text object named name,
+    // or the previous one. false without a syntax tree or when
+    // there is none
+    #[cfg(feature = "tree-sitter")]
+    pub fn goto_object(&mut self, name: &str, forward: bool) -> bool {
+        let at = self.cur.index().unwrap();
+        let here = (at, self.col_byte(at, self.cx));
+
+        if let Some(tree) = self.syntax() {
+            let starts = tree.objects(name).into_iter().map(|(start, _)| start);
+            let to = if forward {
+                starts.filter(|s| *s > here).min()
+            } else {
+                starts.filter(|s| *s < here).max()
+            };
+            if let Some((idx, i)) = to {
+                self.clear_anchor();
+                self.goto(idx, self.byte_col(idx, i));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // selects the smallest text object named name around the
+    // cursor
+    #[cfg(feature = "tree-sitter")]
+    pub fn select_object(&mut self, name: &str) -> bool {
+        let at = self.cur.index().unwrap();
+        let here = (at, self.col_byte(at, self.cx));
+
+        if let Some(tree) = self.syntax() {
+            let found = tree.objects(name).into_iter()
+                .filter(|(start, end)| *start <= here && here < *end)
+                // they nest, so the last to start is the smallest
+                .max_by_key(|&(start, end)| (start, std::cmp::Reverse(end)));
+            if let Some(range) = found {
+                self.select_bytes(range);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // grows the selection, or the cursor, to the syntax node
+    // around it
+    #[cfg(feature = "tree-sitter")]
+    pub fn expand_selection(&mut self) -> bool {
+        let at = self.cur.index().unwrap();
+        let ((l0, c0), (l1, c1)) = self.selection().unwrap_or(((at, self.cx), (at, self.cx)));
+        let start = (l0, self.col_byte(l0, c0));
+        // the selection takes in the last grapheme
+        let end = (l1, self.col_byte(l1, c1 + 1).max(start.1 + (l0 == l1) as usize));
+
+        if let Some(tree) = self.syntax() {
+            if let Some(range) = tree.expand(start, end) {
+                self.select_bytes(range);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // selects the bytes start..end, as line and byte
+    #[cfg(feature = "tree-sitter")]
+    fn select_bytes(&mut self, (start, end): ((usize, usize), (usize, usize))) {
+        // the last selected position is the one before end
+        let end = match end {
+            (idx, 0) if idx > start.0 => {
+                let prev = self.cursor_at(idx - 1).current().unwrap().len();
+                (idx - 1, self.byte_col(idx - 1, prev))
+            },
+            (idx, i) => (idx, self.byte_col(idx, i).saturating_sub(1)),
+        };
+
+        self.goto(start.0, self.byte_col(start.0, start.1));
+        self.set_anchor();
+        self.goto(end.0, end.1);
+    }
+
+    // without tree-sitter there are no text objects
+    #[cfg(not(feature = "tree-sitter"))]
+    pub fn goto_object(&mut self, _name: &str, _forward: bool) -> bool {
+        false
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    pub fn select_object(&mut self, _name: &str) -> bool {
+        false
+    }
+
+    #[cfg(not(feature = "tree-sitter"))]
+    pub fn expand_selection(&mut self) -> bool {
+        false
+    }
+
+    // scrolls the view n lines, negative is up, keeping the
+    // cursor on screen
+    pub fn scroll_view(&mut self, n: isize) {
+        let last = self.line_count() - 1;
+        self.top = (self.top as isize + n).clamp(0, last as isize) as usize;
+        self.cursor_into_view();
+    }
+
+    // scrolls the view and the cursor half a screen, negative
+    // is up
+    pub fn scroll_half_page(&mut self, dir: isize) {
+        let n = (self.height / 2).max(1) as isize * dir.signum();
+        let last = self.line_count() - 1;
+
+        self.top = (self.top as isize + n).clamp(0, last as isize) as usize;
+        self.scroll_rel(n as i32);
+        self.cursor_into_view();
+    }
+
+    // scrolls the view a screen, keeping two lines of context,
+    // negative is up
+    pub fn scroll_page(&mut self, dir: isize) {
+        let lines = self.bottom() + 1 - self.top;
+        let n = (lines.saturating_sub(2).max(1) as isize) * dir.signum();
+
+        self.scroll_view(n);
+    }
+
+    // scrolls so the current line is at the top of the screen
+    pub fn scroll_cursor_top(&mut self) {
+        self.top = self.top_above(self.scroll_off());
+    }
+
+    // scrolls so the current line is in the middle of the screen
+    pub fn scroll_cursor_center(&mut self) {
+        let h = self.line_height(self.current().s);
+        self.top = self.top_above(self.height.saturating_sub(h) / 2);
+    }
+
+    // scrolls so the current line is at the bottom of the screen
+    pub fn scroll_cursor_bottom(&mut self) {
+        self.top = self.top_below(self.scroll_off());
+    }
+
+    // moves down a screen row, the same as a line when not
+    // wrapping
+    pub fn next_row(&mut self) {
+        if !self.vcfg.wrap || self.width == 0 {
+            self.scroll_rel(1);
+            return;
+        }
+
+        let rows = self.line_rows(self.current().s, self.width);
+        let (i, x) = self.cursor_row(&rows);
+
+        if i + 1 < rows.len() {
+            self.cx = Self::row_col(&rows[i + 1], x, i + 2 == rows.len());
+        } else if self.cur.peek_next().is_some() {
+            self.scroll_rel(1);
+            let rows = self.line_rows(self.current().s, self.width);
+            self.cx = Self::row_col(&rows[0], x, rows.len() == 1);
+        }
+    }
+
+    // moves up a screen row, the same as a line when not
+    // wrapping
+    pub fn prev_row(&mut self) {
+        if !self.vcfg.wrap || self.width == 0 {
+            self.scroll_rel(-1);
+            return;
+        }
+
+        let rows = self.line_rows(self.current().s, self.width);
+        let (i, x) = self.cursor_row(&rows);
+
+        if i > 0 {
+            self.cx = Self::row_col(&rows[i - 1], x, false);
+        } else if self.cur.peek_prev().is_some() {
+            self.scroll_rel(-1);
+            let rows = self.line_rows(self.current().s, self.width);
+            self.cx = Self::row_col(&rows[rows.len() - 1], x, true);
+        }
+    }
+
     pub fn last_col(&mut self) {
         self.cx = self.current().cols();
     }
@@ -135,22 +1256,32 @@ impl<'buf> View <'buf> {
     }
 
     pub fn backspace(&mut self) {
-        let cx = self.cx;
-        let mut cs = self.current_mut();
-        let col = cx.min(cs.as_line().cols());
+        let col = self.cx.min(self.current().cols());
 
         if col > 0 {
-            self.cx = col - cs.remove_col(col - 1);
+            self.cx = col - self.current_mut().remove_col(col - 1);
         }
     }
 
     pub fn tab(&mut self) {
-        let cx = self.cx;
-        let mut cs = self.current_mut();
-        self.cx = cs.insert_str_col(cx, "\t");
+        if !self.lcfg.expand_tab {
+            self.insert_str("\t");
+            return;
+        }
+
+        // fill with spaces to the next indent stop
+        let size = self.lcfg.indent_size.max(1) as usize;
+        let cx = self.cx.min(self.current().cols());
+        let n = size - cx % size;
+        self.cx = cx;
+        self.insert_str(&" ".repeat(n));
     }
 
     pub fn insert_str(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
         let cx = self.cx;
         let mut cs = self.current_mut();
         let col = cs.insert_str_col(cx, s);
@@ -158,15 +1289,332 @@ impl<'buf> View <'buf> {
     }
 
     pub fn insert_line_above(&mut self) {
+        self.lines_replaced(self.cur.index().unwrap(), 0, 1);
         self.cur.insert_before("".into());
     }
 
     pub fn insert_line_below(&mut self) {
+        self.lines_replaced(self.cur.index().unwrap() + 1, 0, 1);
         self.cur.insert_after("".into());
     }
 
+    // opens an indented line under the cursor's and moves to
+    // its end
+    pub fn open_below(&mut self) {
+        self.insert_line_below();
+        self.scroll_rel(1);
+        self.indent_new_line();
+    }
+
+    // opens an indented line over the cursor's and moves to its
+    // end
+    pub fn open_above(&mut self) {
+        self.insert_line_above();
+        self.scroll_rel(-1);
+        self.indent_new_line();
+    }
+
+    fn indent_new_line(&mut self) {
+        let idx = self.cur.index().unwrap();
+        self.set_indent(idx, self.auto_indent(idx));
+        self.cx = self.current().cols();
+    }
+
+    // splits the line at the cursor, the rest of it goes on an
+    // indented line under it with the cursor at its start
+    pub fn newline(&mut self) {
+        let (idx, i) = self.cursor_byte();
+        let rest = &self.line(idx).unwrap()[i..];
+        let space = rest.len() - rest.trim_start().len();
+        self.replace((idx, i), (idx, i + space), "\n");
+        self.set_indent(idx + 1, self.auto_indent(idx + 1));
+    }
+
+    // the indent for the line at idx from the line above that
+    // isn't blank: the same, a level more after an opening
+    // bracket, and a level less for a line starting with a
+    // closing one
+    pub fn auto_indent(&self, idx: usize) -> usize {
+        let brackets = self.lcfg.indent_brackets;
+        let level = self.lcfg.indent_width() as isize;
+        let line = |i| Line{
+            s: self.cursor_at(i).current().unwrap(),
+            cfg: self.lcfg,
+        };
+
+        let above = (0..idx).rev().map(line).find(|l| !l.s.trim().is_empty());
+        let mut cols = above.map_or(0, |l| {
+            l.indent_cols() as isize + l.indent_change(brackets) * level
+        });
+        if line(idx).starts_with_closer(brackets) {
+            cols -= level;
+        }
+        cols.max(0) as usize
+    }
+
+    // replaces the white space starting the line at idx with
+    // cols columns of indent
+    pub fn set_indent(&mut self, idx: usize, cols: usize) {
+        let s = self.cursor_at(idx).current().unwrap();
+        let space = s.len() - s.trim_start().len();
+        let indent = self.lcfg.indent_string(cols);
+        if s[..space] != indent {
+            self.replace((idx, 0), (idx, space), &indent);
+        }
+    }
+
+    // reindents the lines first to last, blank ones lose their
+    // white space
+    pub fn reindent(&mut self, first: usize, last: usize) {
+        for idx in first..=last.min(self.line_count() - 1) {
+            let blank = self.cursor_at(idx).current().unwrap().trim().is_empty();
+            let cols = if blank { 0 } else { self.auto_indent(idx) };
+            self.set_indent(idx, cols);
+        }
+    }
+
+    // a closing bracket typed first on a line takes it back a
+    // level
+    pub fn reindent_closer(&mut self) {
+        let (idx, i) = self.cursor_byte();
+        let before = self.line(idx).unwrap()[..i].trim_start();
+        let closer = Line{
+            s: before,
+            cfg: self.lcfg,
+        }.starts_with_closer(self.lcfg.indent_brackets);
+        if closer && before.chars().count() == 1 {
+            self.reindent(idx, idx);
+        }
+    }
+
+    // inserts s at the cursor, splitting lines at its line
+    // breaks, leaving the cursor after it
+    pub fn insert_text(&mut self, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        let s = s.replace("\r\n", "\n").replace('\r', "\n");
+        let mut parts = s.split('\n');
+
+        let first = parts.next().unwrap_or("");
+        let cx = self.cx.min(self.current().cols());
+        self.cx = cx;
+        let tail = self.current_mut().split_off_col(cx);
+        self.insert_str(first);
+
+        for part in parts {
+            self.lines_replaced(self.cur.index().unwrap() + 1, 0, 1);
+            self.cur.insert_after(part.into());
+            self.cur.move_next();
+            self.cx = self.current().cols();
+        }
+
+        self.current_mut().s.push_str(&tail);
+    }
+
+    // replaces the text from start up to end with text, both
+    // are a line and a byte in it. a cursor after the replaced
+    // text stays on the same character, one inside it goes to
+    // the end of the new text
+    pub fn replace(&mut self, start: (usize, usize), end: (usize, usize), text: &str) {
+        let start = self.clamp_byte(start);
+        let end = self.clamp_byte(end).max(start);
+        if start == end && text.is_empty() {
+            return;
+        }
+        let cursor = self.cursor_byte();
+
+        let parts: Vec<&str> = text.split('\n').collect();
+        let last = parts.len() - 1;
+        let last_end = match last {
+            0 => start.1 + text.len(),
+            _ => parts[last].len(),
+        };
+
+        self.lines_replaced(start.0, end.0 - start.0 + 1, parts.len());
+        self.goto(start.0, 0);
+        let tail = self.cursor_at(end.0).current().unwrap()[end.1..].to_string();
+        for _ in start.0..end.0 {
+            self.cur.move_next();
+            self.cur.remove_current();
+            self.cur.move_prev();
+        }
+
+        let line = self.cur.current().unwrap();
+        line.truncate(start.1);
+        line.push_str(parts[0]);
+        for part in &parts[1..] {
+            self.cur.insert_after(part.to_string());
+            self.cur.move_next();
+        }
+        self.cur.current().unwrap().push_str(&tail);
+
+        let (idx, i) = if cursor < start {
+            cursor
+        } else if cursor >= end {
+            let idx = cursor.0 - (end.0 - start.0) + last;
+            let i = if cursor.0 == end.0 { cursor.1 - end.1 + last_end } else { cursor.1 };
+            (idx, i)
+        } else {
+            (start.0 + last, last_end)
+        };
+        self.goto_byte(idx, i);
+    }
+
+    // a line and byte moved into the buffer, onto a character
+    fn clamp_byte(&self, (idx, i): (usize, usize)) -> (usize, usize) {
+        let n = self.line_count();
+        if idx >= n {
+            let s = self.cursor_at(n - 1).current().unwrap();
+            return (n - 1, s.len());
+        }
+
+        let s = self.cursor_at(idx).current().unwrap();
+        let mut i = i.min(s.len());
+        while !s.is_char_boundary(i) {
+            i -= 1;
+        }
+        (idx, i)
+    }
+
+    // the text of the line at idx
+    pub fn line(&self, idx: usize) -> Option<&str> {
+        self.cursor_at(idx).current().map(String::as_str)
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.cur.as_cursor().as_list().iter().map(String::as_str)
+    }
+
+    // the line and byte where the word before the cursor
+    // starts, the cursor when it isn't after a word
+    pub fn word_start(&self) -> (usize, usize) {
+        let (col, idx) = self.cursor();
+        let col = self.current().word_start_col(col);
+        (idx, self.col_byte(idx, col))
+    }
+
+    // the cursor's line and byte in it
+    pub fn cursor_byte(&self) -> (usize, usize) {
+        let (col, idx) = self.cursor();
+        (idx, self.col_byte(idx, col))
+    }
+
+    // moves the cursor to byte i in the line at idx, both are
+    // clamped to the buffer
+    pub fn goto_byte(&mut self, idx: usize, i: usize) {
+        let (idx, i) = self.clamp_byte((idx, i));
+        self.goto(idx, self.byte_col(idx, i));
+    }
+
+    // moves the cursor to col in the line at idx, both are
+    // clamped to the buffer
+    pub fn goto(&mut self, idx: usize, col: usize) {
+        let idx = idx.min(self.line_count() - 1);
+        let at = self.cur.index().unwrap();
+        self.scroll_rel(idx as i32 - at as i32);
+        self.cx = col.min(self.current().cols());
+    }
+
+    // the line and column drawn at x, y in the last render,
+    // positions past the end of the buffer are on the last line
+    pub fn pos_at(&self, x: usize, y: usize) -> (usize, usize) {
+        let x = x.saturating_sub(self.gutter);
+        let mut cur = self.cursor_at(self.top);
+        let mut idx = self.top;
+        let mut y = y;
+
+        while let Some(line) = cur.current() {
+            let rows = self.line_rows(line, self.width);
+            let last = cur.peek_next().is_none();
+            if y < rows.len() || last {
+                let i = y.min(rows.len() - 1);
+                let col = if self.vcfg.wrap {
+                    Self::row_col(&rows[i], x, i + 1 == rows.len())
+                } else {
+                    self.left_col + x
+                };
+
+                let cols = Line{
+                    s: line,
+                    cfg: self.lcfg,
+                }.cols();
+                return (idx, col.min(cols));
+            }
+
+            y -= rows.len();
+            idx += 1;
+            cur.move_next();
+        }
+
+        (idx, 0)
+    }
+
+    // where column col of the line at idx was drawn in the last
+    // render, none when it's off screen
+    pub fn screen_pos(&self, idx: usize, col: usize) -> Option<(usize, usize)> {
+        if idx < self.top {
+            return None;
+        }
+        if !self.vcfg.wrap && (col < self.left_col || col >= self.left_col + self.width) {
+            return None;
+        }
+
+        let mut cur = self.cursor_at(self.top);
+        let mut y = 0;
+        for _ in self.top..idx {
+            y += self.line_rows(cur.current()?, self.width).len();
+            if y >= self.height {
+                return None;
+            }
+            cur.move_next();
+        }
+
+        let rows = self.line_rows(cur.current()?, self.width);
+        let (i, x) = self.col_row(&rows, col);
+        Some((self.gutter + x, y + i)).filter(|&(_, y)| y < self.height)
+    }
+
+    // starts a selection at the cursor
+    pub fn set_anchor(&mut self) {
+        self.anchor = Some((self.cur.index().unwrap(), self.cx));
+    }
+
+    pub fn clear_anchor(&mut self) {
+        self.anchor = None;
+    }
+
+    // the selected text's first and last positions, as line
+    // and column
+    pub fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.anchor?;
+        let cx = self.cx.min(self.current().cols());
+        let cursor = (self.cur.index().unwrap(), cx);
+
+        if anchor <= cursor {
+            Some((anchor, cursor))
+        } else {
+            Some((cursor, anchor))
+        }
+    }
+
+    // the selection as lines and bytes, up to the end of the
+    // last selected character
+    pub fn selection_bytes(&self) -> Option<((usize, usize), (usize, usize))> {
+        let ((l0, c0), (l1, c1)) = self.selection()?;
+        Some(((l0, self.col_byte(l0, c0)), (l1, self.col_byte(l1, c1 + 1))))
+    }
+
+    // the width and height of the last render, with the
+    // gutter
+    pub fn size(&self) -> (usize, usize) {
+        (self.gutter + self.width, self.height)
+    }
+
+    // the cursor's column and line
     pub fn cursor(&self) -> (usize, usize) {
-        (self.cx, self.cur.index().unwrap())
+        (self.cx.min(self.current().cols()), self.cur.index().unwrap())
     }
 
     pub fn scroll_abs(&mut self, p: u16) {
@@ -193,3 +1641,80 @@ impl<'buf> View <'buf> {
         }
     }
 }
+
+// splits s at the display columns a and b, padding with spaces
+// when s is shorter than b
+fn split_cols(s: &str, a: usize, b: usize) -> (&str, String, &str) {
+    let mut col = 0;
+    let mut ia = s.len();
+    let mut ib = s.len();
+
+    for (i, g) in s.grapheme_indices(true) {
+        if col >= a && ia == s.len() {
+            ia = i;
+        }
+        if col >= b {
+            ib = i;
+            break;
+        }
+        col += UnicodeWidthStr::width(g);
+    }
+
+    let ia = ia.min(ib);
+    let mut mid = s[ia..ib].to_string();
+    let pad = b.saturating_sub(col.max(a));
+    if ib == s.len() {
+        mid.push_str(&" ".repeat(pad));
+    }
+
+    (&s[..ia], mid, &s[ib..])
+}
+
+// moves what's kept by line in map when old lines at idx are
+// replaced with new ones, what was on lines which are gone
+// goes with them
+fn shift_lines<T>(map: &mut BTreeMap<usize, T>, idx: usize, old: usize, new: usize) {
+    let mut gone = map.split_off(&(idx + old.min(new)));
+    let after = gone.split_off(&(idx + old));
+    for (i, v) in after {
+        map.insert(i + new - old, v);
+    }
+}
+
+// the run which fills a row with the Normal group, none when
+// it's the terminal's own style
+fn normal_run(normal: Style, width: usize) -> Vec<(usize, usize, Style)> {
+    if normal == Style::default() {
+        Vec::new()
+    } else {
+        vec![(0, width, normal)]
+    }
+}
+
+// writes s with the screen columns of each run in its style,
+// later runs are drawn over earlier ones
+fn write_runs<R : Renderer>(r: &mut R, s: &str, runs: &[(usize, usize, Style)]) -> Result<()> {
+    if runs.is_empty() {
+        return r.write(s);
+    }
+
+    let end = runs.iter()
+        .map(|(_, b, _)| *b)
+        .fold(UnicodeWidthStr::width(s), usize::max);
+    let mut styles = vec![Style::default(); end];
+    for &(a, b, style) in runs {
+        for st in &mut styles[a..b] {
+            *st = style;
+        }
+    }
+
+    let mut a = 0;
+    while a < end {
+        let b = (a..end).find(|&i| styles[i] != styles[a]).unwrap_or(end);
+        let (_, text, _) = split_cols(s, a, b);
+        r.write_styled(&text, styles[a])?;
+        a = b;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,446 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    Float,
+    View,
+    config,
+    float::{
+        Anchor,
+        fit,
+    },
+    lsp::CompletionItem,
+};
+
+
+// the most rows the menu and the documentation next to it take
+const MAX_ROWS: usize = 10;
+// the widest the documentation gets
+const MAX_DOC_WIDTH: usize = 50;
+// the menu is drawn over other floats
+const MENU_Z: i32 = 10;
+
+// something the word before the cursor can be completed to
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Candidate {
+    // what's shown in the menu
+    pub label: String,
+    // what replaces the word
+    pub text: String,
+    // the source it came from, shown after the label
+    pub kind: String,
+    // shown next to the menu while the candidate is selected
+    pub doc: Option<String>,
+    // a snippet expanded in place of the word, instead of text
+    pub snippet: Option<String>,
+    // the lines and bytes replaced instead of the word, from a
+    // language server's edit
+    pub range: Option<((usize, usize), (usize, usize))>,
+}
+
+impl Candidate {
+    pub fn new(text: &str, kind: &str) -> Candidate {
+        Candidate{
+            label: text.into(),
+            text: text.into(),
+            kind: kind.into(),
+            doc: None,
+            snippet: None,
+            range: None,
+        }
+    }
+
+    // the item's edit replaces range, its start and end in the
+    // view, rather than the word
+    pub fn from_lsp(item: &CompletionItem, range: Option<((usize, usize), (usize, usize))>) -> Candidate {
+        let text = item.edit.as_ref().map_or(&item.text, |e| &e.text);
+        let doc = match (&item.detail, &item.documentation) {
+            (Some(d), Some(doc)) => Some(format!("{}\n\n{}", d, doc)),
+            (d, doc) => d.clone().or_else(|| doc.clone()),
+        };
+
+        // snippets are matched by their label
+        let (text, snippet) = match item.snippet {
+            true => (item.label.clone(), Some(text.clone())),
+            false => (text.clone(), None),
+        };
+
+        Candidate{
+            label: item.label.clone(),
+            text,
+            kind: "lsp".into(),
+            doc,
+            snippet,
+            range,
+        }
+    }
+}
+
+// what sources get to see when completion starts
+#[derive(Debug)]
+pub struct Context<'a> {
+    // the lines of the open buffers
+    pub lines: Vec<&'a str>,
+    // the cursor's line up to the cursor
+    pub before: &'a str,
+    // the word being completed, the end of before
+    pub word: &'a str,
+    // where relative paths start from
+    pub dir: PathBuf,
+}
+
+// somewhere candidates come from. sources which can't answer
+// straight away, like a language server, add to the menu later
+// with Menu::add
+pub trait Source {
+    fn candidates(&mut self, ctx: &Context) -> Vec<Candidate>;
+}
+
+// words in the open buffers
+#[derive(Debug,Default)]
+pub struct Words;
+
+impl Source for Words {
+    fn candidates(&mut self, ctx: &Context) -> Vec<Candidate> {
+        let mut seen = HashSet::new();
+        ctx.lines.iter()
+            .flat_map(|l| l.split(|c| !is_word(c)))
+            .filter(|w| w.chars().nth(1).is_some() && *w != ctx.word)
+            .filter(|w| seen.insert(*w))
+            .map(|w| Candidate::new(w, "word"))
+            .collect()
+    }
+}
+
+// the files in the directory of the path before the cursor
+#[derive(Debug,Default)]
+pub struct Paths;
+
+impl Source for Paths {
+    fn candidates(&mut self, ctx: &Context) -> Vec<Candidate> {
+        let path = ctx.before
+            .rsplit(|c: char| c.is_whitespace() || "\"'`()[]{}<>=,;:".contains(c))
+            .next()
+            .unwrap_or("");
+        let (dir, name) = match path.rfind('/') {
+            Some(i) => (&path[..=i], &path[i + 1..]),
+            None => return Vec::new(),
+        };
+        // the word is the end of the name, what's before it
+        // stays and isn't part of the candidates
+        let kept = match name.strip_suffix(ctx.word) {
+            Some(kept) => kept,
+            None => return Vec::new(),
+        };
+
+        let dir = ctx.dir.join(config::expand_home(dir));
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut ret: Vec<Candidate> = entries
+            .filter_map(|e| {
+                let e = e.ok()?;
+                let file = e.file_name().into_string().ok()?;
+                // hidden files only when asked for
+                if !file.starts_with(kept) || (file.starts_with('.') && !name.starts_with('.')) {
+                    return None;
+                }
+                let label = if e.path().is_dir() { format!("{}/", file) } else { file };
+                Some(Candidate{
+                    text: label[kept.len()..].into(),
+                    label,
+                    kind: "path".into(),
+                    doc: None,
+                    snippet: None,
+                    range: None,
+                })
+            })
+            .collect();
+        ret.sort_by(|a, b| a.label.cmp(&b.label));
+        ret
+    }
+}
+
+// the characters prev_word_col keeps together as a word
+fn is_word(c: char) -> bool {
+    !c.is_ascii_punctuation() && !c.is_ascii_whitespace()
+}
+
+// how well pattern matches text, none unless the characters
+// of pattern are all in text in order. matches at the start,
+// at the start of words and next to each other count for more.
+// case is ignored unless pattern has upper case letters
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let same = |a: char, b: char| {
+        if ignore_case {
+            a.to_lowercase().eq(b.to_lowercase())
+        } else {
+            a == b
+        }
+    };
+
+    let mut pat = pattern.chars().peekable();
+    let mut score = 0;
+    let mut prev: Option<char> = None;
+    let mut last_match = None;
+
+    for (i, c) in text.chars().enumerate() {
+        let p = match pat.peek() {
+            Some(&p) => p,
+            None => break,
+        };
+
+        if same(p, c) {
+            score += match prev {
+                None => 12,
+                Some(_) if last_match == Some(i - 1) => 5,
+                Some(b) if !b.is_alphanumeric() || (b.is_lowercase() && c.is_uppercase()) => 7,
+                Some(_) => 1,
+            };
+            last_match = Some(i);
+            pat.next();
+        }
+        prev = Some(c);
+    }
+
+    match pat.peek() {
+        Some(_) => None,
+        None => Some(score),
+    }
+}
+
+// the insert mode completion menu, the candidates for the word
+// before the cursor which match what's been typed of it
+#[derive(Debug,Clone)]
+pub struct Menu {
+    // where the word starts, as a line and a byte
+    pub start: (usize, usize),
+    query: String,
+    items: Vec<Candidate>,
+    // the items matching the query, best first
+    matches: Vec<usize>,
+    selected: usize,
+    // the first match shown
+    top: usize,
+}
+
+impl Menu {
+    pub fn new(start: (usize, usize), query: &str, items: Vec<Candidate>) -> Menu {
+        let mut ret = Menu{
+            start,
+            query: query.into(),
+            items: Vec::new(),
+            matches: Vec::new(),
+            selected: 0,
+            top: 0,
+        };
+        ret.add(items);
+        ret
+    }
+
+    // adds candidates, ones with the same text as one already
+    // there are left out
+    pub fn add(&mut self, items: Vec<Candidate>) {
+        for item in items {
+            if !self.items.iter().any(|i| i.text == item.text) {
+                self.items.push(item);
+            }
+        }
+        self.filter();
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        if query != self.query {
+            self.query = query.into();
+            self.filter();
+        }
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self.items.iter()
+            .enumerate()
+            .filter_map(|(i, c)| Some((fuzzy_score(&self.query, &c.text)?, i)))
+            .collect();
+        let items = &self.items;
+        scored.sort_by_key(|&(score, i)| (Reverse(score), items[i].text.len(), i));
+
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    // the candidates matching the query, best first
+    pub fn matches(&self) -> impl Iterator<Item = &Candidate> {
+        self.matches.iter().map(move |&i| &self.items[i])
+    }
+
+    pub fn selected(&self) -> Option<&Candidate> {
+        self.matches.get(self.selected).map(|&i| &self.items[i])
+    }
+
+    // replaces the word before the cursor, or the candidate's
+    // range, with the selected candidate in one edit. a
+    // snippet's body is given back to be expanded where the word
+    // was
+    pub fn accept(&self, view: &mut View) -> Option<String> {
+        let c = self.selected()?;
+
+        // what's been typed since the server was asked is
+        // replaced too
+        let cursor = view.cursor_byte();
+        let (start, end) = match c.range {
+            Some((start, end)) if start.0 == cursor.0 && end.0 == cursor.0 => (start, end.max(cursor)),
+            Some(range) => range,
+            None => (self.start, cursor),
+        };
+
+        match &c.snippet {
+            Some(body) => {
+                view.replace(start, end, "");
+                Some(body.clone())
+            },
+            None => {
+                view.replace(start, end, &c.text);
+                None
+            },
+        }
+    }
+
+    // moves the selection by n, wrapping around the ends
+    pub fn select(&mut self, n: isize) {
+        let len = self.matches.len() as isize;
+        if len == 0 {
+            return;
+        }
+        self.selected = (self.selected as isize + n).rem_euclid(len) as usize;
+
+        // scrolled to keep the selection shown
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + MAX_ROWS {
+            self.top = self.selected + 1 - MAX_ROWS;
+        }
+    }
+
+    // the menu as a float without a border under the start of
+    // the word at anchor, or over it when there's more room, with
+    // the selected candidate's documentation to its right
+    pub fn float(&self, anchor: Anchor) -> Float {
+        let rows = self.matches.len().min(MAX_ROWS);
+        let label_w = self.matches().map(|c| c.label.width()).max().unwrap_or(0);
+        let kind_w = self.matches().map(|c| c.kind.width()).max().unwrap_or(0);
+        let menu_w = label_w + kind_w + 4;
+
+        let doc: Vec<&str> = self.selected()
+            .and_then(|c| c.doc.as_deref())
+            .map_or(Vec::new(), |d| d.lines().take(MAX_ROWS).collect());
+        let doc_w = match doc.iter().map(|l| l.width()).max() {
+            Some(w) => w.min(MAX_DOC_WIDTH) + 2,
+            None => 0,
+        };
+
+        let mut f = Float::new(anchor, "");
+        f.z = MENU_Z;
+        f.border = false;
+        f.highlight = "Pmenu".into();
+        f.width = menu_w + doc_w;
+        f.height = rows.max(doc.len()).max(1);
+
+        let mut lines = Vec::new();
+        for row in 0..f.height {
+            let n = self.top + row;
+            let mut line = match self.matches.get(n).filter(|_| row < rows) {
+                Some(&i) => {
+                    let c = &self.items[i];
+                    if n == self.selected {
+                        f.spans.push((row, 0, menu_w, "PmenuSel".into()));
+                    } else {
+                        f.spans.push((row, label_w + 3, menu_w, "PmenuKind".into()));
+                    }
+                    format!("{}{} ", fit(&format!(" {}", c.label), label_w + 3), fit(&c.kind, kind_w))
+                },
+                None => " ".repeat(menu_w),
+            };
+            if doc_w > 0 {
+                line.push_str(&fit(&format!(" {}", doc.get(row).unwrap_or(&"")), doc_w));
+                f.spans.push((row, menu_w, menu_w + doc_w, "PmenuDoc".into()));
+            }
+            lines.push(line);
+        }
+        f.set_text(&lines.join("\n"));
+        f
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_rank_word_starts() {
+        assert_eq!(fuzzy_score("cfg", "cat"), None);
+        assert_eq!(fuzzy_score("", "cat"), Some(0));
+        assert_eq!(fuzzy_score("Cf", "config"), None);
+
+        let items = ["recfg", "config", "cf_get", "other"].iter()
+            .map(|t| Candidate::new(t, "word"))
+            .collect();
+        let mut menu = Menu::new((0, 0), "cfg", items);
+        let texts: Vec<&str> = menu.matches().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["cf_get", "config", "recfg"]);
+
+        menu.select(-1);
+        assert_eq!(menu.selected().unwrap().text, "recfg");
+        menu.set_query("con");
+        assert_eq!(menu.selected().unwrap().text, "config");
+    }
+
+    #[test]
+    fn words_and_paths() {
+        let dir = std::env::temp_dir().join(format!("editor-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src").join("main.rs"), "").unwrap();
+        fs::write(dir.join("src").join(".hidden"), "").unwrap();
+        fs::create_dir_all(dir.join("src").join("buffer")).unwrap();
+
+        let ctx = Context{
+            lines: vec!["let alpha = beta;", "alpha(x, al"],
+            before: "open(\"src/main.",
+            word: "",
+            dir: dir.clone(),
+        };
+        let words: Vec<String> = Words.candidates(&ctx).into_iter().map(|c| c.text).collect();
+        assert_eq!(words, vec!["let", "alpha", "beta", "al"]);
+
+        let paths = Paths.candidates(&ctx);
+        assert_eq!(paths.len(), 1);
+        assert_eq!((paths[0].label.as_str(), paths[0].text.as_str()), ("main.rs", "rs"));
+
+        let ctx = Context{
+            before: "src/b",
+            word: "b",
+            ..ctx
+        };
+        let paths: Vec<String> = Paths.candidates(&ctx).into_iter().map(|c| c.text).collect();
+        assert_eq!(paths, vec!["buffer/", "main.rs"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    env,
+    fs,
+    rc::Rc,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use crate::{
+    Error,
+    Result,
+    Options,
+    Keymap,
+    Theme,
+    View,
+    Grammar,
+    Highlighter,
+    filetype,
+    keymap::{
+        self,
+        Action,
+        Mode,
+    },
+};
+
+// maximum depth of nested `source` commands
+const MAX_SOURCE_DEPTH: usize = 16;
+
+// the state set up by the config file. each line of the
+// file is a command, the same commands can be given at the
+// `:` prompt
+//
+//     " comment
+//     set tabwidth=4
+//     map normal <C-q> quit
+//     unmap normal q
+//     highlight Comment fg=blue italic
+//     highlight link Keyword Statement
+//     colorscheme dark
+//     filetype rust *.rs
+//     lsp rust rust-analyzer
+//     source other-file
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub options: Options,
+    pub keymap: Keymap,
+    // the styles of highlight groups
+    pub theme: Theme,
+    // filetype name and the file name glob which selects it
+    pub filetypes: Vec<(String, String)>,
+    // filetype name and the language server command for it,
+    // an empty command turns the server off
+    pub servers: Vec<(String, Vec<String>)>,
+
+    // the file that was loaded at startup
+    pub path: Option<PathBuf>,
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/editor/config falling back to
+    // $HOME/.config/editor/config
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|h| PathBuf::from(h).join(".config"))
+            })?;
+
+        Some(base.join("editor").join("config"))
+    }
+
+    // loads the config at path on top of the defaults, lines with
+    // errors are skipped and the first error is returned alongside
+    // the config
+    pub fn load<P: AsRef<Path>>(path: P) -> (Config, Option<Error>) {
+        let mut ret = Config{
+            path: Some(path.as_ref().to_path_buf()),
+            ..Config::default()
+        };
+
+        let err = ret.source(path).err();
+        (ret, err)
+    }
+
+    // runs every line of the file at path
+    pub fn source<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.source_depth(path.as_ref(), 0)
+    }
+
+    fn source_depth(&mut self, path: &Path, depth: usize) -> Result<()> {
+        let err = |line, msg: String| Error::Config{
+            path: path.to_path_buf(),
+            line,
+            msg,
+        };
+
+        if depth > MAX_SOURCE_DEPTH {
+            return Err(err(0, "source nested too deeply".into()));
+        }
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| err(0, e.to_string()))?;
+
+        let mut first_err = None;
+
+        for (n, line) in text.lines().enumerate() {
+            let res = match parse_command(line) {
+                Some(("source", arg)) => {
+                    let p = resolve(path, arg);
+                    self.source_depth(&p, depth + 1)
+                },
+                _ => self.exec(line).map_err(|msg| err(n + 1, msg)),
+            };
+
+            if let Err(e) = res {
+                first_err.get_or_insert(e);
+            }
+        }
+
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    // runs a single command, `source` is handled by the caller
+    // since it needs to know where relative paths start from
+    pub fn exec(&mut self, line: &str) -> std::result::Result<(), String> {
+        let (cmd, arg) = match parse_command(line) {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+
+        let mut args = arg.split_whitespace();
+
+        match cmd {
+            "set" | "se" => {
+                if arg.is_empty() {
+                    return Err("set requires an option".into());
+                }
+                for a in split_escaped(arg) {
+                    self.options.set(&a)?;
+                }
+            },
+            "map" | "unmap" => {
+                let mode = args.next()
+                    .ok_or_else(|| format!("{} requires a mode", cmd))?;
+                let mode = Mode::from_name(mode)
+                    .ok_or_else(|| format!("unknown mode: {}", mode))?;
+                let keys = args.next()
+                    .ok_or_else(|| format!("{} requires keys", cmd))?;
+                let keys = keymap::parse_keys(keys)?;
+
+                if cmd == "unmap" {
+                    if !self.keymap.unbind(mode, &keys) {
+                        return Err("no such mapping".into());
+                    }
+                } else {
+                    let name = args.next()
+                        .ok_or_else(|| "map requires an action".to_string())?;
+                    let action = Action::from_name(name)
+                        .ok_or_else(|| format!("unknown action: {}", name))?;
+                    self.keymap.bind(mode, keys, action);
+                }
+            },
+            "highlight" | "hi" => self.theme.highlight(arg)?,
+            "colorscheme" | "colo" => {
+                let name = args.next()
+                    .ok_or_else(|| "colorscheme requires a name".to_string())?;
+                self.colorscheme(name)?;
+            },
+            "filetype" | "ft" => {
+                let name = args.next()
+                    .ok_or_else(|| "filetype requires a name".to_string())?;
+                let mut any = false;
+                for glob in args {
+                    self.filetypes.push((name.into(), glob.into()));
+                    any = true;
+                }
+                if !any {
+                    return Err("filetype requires a pattern".into());
+                }
+            },
+            "lsp" => {
+                let name = args.next()
+                    .ok_or_else(|| "lsp requires a filetype".to_string())?;
+                self.servers.push((name.into(), args.map(String::from).collect()));
+            },
+            _ => return Err(format!("unknown command: {}", cmd)),
+        }
+
+        Ok(())
+    }
+
+    // colorschemes are files in `colors` next to the config
+    // file, `default` is built in
+    pub fn colorscheme(&mut self, name: &str) -> std::result::Result<(), String> {
+        let path = self.dir()
+            .map(|d| d.join("colors").join(name))
+            .filter(|p| p.exists());
+
+        self.theme = match path {
+            Some(p) => Theme::load(name, p).map_err(|e| e.to_string())?,
+            None if name == "default" => Theme::default(),
+            None => return Err(format!("no such colorscheme: {}", name)),
+        };
+        Ok(())
+    }
+
+    // the language server for a filetype, the one set with
+    // `lsp` or else the filetype's own
+    pub fn server(&self, filetype: &str) -> Option<Vec<String>> {
+        let cmd = match self.servers.iter().rev().find(|(ft, _)| ft == filetype) {
+            Some((_, cmd)) => cmd.clone(),
+            None => filetype::get(filetype)?.lsp.iter().map(|s| s.to_string()).collect(),
+        };
+        Some(cmd).filter(|c| !c.is_empty())
+    }
+
+    // whether the server for filetype was set with `lsp`
+    pub fn has_server(&self, filetype: &str) -> bool {
+        self.servers.iter().any(|(ft, _)| ft == filetype)
+    }
+
+    // the directory of the config file, or the one it would be
+    // in when there isn't one
+    fn dir(&self) -> Option<PathBuf> {
+        let path = self.path.clone().or_else(Config::default_path)?;
+        path.parent().map(Path::to_path_buf)
+    }
+
+    // grammars for highlighting are files named after their
+    // filetype in `syntax` next to the config file
+    pub fn grammar_path(&self, filetype: &str) -> Option<PathBuf> {
+        Some(self.dir()?.join("syntax").join(format!("{}.syntax", filetype)))
+    }
+
+    // snippets for a filetype are in `snippets` next to the
+    // config file
+    pub fn snippet_path(&self, filetype: &str) -> Option<PathBuf> {
+        Some(self.dir()?.join("snippets").join(format!("{}.snippets", filetype)))
+    }
+
+    // tree-sitter queries for a filetype are in a directory
+    // named after it in `syntax`
+    pub fn query_dir(&self, filetype: &str) -> Option<PathBuf> {
+        Some(self.dir()?.join("syntax").join(filetype))
+    }
+
+    // highlights the view with the grammar of its filetype, if
+    // there is one, dropping what highlighted it before
+    pub fn load_highlighter(&self, view: &mut View) -> std::result::Result<(), String> {
+        let filetype = view.file_config().filetype.clone();
+
+        // a tree-sitter language takes over from a grammar
+        #[cfg(feature = "tree-sitter")]
+        {
+            let tree = match &filetype {
+                Some(ft) => crate::SyntaxTree::for_filetype(ft, self.query_dir(ft).as_deref())?,
+                None => None,
+            };
+            if let Some(mut tree) = tree {
+                tree.set_theme(&self.theme);
+                view.set_syntax_tree(Some(tree));
+                view.set_highlighter(None);
+                return Ok(());
+            }
+            view.set_syntax_tree(None);
+        }
+
+        let hl = filetype
+            .and_then(|ft| self.grammar_path(&ft))
+            .filter(|p| p.exists())
+            .map(Grammar::load)
+            .transpose()
+            .map_err(|e| e.to_string())?
+            .map(|g| Highlighter::new(Rc::new(g), &self.theme));
+
+        view.set_highlighter(hl);
+        Ok(())
+    }
+}
+
+// splits a line into the command and the rest, blank lines
+// and comments return None
+pub fn parse_command(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('"') || line.starts_with('#') {
+        return None;
+    }
+
+    match line.find(char::is_whitespace) {
+        Some(idx) => Some((&line[..idx], line[idx..].trim())),
+        None => Some((line, "")),
+    }
+}
+
+// splits at white space which isn't escaped with a backslash,
+// so values like `statusline=%f\ %m` can have spaces
+fn split_escaped(s: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut cur = String::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(n) if n.is_whitespace() || n == '\\' => cur.push(n),
+                Some(n) => {
+                    cur.push(c);
+                    cur.push(n);
+                },
+                None => cur.push(c),
+            },
+            c if c.is_whitespace() => {
+                if !cur.is_empty() {
+                    ret.push(std::mem::take(&mut cur));
+                }
+            },
+            c => cur.push(c),
+        }
+    }
+    if !cur.is_empty() {
+        ret.push(cur);
+    }
+
+    ret
+}
+
+// paths in a sourced file are relative to that file
+fn resolve(from: &Path, arg: &str) -> PathBuf {
+    let p = expand_home(arg);
+    if p.is_absolute() {
+        return p;
+    }
+
+    from.parent()
+        .map(|dir| dir.join(&p))
+        .unwrap_or(p)
+}
+
+pub fn expand_home(arg: &str) -> PathBuf {
+    if let Some(rest) = arg.strip_prefix("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+
+    PathBuf::from(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes files under a temp directory named after the test
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir()
+            .join(format!("editor-config-{}-{}", name, std::process::id()));
+        for (path, text) in files {
+            let p = dir.join(path);
+            fs::create_dir_all(p.parent().unwrap()).unwrap();
+            fs::write(p, text).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn bad_lines_are_skipped() {
+        let dir = write_files("bad", &[
+            ("config", "set tabwidth=4\n\" comment\nfrobnicate x\nset nonumber\n"),
+        ]);
+
+        let (config, err) = Config::load(dir.join("config"));
+        let err = err.unwrap().to_string();
+        assert_eq!(err, format!("{}: unknown command: frobnicate", dir.join("config:3").display()));
+        assert_eq!(config.options.tab_width, 4);
+        assert!(!config.options.number);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_in_sourced_files_say_where() {
+        let dir = write_files("source", &[
+            ("config", "set tabwidth=2\nsource sub/keys\nset nonumber\n"),
+            ("sub/keys", "map normal <C-q> quit\nmap normal x nothing\nsource more\n"),
+            ("sub/more", "set tabwidth=3\n"),
+        ]);
+
+        let (config, err) = Config::load(dir.join("config"));
+        match err {
+            Some(Error::Config{path, line, msg}) => {
+                assert_eq!(path, dir.join("sub/keys"));
+                assert_eq!(line, 2);
+                assert_eq!(msg, "unknown action: nothing");
+            },
+            e => panic!("{:?}", e),
+        }
+        // relative to the file sourcing it
+        assert_eq!(config.options.tab_width, 3);
+        assert!(!config.options.number);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn alt_screen_option() {
+        let mut config = Config::default();
+        assert!(config.options.alt_screen);
+        config.exec("set altscreen=false").unwrap();
+        assert!(!config.options.alt_screen);
+        config.exec("set altscreen").unwrap();
+        assert!(config.options.alt_screen);
+        config.exec("set noaltscreen").unwrap();
+        assert!(!config.options.alt_screen);
+        assert!(config.exec("set altscreen=maybe").is_err());
+    }
+
+    #[test]
+    fn filetype_rules_take_globs() {
+        let mut config = Config::default();
+        config.exec("filetype conf *.rs.in {a,b}.cfg").unwrap();
+        config.exec("filetype make Makefile.[!c]*").unwrap();
+        assert!(config.exec("filetype conf").is_err());
+
+        let detect = |name: &str| filetype::detect(&config.filetypes, Some(Path::new(name)), &[]);
+        assert_eq!(detect("x/build.rs.in").as_deref(), Some("conf"));
+        assert_eq!(detect("b.cfg").as_deref(), Some("conf"));
+        assert_eq!(detect("c.cfg"), None);
+        assert_eq!(detect("Makefile.am").as_deref(), Some("make"));
+        assert_eq!(detect("main.rs").as_deref(), Some("rust"));
+
+        // later rules win
+        config.exec("filetype text *.rs").unwrap();
+        assert_eq!(filetype::detect(&config.filetypes, Some(Path::new("main.rs")), &[]).as_deref(), Some("text"));
+    }
+
+    #[test]
+    fn runtime_files_without_a_config_file() {
+        let dir = write_files("xdg", &[
+            ("editor/syntax/c.syntax", &fs::read_to_string("syntax/rust.syntax").unwrap()),
+            ("editor/colors/dusk", "highlight Comment fg=blue\n"),
+            ("editor/snippets/c.snippets", "snippet main\n\tint main() {}\n"),
+        ]);
+        // the only test which sets it
+        env::set_var("XDG_CONFIG_HOME", &dir);
+
+        let mut config = Config::default();
+        assert_eq!(config.grammar_path("c"), Some(dir.join("editor/syntax/c.syntax")));
+        assert_eq!(config.query_dir("c"), Some(dir.join("editor/syntax/c")));
+        let snippets = crate::Snippets::load(config.snippet_path("c").unwrap()).unwrap();
+        assert_eq!(snippets.body("main"), Some("int main() {}"));
+
+        let mut buf = crate::Buffer::from("fn a() {}");
+        let mut view = View::new(&mut buf);
+        view.file_config_mut().filetype = Some("c".into());
+        config.load_highlighter(&mut view).unwrap();
+        assert!(view.highlighter_mut().is_some());
+
+        config.colorscheme("dusk").unwrap();
+        assert!(config.colorscheme("dawn").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs,
+    io,
+    path::Path,
+};
+
+use crate::{
+    Result,
+    glob,
+    LineConfig,
+    FileConfig,
+    LineEnding,
+    Charset,
+};
+
+const FILE_NAME: &str = ".editorconfig";
+
+// the properties from every .editorconfig which applies to
+// a file, see https://editorconfig.org
+#[derive(Debug, Clone, Default)]
+pub struct Properties {
+    props: HashMap<String, String>,
+}
+
+// finds the .editorconfig files from the file's directory up
+// to the first one with `root = true` and merges the sections
+// matching the file, nearer files take precedence
+pub fn lookup<P: AsRef<Path>>(path: P) -> Result<Properties> {
+    let path = path.as_ref();
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()?.join(path)
+    };
+
+    let mut files = Vec::new();
+
+    for dir in path.ancestors().skip(1) {
+        let text = match fs::read_to_string(dir.join(FILE_NAME)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let file = File::parse(&text);
+        let root = file.root;
+        files.push((dir.to_path_buf(), file));
+
+        if root {
+            break;
+        }
+    }
+
+    let mut ret = Properties::default();
+
+    for (dir, file) in files.iter().rev() {
+        let rel = match path.strip_prefix(dir) {
+            Ok(rel) => to_slashes(rel),
+            Err(_) => continue,
+        };
+
+        for (glob, props) in file.sections.iter() {
+            if !section_matches(glob, &rel) {
+                continue;
+            }
+
+            for (k, v) in props.iter() {
+                if v == "unset" {
+                    ret.props.remove(k);
+                } else {
+                    ret.props.insert(k.clone(), v.clone());
+                }
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+impl Properties {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.props.get(name).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.props.is_empty()
+    }
+
+    pub fn apply(&self, lcfg: &mut LineConfig, fcfg: &mut FileConfig) {
+        self.apply_line_config(lcfg);
+        self.apply_file_config(fcfg);
+    }
+
+    pub fn apply_line_config(&self, lcfg: &mut LineConfig) {
+        match self.get("indent_style") {
+            Some("space") => lcfg.expand_tab = true,
+            Some("tab") => lcfg.expand_tab = false,
+            _ => {},
+        }
+
+        let indent_size = self.get("indent_size")
+            .and_then(|v| v.parse::<u8>().ok());
+        let tab_width = self.get("tab_width")
+            .and_then(|v| v.parse::<u8>().ok())
+            .or(indent_size);
+
+        if let Some(n) = tab_width {
+            lcfg.tab_width = n;
+        }
+
+        match self.get("indent_size") {
+            Some("tab") => lcfg.indent_size = lcfg.tab_width,
+            _ => if let Some(n) = indent_size {
+                lcfg.indent_size = n;
+            },
+        }
+    }
+
+    pub fn apply_file_config(&self, fcfg: &mut FileConfig) {
+        if let Some(le) = self.get("end_of_line").and_then(LineEnding::from_name) {
+            fcfg.line_ending = le;
+        }
+
+        if let Some(cs) = self.get("charset").and_then(Charset::from_name) {
+            fcfg.charset = cs;
+        }
+
+        if let Some(b) = self.get("trim_trailing_whitespace").and_then(parse_bool) {
+            fcfg.trim_trailing_whitespace = b;
+        }
+
+        if let Some(b) = self.get("insert_final_newline").and_then(parse_bool) {
+            fcfg.final_newline = b;
+        }
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn to_slashes(p: &Path) -> String {
+    p.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// a parsed .editorconfig file
+#[derive(Debug, Default)]
+struct File {
+    root: bool,
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl File {
+    // invalid lines are ignored, as the spec says
+    fn parse(text: &str) -> File {
+        let mut ret = File::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let glob = line[1..line.len()-1].to_string();
+                ret.sections.push((glob, Vec::new()));
+                continue;
+            }
+
+            let idx = match line.find('=') {
+                Some(idx) => idx,
+                None => continue,
+            };
+
+            let key = line[..idx].trim().to_ascii_lowercase();
+            let value = line[idx+1..].trim().to_ascii_lowercase();
+
+            match ret.sections.last_mut() {
+                Some((_, props)) => props.push((key, value)),
+                None if key == "root" => ret.root = value == "true",
+                None => {},
+            }
+        }
+
+        ret
+    }
+}
+
+// matches a path relative to the .editorconfig's directory,
+// globs without a `/` match the file name in any directory
+fn section_matches(glob: &str, path: &str) -> bool {
+    let glob = match glob.strip_prefix('/') {
+        Some(g) => g.to_string(),
+        None if glob.contains('/') => glob.to_string(),
+        None => format!("**/{}", glob),
+    };
+
+    glob::matches(&glob, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    // a directory tree with .editorconfig files in it, removed
+    // when dropped
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str, files: &[(&str, &str)]) -> Tree {
+            let dir = env::temp_dir()
+                .join(format!("editor-ec-{}-{}", name, std::process::id()));
+            for (path, text) in files {
+                let p = dir.join(path);
+                fs::create_dir_all(p.parent().unwrap()).unwrap();
+                fs::write(p, text).unwrap();
+            }
+            Tree(dir)
+        }
+
+        fn lookup(&self, path: &str) -> Properties {
+            lookup(self.0.join(path)).unwrap()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn sections_match_names_anywhere_and_paths_from_the_file() {
+        assert!(section_matches("*.rs", "main.rs"));
+        assert!(section_matches("*.rs", "src/main.rs"));
+        assert!(section_matches("src/*.rs", "src/main.rs"));
+        assert!(!section_matches("src/*.rs", "a/src/main.rs"));
+        assert!(section_matches("/Makefile", "Makefile"));
+        assert!(!section_matches("/Makefile", "sub/Makefile"));
+    }
+
+    #[test]
+    fn parse_ignores_invalid_lines() {
+        let file = File::parse("root = TRUE\nnonsense\n[*]\n; comment\nIndent_Style = Tab\n");
+        assert!(file.root);
+        assert_eq!(file.sections, vec![
+            ("*".to_string(), vec![("indent_style".to_string(), "tab".to_string())]),
+        ]);
+    }
+
+    #[test]
+    fn nearer_files_and_later_sections_win() {
+        let tree = Tree::new("precedence", &[
+            (".editorconfig", "root = true\n[*]\nindent_size = 2\ntab_width = 8\n"),
+            ("src/.editorconfig", "[*]\nindent_size = 4\n[*.rs]\nindent_size = 3\n"),
+        ]);
+
+        let props = tree.lookup("src/main.rs");
+        assert_eq!(props.get("indent_size"), Some("3"));
+        assert_eq!(props.get("tab_width"), Some("8"));
+        assert_eq!(tree.lookup("src/a.c").get("indent_size"), Some("4"));
+        assert_eq!(tree.lookup("a.c").get("indent_size"), Some("2"));
+    }
+
+    #[test]
+    fn root_stops_the_search() {
+        let tree = Tree::new("root", &[
+            (".editorconfig", "[*]\ncharset = latin1\nindent_style = tab\n"),
+            ("sub/.editorconfig", "root = true\n[*]\nindent_style = space\n"),
+        ]);
+
+        let props = tree.lookup("sub/a.txt");
+        assert_eq!(props.get("indent_style"), Some("space"));
+        assert_eq!(props.get("charset"), None);
+    }
+
+    #[test]
+    fn unset_removes_a_property() {
+        let tree = Tree::new("unset", &[
+            (".editorconfig", "root = true\n[*]\nend_of_line = crlf\ninsert_final_newline = false\n"),
+            ("sub/.editorconfig", "[*.md]\nend_of_line = unset\n"),
+        ]);
+
+        let props = tree.lookup("sub/a.md");
+        assert_eq!(props.get("end_of_line"), None);
+        assert_eq!(props.get("insert_final_newline"), Some("false"));
+        assert_eq!(tree.lookup("sub/a.txt").get("end_of_line"), Some("crlf"));
+
+        let mut fcfg = FileConfig::default();
+        props.apply_file_config(&mut fcfg);
+        assert_eq!(fcfg.line_ending, LineEnding::Lf);
+        assert!(!fcfg.final_newline);
+    }
+}
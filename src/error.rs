@@ -6,13 +6,25 @@ pub enum Error {
     InvalidCursorSize(usize),
     InvalidRendererSize(usize),
     Io(std::io::Error),
+    // an error in a config file, line 0 means the file itself
+    Config{
+        path: std::path::PathBuf,
+        line: usize,
+        msg: String,
+    },
 }
 
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Error::Config{path, line, msg} => {
+                write!(f, "{}:{}: {}", path.display(), line, msg)
+            },
+            Error::Io(e) => write!(f, "{}", e),
+            _ => write!(f, "{:?}", self),
+        }
     }
 }
 
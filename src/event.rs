@@ -0,0 +1,297 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    sync::mpsc::{
+        self,
+        Receiver,
+        RecvTimeoutError,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use crate::{
+    Result,
+    signal,
+    input::{
+        self,
+        Decoder,
+        Input,
+        Key,
+        Mouse,
+    },
+};
+
+// how long a lone escape waits for the rest of a sequence
+// before it's the escape key
+const ESCAPE_TIMEOUT: Duration = Duration::from_millis(25);
+
+// bytes read from stdin, sent to the event loop to decode
+struct Bytes(Vec<u8>);
+
+
+#[derive(Debug)]
+pub enum Event {
+    Key(Key),
+    Mouse(Mouse),
+    // pasted text, to be inserted as is
+    Paste(String),
+    // the terminal changed size, everything has to be laid
+    // out and drawn again
+    Resize,
+    // a SIGTERM or SIGHUP asked the editor to exit
+    Terminate,
+    // a timer set with EventLoop::set_timer went off
+    Timer(TimerId),
+    // something sent through a Handle, usually the result of
+    // a job running in another thread
+    Message(Box<dyn Any + Send>),
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash)]
+pub struct TimerId(usize);
+
+// sends messages to the event loop from other threads
+#[derive(Debug,Clone)]
+pub struct Handle {
+    tx: mpsc::Sender<Event>,
+}
+
+impl Handle {
+    // false once the event loop is gone
+    pub fn send<T: Any + Send>(&self, msg: T) -> bool {
+        self.tx.send(Event::Message(Box::new(msg))).is_ok()
+    }
+}
+
+// merges keys, resizes, timers and messages from other
+// threads into one stream of events
+#[derive(Debug)]
+pub struct EventLoop {
+    tx: mpsc::Sender<Event>,
+    rx: Receiver<Event>,
+    // pending timers, soonest last
+    timers: Vec<(Instant, TimerId)>,
+    next_timer: usize,
+    decoder: Decoder,
+    // decoded from one read and not returned yet
+    inputs: VecDeque<Event>,
+    // flushes a lone escape
+    escape_timer: Option<TimerId>,
+}
+
+impl Default for EventLoop {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        EventLoop{
+            tx,
+            rx,
+            timers: Vec::new(),
+            next_timer: 0,
+            decoder: Decoder::new(),
+            inputs: VecDeque::new(),
+            escape_timer: None,
+        }
+    }
+}
+
+impl EventLoop {
+    pub fn new() -> EventLoop {
+        EventLoop::default()
+    }
+
+    // starts reading input from stdin and watching for
+    // resizes and signals to exit
+    pub fn listen(&mut self) -> Result<()> {
+        let resize = self.tx.clone();
+        signal::watch(&[signal::SIGWINCH], move |_| {
+            let _ = resize.send(Event::Resize);
+        })?;
+
+        let term = self.tx.clone();
+        signal::watch(&[signal::SIGTERM, signal::SIGHUP], move |_| {
+            let _ = term.send(Event::Terminate);
+        })?;
+
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut b = [0u8; 4096];
+
+            while let Ok(n) = stdin.read(&mut b) {
+                if n == 0 {
+                    break;
+                }
+                let bytes = Event::Message(Box::new(Bytes(b[..n].to_vec())));
+                if tx.send(bytes).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    // decodes what was read, a lone escape at the end waits a
+    // little for the rest of its sequence
+    fn decode(&mut self, b: &[u8]) {
+        if let Some(id) = self.escape_timer.take() {
+            self.cancel_timer(id);
+        }
+
+        let inputs = self.decoder.feed(b);
+        self.inputs.extend(inputs.into_iter().map(|input| match input {
+            Input::Key(k) => Event::Key(k),
+            Input::Mouse(m) => Event::Mouse(m),
+            Input::Paste(s) => Event::Paste(s),
+        }));
+
+        if self.decoder.has_escape() {
+            self.escape_timer = Some(self.set_timer(ESCAPE_TIMEOUT));
+        }
+
+        // the terminal knows kitty's keyboard protocol
+        if self.decoder.take_kitty() {
+            let mut out = io::stdout();
+            let _ = write!(out, "{}", input::ENABLE_KITTY);
+            let _ = out.flush();
+        }
+    }
+
+    pub fn handle(&self) -> Handle {
+        Handle{
+            tx: self.tx.clone(),
+        }
+    }
+
+    // runs f in a new thread, whatever it returns comes back
+    // as a message
+    pub fn spawn<F, T>(&self, f: F)
+    where
+        F: FnOnce(Handle) -> T + Send + 'static,
+        T: Any + Send,
+    {
+        let handle = self.handle();
+        thread::spawn(move || {
+            let ret = f(handle.clone());
+            handle.send(ret);
+        });
+    }
+
+    pub fn set_timer(&mut self, after: Duration) -> TimerId {
+        let id = TimerId(self.next_timer);
+        self.next_timer += 1;
+
+        let at = Instant::now() + after;
+        let idx = self.timers.iter()
+            .position(|(t, _)| *t <= at)
+            .unwrap_or(self.timers.len());
+        self.timers.insert(idx, (at, id));
+
+        id
+    }
+
+    pub fn cancel_timer(&mut self, id: TimerId) {
+        self.timers.retain(|(_, t)| *t != id);
+    }
+
+    // waits for the next event, timers which are due come
+    // before anything else
+    pub fn wait(&mut self) -> Option<Event> {
+        loop {
+            if let Some(ev) = self.inputs.pop_front() {
+                return Some(ev);
+            }
+
+            let ev = match self.timers.last() {
+                Some((at, _)) if *at <= Instant::now() => {
+                    let (_, id) = self.timers.pop().unwrap();
+                    if self.escape_timer == Some(id) {
+                        self.escape_timer = None;
+                        if let Some(Input::Key(k)) = self.decoder.flush_escape() {
+                            self.inputs.push_back(Event::Key(k));
+                        }
+                        continue;
+                    }
+                    Event::Timer(id)
+                },
+                Some((at, _)) => {
+                    let left = at.saturating_duration_since(Instant::now());
+                    match self.rx.recv_timeout(left) {
+                        Ok(ev) => ev,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return None,
+                    }
+                },
+                None => self.rx.recv().ok()?,
+            };
+
+            match ev {
+                Event::Message(msg) => match msg.downcast::<Bytes>() {
+                    Ok(bytes) => self.decode(&bytes.0),
+                    Err(msg) => return Some(Event::Message(msg)),
+                },
+                ev => return Some(ev),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::KeyCode;
+
+    #[test]
+    fn timers_fire_in_order() {
+        let mut ev = EventLoop::new();
+        let late = ev.set_timer(Duration::from_millis(20));
+        let early = ev.set_timer(Duration::from_millis(5));
+        let cancelled = ev.set_timer(Duration::from_millis(10));
+        ev.cancel_timer(cancelled);
+
+        assert!(matches!(ev.wait(), Some(Event::Timer(id)) if id == early));
+        assert!(matches!(ev.wait(), Some(Event::Timer(id)) if id == late));
+    }
+
+    fn read(ev: &EventLoop, b: &[u8]) {
+        ev.tx.send(Event::Message(Box::new(Bytes(b.to_vec())))).unwrap();
+    }
+
+    #[test]
+    fn lone_escape_waits_for_the_rest() {
+        let mut ev = EventLoop::new();
+        read(&ev, b"a\x1b");
+        read(&ev, b"[A");
+        assert!(matches!(ev.wait(), Some(Event::Key(k)) if k == Key::char('a')));
+        assert!(matches!(ev.wait(), Some(Event::Key(k)) if k == KeyCode::Up.into()));
+
+        // nothing follows, so it's the escape key
+        read(&ev, b"\x1b");
+        let timer = ev.set_timer(ESCAPE_TIMEOUT * 4);
+        assert!(matches!(ev.wait(), Some(Event::Key(k)) if k == KeyCode::Esc.into()));
+        assert!(matches!(ev.wait(), Some(Event::Timer(id)) if id == timer));
+    }
+
+    #[test]
+    fn messages_from_jobs() {
+        let mut ev = EventLoop::new();
+        ev.set_timer(Duration::from_secs(10));
+        ev.spawn(|_| 42usize);
+
+        match ev.wait() {
+            Some(Event::Message(msg)) => assert_eq!(msg.downcast_ref(), Some(&42usize)),
+            ev => panic!("unexpected event: {:?}", ev),
+        }
+    }
+}
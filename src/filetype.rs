@@ -0,0 +1,383 @@
+use std::path::Path;
+
+use crate::{
+    LineConfig,
+    glob,
+};
+
+
+// what the editor knows about a kind of file
+#[derive(Debug)]
+pub struct FileType {
+    pub name: &'static str,
+    // file name globs
+    globs: &'static [&'static str],
+    // programs named in a `#!` line
+    interpreters: &'static [&'static str],
+    // how a line is commented out, `%s` is the line
+    pub comment: &'static str,
+    // indent size and whether to indent with spaces
+    pub indent: Option<(u8, bool)>,
+    // the bracket pairs lines are indented between
+    pub indent_brackets: &'static str,
+    // the language server and its arguments
+    pub lsp: &'static [&'static str],
+}
+
+impl FileType {
+    pub fn apply_line_config(&self, lcfg: &mut LineConfig) {
+        lcfg.indent_brackets = self.indent_brackets;
+        if let Some((size, expand)) = self.indent {
+            lcfg.indent_size = size;
+            lcfg.expand_tab = expand;
+            if !expand {
+                lcfg.tab_width = size;
+            }
+        }
+    }
+}
+
+const FILETYPES: &[FileType] = &[
+    FileType{
+        name: "rust",
+        globs: &["*.rs"],
+        interpreters: &[],
+        comment: "// %s",
+        indent: Some((4, true)),
+        indent_brackets: "(){}[]",
+        lsp: &["rust-analyzer"],
+    },
+    FileType{
+        name: "c",
+        globs: &["*.c", "*.h"],
+        interpreters: &[],
+        comment: "/* %s */",
+        indent: None,
+        indent_brackets: "(){}[]",
+        lsp: &["clangd"],
+    },
+    FileType{
+        name: "cpp",
+        globs: &["*.cpp", "*.cc", "*.cxx", "*.hpp", "*.hh", "*.hxx"],
+        interpreters: &[],
+        comment: "// %s",
+        indent: None,
+        indent_brackets: "(){}[]",
+        lsp: &["clangd"],
+    },
+    FileType{
+        name: "go",
+        globs: &["*.go"],
+        interpreters: &[],
+        comment: "// %s",
+        indent: Some((8, false)),
+        indent_brackets: "(){}[]",
+        lsp: &["gopls"],
+    },
+    FileType{
+        name: "python",
+        globs: &["*.py", "*.pyi"],
+        interpreters: &["python"],
+        comment: "# %s",
+        indent: Some((4, true)),
+        indent_brackets: "(){}[]",
+        lsp: &["pylsp"],
+    },
+    FileType{
+        name: "sh",
+        globs: &["*.sh", "*.bash", ".bashrc", ".bash_profile", ".profile"],
+        interpreters: &["sh", "bash", "dash", "ksh"],
+        comment: "# %s",
+        indent: None,
+        indent_brackets: "(){}[]",
+        lsp: &["bash-language-server", "start"],
+    },
+    FileType{
+        name: "javascript",
+        globs: &["*.js", "*.mjs", "*.cjs", "*.jsx"],
+        interpreters: &["node"],
+        comment: "// %s",
+        indent: Some((2, true)),
+        indent_brackets: "(){}[]",
+        lsp: &["typescript-language-server", "--stdio"],
+    },
+    FileType{
+        name: "typescript",
+        globs: &["*.ts", "*.tsx"],
+        interpreters: &[],
+        comment: "// %s",
+        indent: Some((2, true)),
+        indent_brackets: "(){}[]",
+        lsp: &["typescript-language-server", "--stdio"],
+    },
+    FileType{
+        name: "lua",
+        globs: &["*.lua"],
+        interpreters: &["lua"],
+        comment: "-- %s",
+        indent: None,
+        indent_brackets: "(){}[]",
+        lsp: &["lua-language-server"],
+    },
+    FileType{
+        name: "ruby",
+        globs: &["*.rb", "Gemfile", "Rakefile"],
+        interpreters: &["ruby"],
+        comment: "# %s",
+        indent: Some((2, true)),
+        indent_brackets: "(){}[]",
+        lsp: &[],
+    },
+    FileType{
+        name: "perl",
+        globs: &["*.pl", "*.pm"],
+        interpreters: &["perl"],
+        comment: "# %s",
+        indent: None,
+        indent_brackets: "(){}[]",
+        lsp: &[],
+    },
+    FileType{
+        name: "make",
+        globs: &["Makefile", "makefile", "GNUmakefile", "*.mk"],
+        interpreters: &["make"],
+        comment: "# %s",
+        indent: Some((8, false)),
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "dockerfile",
+        globs: &["Dockerfile", "*.dockerfile"],
+        interpreters: &[],
+        comment: "# %s",
+        indent: None,
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "json",
+        globs: &["*.json"],
+        interpreters: &[],
+        comment: "",
+        indent: Some((2, true)),
+        indent_brackets: "(){}[]",
+        lsp: &[],
+    },
+    FileType{
+        name: "toml",
+        globs: &["*.toml", "Cargo.lock"],
+        interpreters: &[],
+        comment: "# %s",
+        indent: None,
+        indent_brackets: "{}[]",
+        lsp: &[],
+    },
+    FileType{
+        name: "yaml",
+        globs: &["*.yml", "*.yaml"],
+        interpreters: &[],
+        comment: "# %s",
+        indent: Some((2, true)),
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "markdown",
+        globs: &["*.md", "*.markdown"],
+        interpreters: &[],
+        comment: "<!-- %s -->",
+        indent: None,
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "html",
+        globs: &["*.html", "*.htm"],
+        interpreters: &[],
+        comment: "<!-- %s -->",
+        indent: Some((2, true)),
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "xml",
+        globs: &["*.xml", "*.svg"],
+        interpreters: &[],
+        comment: "<!-- %s -->",
+        indent: Some((2, true)),
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "css",
+        globs: &["*.css"],
+        interpreters: &[],
+        comment: "/* %s */",
+        indent: Some((2, true)),
+        indent_brackets: "(){}[]",
+        lsp: &[],
+    },
+    FileType{
+        name: "diff",
+        globs: &["*.diff", "*.patch"],
+        interpreters: &[],
+        comment: "",
+        indent: None,
+        indent_brackets: "",
+        lsp: &[],
+    },
+    FileType{
+        name: "gitcommit",
+        globs: &["COMMIT_EDITMSG"],
+        interpreters: &[],
+        comment: "# %s",
+        indent: None,
+        indent_brackets: "",
+        lsp: &[],
+    },
+];
+
+// a built in filetype
+pub fn get(name: &str) -> Option<&'static FileType> {
+    FILETYPES.iter().find(|ft| ft.name == name)
+}
+
+// guesses the filetype of a file from, in order, a modeline,
+// the user's rules, the file name, a `#!` line or what the
+// text looks like. lines are the first and last few lines of
+// the file
+pub fn detect(rules: &[(String, String)], path: Option<&Path>, lines: &[&str]) ->
+    Option<String> {
+
+    if let Some(ft) = lines.iter().find_map(|l| modeline(l)) {
+        return Some(ft);
+    }
+
+    let name = path.and_then(|p| p.file_name()).and_then(|n| n.to_str());
+    if let Some(name) = name {
+        // later rules take precedence
+        let user = rules.iter()
+            .rev()
+            .find(|(_, glob)| glob::matches(glob, name));
+        if let Some((ft, _)) = user {
+            return Some(ft.clone());
+        }
+
+        let builtin = FILETYPES.iter()
+            .find(|ft| ft.globs.iter().any(|g| glob::matches(g, name)));
+        if let Some(ft) = builtin {
+            return Some(ft.name.into());
+        }
+    }
+
+    let first = lines.first()?;
+    if let Some(prog) = shebang(first) {
+        let ft = FILETYPES.iter().find(|ft| {
+            ft.interpreters.iter().any(|i| {
+                // python3 and python3.11 are python
+                prog.strip_prefix(i)
+                    .is_some_and(|v| v.chars().all(|c| c.is_ascii_digit() || c == '.'))
+            })
+        });
+        return ft.map(|ft| ft.name.into());
+    }
+
+    sniff(first).map(String::from)
+}
+
+// the filetype set in a vim or emacs modeline, like
+// `vim: set ft=rust :` or `-*- mode: rust -*-`
+fn modeline(line: &str) -> Option<String> {
+    for marker in ["vim:", "vi:", "ex:"].iter() {
+        let idx = match line.find(marker) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        // vi: has to start a word
+        if idx > 0 && !line[..idx].ends_with(char::is_whitespace) {
+            continue;
+        }
+
+        let opts = line[idx + marker.len()..].split(|c: char| c.is_whitespace() || c == ':');
+        for opt in opts {
+            let ft = opt.strip_prefix("ft=").or_else(|| opt.strip_prefix("filetype="));
+            if let Some(ft) = ft.filter(|ft| !ft.is_empty()) {
+                return Some(ft.into());
+            }
+        }
+    }
+
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+    if !vars.contains(':') {
+        return Some(vars.to_lowercase()).filter(|s| !s.is_empty());
+    }
+
+    vars.split(';').find_map(|var| {
+        let (k, v) = var.split_once(':')?;
+        if k.trim().eq_ignore_ascii_case("mode") {
+            Some(v.trim().to_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+// the program named in a `#!` line, skipping env and its
+// options
+fn shebang(line: &str) -> Option<&str> {
+    let mut words = line.strip_prefix("#!")?.split_whitespace();
+    let prog = words.next()?.rsplit('/').next()?;
+    if prog != "env" {
+        return Some(prog);
+    }
+
+    words.find(|w| !w.starts_with('-') && !w.contains('='))
+}
+
+// a filetype from how the first line looks
+fn sniff(line: &str) -> Option<&'static str> {
+    let lower = line.trim_start().to_ascii_lowercase();
+
+    if lower.starts_with("<?xml") {
+        Some("xml")
+    } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        Some("html")
+    } else if lower.starts_with("diff ") || lower.starts_with("--- ") {
+        Some("diff")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_in(path: &str, lines: &[&str]) -> Option<String> {
+        let rules = vec![("conf".to_string(), "*.rs.in".to_string())];
+        detect(&rules, Some(Path::new(path)), lines)
+    }
+
+    #[test]
+    fn detects_from_names_and_contents() {
+        assert_eq!(detect_in("src/main.rs", &[]).as_deref(), Some("rust"));
+        assert_eq!(detect_in("Makefile", &["all:"]).as_deref(), Some("make"));
+        assert_eq!(detect_in("build.rs.in", &[]).as_deref(), Some("conf"));
+        assert_eq!(detect_in("run", &["#!/usr/bin/env -S python3.11 -u"]).as_deref(), Some("python"));
+        assert_eq!(detect_in("run", &["#!/bin/bash"]).as_deref(), Some("sh"));
+        assert_eq!(detect_in("page", &["<!DOCTYPE html>"]).as_deref(), Some("html"));
+        assert_eq!(detect_in("notes", &["plain text"]), None);
+    }
+
+    #[test]
+    fn modelines_win() {
+        assert_eq!(detect_in("a.txt", &["x", "# vim: set ts=4 ft=python :"]).as_deref(), Some("python"));
+        assert_eq!(detect_in("a.rs", &["/* -*- mode: C; tab-width: 4 -*- */"]).as_deref(), Some("c"));
+        assert_eq!(detect_in("a", &["// -*- lua -*-"]).as_deref(), Some("lua"));
+        // not a modeline
+        assert_eq!(detect_in("a.rs", &["let envim: ft=c"]).as_deref(), Some("rust"));
+    }
+}
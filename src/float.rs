@@ -0,0 +1,391 @@
+use std::mem;
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    Cell,
+    Grid,
+    Style,
+    Theme,
+};
+
+
+// the largest a float is made to fit its text
+const MAX_WIDTH: usize = 60;
+const MAX_HEIGHT: usize = 12;
+
+// the columns a tab takes in a float
+const TAB: &str = "    ";
+
+// where a float is placed
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Anchor {
+    // the top left corner goes at this screen cell
+    Screen(usize, usize),
+    // the float goes under this line and column of the buffer,
+    // or over it when there's more room there, and is hidden
+    // while they're off screen
+    Buffer(usize, usize),
+}
+
+pub type FloatId = usize;
+
+// a window of text drawn over everything else
+#[derive(Debug,Clone)]
+pub struct Float {
+    pub anchor: Anchor,
+    // floats with a higher z are drawn over lower ones
+    pub z: i32,
+    pub border: bool,
+    // the size of the text area, without the border
+    pub width: usize,
+    pub height: usize,
+    pub highlight: String,
+    // highlight groups over parts of lines, as the line, the
+    // first column and the column after the last
+    pub spans: Vec<(usize, usize, usize, String)>,
+    lines: Vec<String>,
+    // the first line shown
+    scroll: usize,
+}
+
+impl Float {
+    // a float with a border sized to fit text, up to
+    // MAX_WIDTH by MAX_HEIGHT
+    pub fn new(anchor: Anchor, text: &str) -> Float {
+        let mut ret = Float{
+            anchor,
+            z: 0,
+            border: true,
+            width: 0,
+            height: 0,
+            highlight: "NormalFloat".into(),
+            spans: Vec::new(),
+            lines: Vec::new(),
+            scroll: 0,
+        };
+        ret.set_text(text);
+        ret.width = ret.lines.iter().map(|l| l.width()).max().unwrap_or(0).clamp(1, MAX_WIDTH);
+        ret.height = ret.lines.len().clamp(1, MAX_HEIGHT);
+        ret
+    }
+
+    // replaces the text, keeping the size
+    pub fn set_text(&mut self, text: &str) {
+        self.lines = text.lines().map(|l| l.replace('\t', TAB)).collect();
+        self.scroll = self.scroll.min(self.max_scroll());
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    fn max_scroll(&self) -> usize {
+        self.lines.len().saturating_sub(self.height)
+    }
+
+    // scrolls the text by n lines, it stops with the last line
+    // at the bottom
+    pub fn scroll(&mut self, n: isize) {
+        let to = (self.scroll as isize + n).max(0) as usize;
+        self.scroll = to.min(self.max_scroll());
+    }
+
+    pub fn scroll_pos(&self) -> usize {
+        self.scroll
+    }
+
+    // the size with the border
+    fn outer(&self) -> (usize, usize) {
+        let b = 2 * self.border as usize;
+        (self.width + b, self.height + b)
+    }
+
+    // the top left corner for an anchor at x, y on a screen of
+    // width by height, moved to fit when it can
+    fn place(&self, (x, y): (usize, usize), (width, height): (usize, usize)) -> (usize, usize) {
+        let (w, h) = self.outer();
+        let x = x.min(width.saturating_sub(w));
+
+        let y = match self.anchor {
+            Anchor::Screen(..) => y.min(height.saturating_sub(h)),
+            Anchor::Buffer(..) => {
+                let below = height.saturating_sub(y + 1);
+                if h <= below || below >= y {
+                    y + 1
+                } else {
+                    y.saturating_sub(h)
+                }
+            },
+        };
+        (x, y)
+    }
+}
+
+// a float as it was last drawn, with the rows of the screen
+// it was drawn over
+#[derive(Debug,Clone)]
+struct Placed {
+    id: FloatId,
+    x: usize,
+    y: usize,
+    under: Vec<(usize, Vec<Cell>)>,
+    style: Style,
+    border: Style,
+    spans: Vec<(usize, usize, usize, Style)>,
+}
+
+// the floats open over the screen. drawing them keeps what they
+// covered, so closing one puts back exactly what was under it
+// without drawing the rest of the screen again
+#[derive(Debug,Default)]
+pub struct Floats {
+    // lowest z first, floats with the same z in the order they
+    // were opened
+    floats: Vec<(FloatId, Float)>,
+    next_id: FloatId,
+    // lowest first, like floats
+    placed: Vec<Placed>,
+}
+
+impl Floats {
+    pub fn open(&mut self, float: Float) -> FloatId {
+        self.next_id += 1;
+        let i = self.floats.iter()
+            .position(|(_, f)| f.z > float.z)
+            .unwrap_or(self.floats.len());
+        self.floats.insert(i, (self.next_id, float));
+        self.next_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.floats.is_empty()
+    }
+
+    pub fn get(&self, id: FloatId) -> Option<&Float> {
+        self.floats.iter().find(|(i, _)| *i == id).map(|(_, f)| f)
+    }
+
+    pub fn get_mut(&mut self, id: FloatId) -> Option<&mut Float> {
+        self.floats.iter_mut().find(|(i, _)| *i == id).map(|(_, f)| f)
+    }
+
+    // the float drawn over the others
+    pub fn top_mut(&mut self) -> Option<&mut Float> {
+        self.floats.last_mut().map(|(_, f)| f)
+    }
+
+    // closes a float, it's gone from the screen the next time
+    // everything is drawn
+    pub fn remove(&mut self, id: FloatId) -> Option<Float> {
+        let i = self.floats.iter().position(|(i, _)| *i == id)?;
+        Some(self.floats.remove(i).1)
+    }
+
+    // closes a float and takes it off grid, which has to hold
+    // the last render. what was under it is put back and the
+    // floats left are drawn again
+    pub fn close(&mut self, id: FloatId, grid: &mut Grid) -> Option<Float> {
+        let ret = self.remove(id)?;
+
+        // from the top down, each float's rows had the ones
+        // under it drawn already
+        for p in self.placed.iter().rev() {
+            for (y, cells) in &p.under {
+                grid.set_row(*y, cells);
+            }
+        }
+
+        for p in mem::take(&mut self.placed) {
+            if let Some(f) = self.get(p.id) {
+                let placed = draw(grid, p.id, f, (p.x, p.y), p.style, p.border, p.spans);
+                self.placed.push(placed);
+            }
+        }
+        Some(ret)
+    }
+
+    // closes every float, see close
+    pub fn close_all(&mut self, grid: &mut Grid) {
+        while let Some(&(id, _)) = self.floats.last() {
+            self.close(id, grid);
+        }
+    }
+
+    // draws the floats over grid, lowest first. buffer anchors
+    // are turned into screen cells by resolve
+    pub fn render<F>(&mut self, grid: &mut Grid, theme: &Theme, resolve: F)
+        where F: Fn(usize, usize) -> Option<(usize, usize)> {
+
+        self.placed.clear();
+        let screen = (grid.width(), grid.height());
+
+        for (id, f) in &self.floats {
+            let at = match f.anchor {
+                Anchor::Screen(x, y) => Some((x, y)),
+                Anchor::Buffer(idx, col) => resolve(idx, col),
+            };
+            let at = match at {
+                Some(at) => f.place(at, screen),
+                None => continue,
+            };
+
+            let style = theme.style(&f.highlight);
+            let border = theme.style("FloatBorder").on(style);
+            let spans = f.spans.iter()
+                .map(|(line, a, b, group)| (*line, *a, *b, theme.style(group).on(style)))
+                .collect();
+            self.placed.push(draw(grid, *id, f, at, style, border, spans));
+        }
+    }
+}
+
+// draws f with its top left corner at x, y, cut off by the
+// edges of grid
+fn draw(grid: &mut Grid, id: FloatId, f: &Float, (x, y): (usize, usize),
+        style: Style, border: Style, spans: Vec<(usize, usize, usize, Style)>) -> Placed {
+
+    let (_, h) = f.outer();
+    let under = (y..(y + h).min(grid.height()))
+        .map(|row| (row, grid.row(row).to_vec()))
+        .collect();
+
+    let b = f.border as usize;
+    if f.border {
+        let edge = "─".repeat(f.width);
+        grid.write(x, y, &format!("┌{}┐", edge), border);
+        grid.write(x, y + h - 1, &format!("└{}┘", edge), border);
+    }
+    for i in 0..f.height {
+        let line = f.lines.get(f.scroll + i).map_or("", String::as_str);
+        if f.border {
+            grid.write(x, y + b + i, "│", border);
+            grid.write(x + b + f.width, y + b + i, "│", border);
+        }
+        grid.write(x + b, y + b + i, &fit(line, f.width), style);
+
+        for &(_, start, end, st) in spans.iter().filter(|s| s.0 == f.scroll + i) {
+            let end = end.min(f.width);
+            if start < end {
+                grid.set_style(x + b + start, y + b + i, end - start, st);
+            }
+        }
+    }
+
+    Placed{
+        id,
+        x,
+        y,
+        under,
+        style,
+        border,
+        spans,
+    }
+}
+
+// s cut or padded to exactly w columns
+pub(crate) fn fit(s: &str, w: usize) -> String {
+    let mut ret = String::new();
+    let mut cols = 0;
+    for g in s.graphemes(true) {
+        let gw = g.width();
+        if cols + gw > w {
+            break;
+        }
+        ret.push_str(g);
+        cols += gw;
+    }
+    ret.extend(std::iter::repeat_n(' ', w - cols));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(g: &Grid) -> Vec<String> {
+        (0..g.height())
+            .map(|y| g.row(y).iter().map(|c| c.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn placed_in_z_order_and_clipped() {
+        let mut grid = Grid::new(10, 4);
+        let mut floats = Floats::default();
+
+        let mut low = Float::new(Anchor::Screen(0, 0), "aaaa\nbb");
+        low.z = 1;
+        let mut high = Float::new(Anchor::Buffer(0, 0), "x");
+        high.z = 2;
+        let high = floats.open(high);
+        floats.open(low);
+
+        // the buffer position is drawn at 3, 0 so the float goes
+        // under it
+        floats.render(&mut grid, &Theme::default(), |_, _| Some((3, 0)));
+        assert_eq!(text(&grid), vec![
+            "┌────┐    ",
+            "│aa┌─┐    ",
+            "│bb│x│    ",
+            "└──└─┘    ",
+        ]);
+
+        // off screen it's hidden, too wide it's cut off
+        floats.get_mut(high).unwrap().anchor = Anchor::Buffer(5, 0);
+        let mut grid = Grid::new(10, 4);
+        let mut wide = Float::new(Anchor::Screen(2, 2), "0123456789ab");
+        wide.z = 3;
+        floats.open(wide);
+        floats.render(&mut grid, &Theme::default(), |_, _| None);
+        assert_eq!(text(&grid), vec![
+            "┌────┐    ",
+            "┌─────────",
+            "│012345678",
+            "└─────────",
+        ]);
+    }
+
+    #[test]
+    fn closing_restores_what_was_under() {
+        let mut grid = Grid::new(8, 4);
+        grid.write(0, 0, "界界界界", Style::default());
+        grid.write(0, 2, "abcdefgh", Style::default().bold());
+        let before = grid.clone();
+
+        let mut floats = Floats::default();
+        let a = floats.open(Float::new(Anchor::Screen(1, 0), "one"));
+        let b = floats.open(Float::new(Anchor::Screen(3, 1), "two"));
+        floats.render(&mut grid, &Theme::default(), |_, _| None);
+
+        // the one below goes, the one above stays as it was
+        floats.close(a, &mut grid);
+        assert_eq!(text(&grid), vec![
+            "界界界界",
+            "   ┌───┐",
+            "abc│two│",
+            "   └───┘",
+        ]);
+
+        floats.close(b, &mut grid);
+        for y in 0..grid.height() {
+            assert_eq!(grid.row(y), before.row(y));
+        }
+        assert!(floats.close(b, &mut grid).is_none());
+    }
+
+    #[test]
+    fn scrolls_within_the_text() {
+        let text: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut f = Float::new(Anchor::Screen(0, 0), &text.join("\n"));
+        assert_eq!((f.width, f.height), (2, MAX_HEIGHT));
+
+        f.scroll(5);
+        assert_eq!(f.scroll_pos(), 5);
+        f.scroll(100);
+        assert_eq!(f.scroll_pos(), 20 - MAX_HEIGHT);
+        f.scroll(-100);
+        assert_eq!(f.scroll_pos(), 0);
+    }
+}
@@ -0,0 +1,267 @@
+// globs for file names and paths, as .editorconfig has them
+//
+//     ?        any character but `/`
+//     *        any characters but `/`
+//     **       any characters
+//     [a-z]    one of the characters, [!a-z] or [^a-z] for
+//              one which isn't
+//     {a,b}    one of the alternatives
+//     {1..10}  a number in the range
+//
+// a backslash makes the next character literal
+
+// whether the whole of path matches glob, `**/` at the start
+// also matches no directories at all
+pub fn matches(glob: &str, path: &str) -> bool {
+    let toks = match parse_glob(&glob.chars().collect::<Vec<_>>()) {
+        Some(toks) => toks,
+        None => return false,
+    };
+
+    let path: Vec<char> = path.chars().collect();
+    match_toks(&toks, &path) || match toks.as_slice() {
+        [Tok::DoubleStar, Tok::Char('/'), rest @ ..] => match_toks(rest, &path),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Tok {
+    Char(char),
+    // ?
+    Any,
+    // *, doesn't match `/`
+    Star,
+    // **
+    DoubleStar,
+    // [...] and [!...]
+    Class(bool, Vec<(char, char)>),
+    // {a,b,c}
+    Alt(Vec<Vec<Tok>>),
+    // {n..m}
+    Range(i64, i64),
+}
+
+fn parse_glob(g: &[char]) -> Option<Vec<Tok>> {
+    let mut ret = Vec::new();
+    let mut i = 0;
+
+    while i < g.len() {
+        match g[i] {
+            '\\' if i + 1 < g.len() => {
+                ret.push(Tok::Char(g[i+1]));
+                i += 2;
+            },
+            '?' => {
+                ret.push(Tok::Any);
+                i += 1;
+            },
+            '*' if g.get(i+1) == Some(&'*') => {
+                ret.push(Tok::DoubleStar);
+                i += 2;
+            },
+            '*' => {
+                ret.push(Tok::Star);
+                i += 1;
+            },
+            '[' => match g[i..].iter().position(|c| *c == ']') {
+                Some(len) => {
+                    ret.push(parse_class(&g[i+1..i+len]));
+                    i += len + 1;
+                },
+                None => {
+                    ret.push(Tok::Char('['));
+                    i += 1;
+                },
+            },
+            '{' => match matching_brace(&g[i..]) {
+                Some(len) => {
+                    let inner = &g[i+1..i+len];
+                    match parse_brace(inner)? {
+                        Some(tok) => ret.push(tok),
+                        None => {
+                            // a single item in braces is literal
+                            ret.push(Tok::Char('{'));
+                            ret.extend(parse_glob(inner)?);
+                            ret.push(Tok::Char('}'));
+                        },
+                    }
+                    i += len + 1;
+                },
+                None => {
+                    ret.push(Tok::Char('{'));
+                    i += 1;
+                },
+            },
+            c => {
+                ret.push(Tok::Char(c));
+                i += 1;
+            },
+        }
+    }
+
+    Some(ret)
+}
+
+fn parse_class(set: &[char]) -> Tok {
+    let (negate, set) = match set.first() {
+        Some('!') | Some('^') => (true, &set[1..]),
+        _ => (false, set),
+    };
+
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i+1] == '-' {
+            ranges.push((set[i], set[i+2]));
+            i += 3;
+        } else {
+            ranges.push((set[i], set[i]));
+            i += 1;
+        }
+    }
+
+    Tok::Class(negate, ranges)
+}
+
+// the index of the brace closing the one at g[0]
+fn matching_brace(g: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < g.len() {
+        match g[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+
+    None
+}
+
+// parses the inside of braces, None if it has a single item
+fn parse_brace(inner: &[char]) -> Option<Option<Tok>> {
+    let s: String = inner.iter().collect();
+    if let Some(idx) = s.find("..") {
+        if let (Ok(a), Ok(b)) = (s[..idx].parse(), s[idx+2..].parse()) {
+            return Some(Some(Tok::Range(a, b)));
+        }
+    }
+
+    let mut alts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < inner.len() {
+        match inner[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alts.push(parse_glob(&inner[start..i])?);
+                start = i + 1;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+
+    if alts.is_empty() {
+        return Some(None);
+    }
+
+    alts.push(parse_glob(&inner[start..])?);
+    Some(Some(Tok::Alt(alts)))
+}
+
+fn match_toks(toks: &[Tok], s: &[char]) -> bool {
+    let (tok, rest) = match toks.split_first() {
+        Some(t) => t,
+        None => return s.is_empty(),
+    };
+
+    match tok {
+        Tok::Char(c) => {
+            s.first() == Some(c) && match_toks(rest, &s[1..])
+        },
+        Tok::Any => {
+            matches!(s.first(), Some(c) if *c != '/') && match_toks(rest, &s[1..])
+        },
+        Tok::Star => {
+            let end = s.iter().position(|c| *c == '/').unwrap_or(s.len());
+            (0..=end).any(|i| match_toks(rest, &s[i..]))
+        },
+        Tok::DoubleStar => {
+            (0..=s.len()).any(|i| match_toks(rest, &s[i..]))
+        },
+        Tok::Class(negate, ranges) => {
+            let c = match s.first() {
+                Some(c) if *c != '/' => *c,
+                _ => return false,
+            };
+            let found = ranges.iter().any(|(a, b)| *a <= c && c <= *b);
+
+            found != *negate && match_toks(rest, &s[1..])
+        },
+        Tok::Alt(alts) => {
+            alts.iter().any(|alt| {
+                let mut toks = alt.clone();
+                toks.extend_from_slice(rest);
+                match_toks(&toks, s)
+            })
+        },
+        Tok::Range(a, b) => {
+            let (lo, hi) = (*a.min(b), *a.max(b));
+            (1..=s.len()).any(|i| {
+                let n: String = s[..i].iter().collect();
+                let valid = n.trim_start_matches('-').chars()
+                    .all(|c| c.is_ascii_digit());
+                match n.parse::<i64>() {
+                    Ok(n) if valid => lo <= n && n <= hi && match_toks(rest, &s[i..]),
+                    _ => false,
+                }
+            })
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stars_and_classes() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "src/main.rs"));
+        assert!(matches("**/*.rs", "src/buffer/main.rs"));
+        assert!(matches("**/*.rs", "main.rs"));
+        assert!(matches("src/**", "src/a/b"));
+        assert!(matches("?.[ch]", "a.h"));
+        assert!(!matches("?.[!ch]", "a.h"));
+        assert!(matches("[^a-c]x", "dx"));
+        assert!(matches("\\*.rs", "*.rs"));
+        assert!(!matches("\\*.rs", "a.rs"));
+    }
+
+    #[test]
+    fn braces() {
+        assert!(matches("*.{js,ts}", "a.ts"));
+        assert!(!matches("*.{js,ts}", "a.rs"));
+        assert!(matches("{src,lib/{a,b}}/x", "lib/b/x"));
+        assert!(matches("{single}", "{single}"));
+
+        assert!(matches("v{1..10}", "v7"));
+        assert!(matches("v{10..1}", "v10"));
+        assert!(!matches("v{1..10}", "v11"));
+        assert!(matches("v{-3..3}.txt", "v-2.txt"));
+        assert!(!matches("v{1..10}", "v+3"));
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use regex::Regex;
+
+use crate::{
+    Error,
+    Result,
+    Style,
+    Theme,
+    config,
+};
+
+
+// a grammar read from a file of rules, one per line. a line
+// is highlighted from left to right in states, each with its
+// own rules. at every position the rule which matches first
+// wins, ties go to the rule written first
+//
+//     " text no rule matches gets the state's group
+//     state main
+//     match Comment //.*
+//     match Keyword \b(fn|let|mut)\b
+//     push string String "
+//     state string String
+//     match SpecialChar \\.
+//     pop String "
+//
+// the first state is where a file starts. push enters a
+// state and pop goes back to the one before, states carry
+// over to the next line. a group of `-` is not highlighted
+#[derive(Debug)]
+pub struct Grammar {
+    states: Vec<State>,
+    // the highlight groups rules refer to, by index
+    groups: Vec<String>,
+}
+
+#[derive(Debug)]
+struct State {
+    name: String,
+    group: Option<usize>,
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    group: Option<usize>,
+    action: Action,
+}
+
+#[derive(Debug,PartialEq,Eq)]
+enum Action {
+    None,
+    Push(usize),
+    Pop,
+}
+
+// a highlighted part of a line, in bytes
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub group: usize,
+}
+
+impl Grammar {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Grammar> {
+        let path = path.as_ref();
+        let err = |line, msg| Error::Config{
+            path: path.to_path_buf(),
+            line,
+            msg,
+        };
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| err(0, e.to_string()))?;
+        Grammar::parse(&text).map_err(|(line, msg)| err(line, msg))
+    }
+
+    // errors come with their line number
+    pub fn parse(text: &str) -> std::result::Result<Grammar, (usize, String)> {
+        let mut ret = Grammar{
+            states: Vec::new(),
+            groups: Vec::new(),
+        };
+        // pushed states are looked up once every state is known
+        let mut pushes = Vec::new();
+
+        for (n, line) in text.lines().enumerate() {
+            let err = |msg: String| (n + 1, msg);
+            let (cmd, arg) = match config::parse_command(line) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            if cmd == "state" {
+                let (name, group) = split_word(arg);
+                if name.is_empty() {
+                    return Err(err("state requires a name".into()));
+                }
+                if ret.states.iter().any(|s| s.name == name) {
+                    return Err(err(format!("state defined twice: {}", name)));
+                }
+                let group = if group.is_empty() { None } else { ret.group(group) };
+                ret.states.push(State{
+                    name: name.into(),
+                    group,
+                    rules: Vec::new(),
+                });
+                continue;
+            }
+
+            let (target, rest) = match cmd {
+                "match" | "pop" => (None, arg),
+                "push" => {
+                    let (to, rest) = split_word(arg);
+                    (Some(to), rest)
+                },
+                _ => return Err(err(format!("unknown command: {}", cmd))),
+            };
+            let (group, pattern) = split_word(rest);
+            if pattern.is_empty() {
+                return Err(err(format!("{} requires a group and a pattern", cmd)));
+            }
+
+            let regex = Regex::new(pattern).map_err(|e| err(e.to_string()))?;
+            let group = ret.group(group);
+            let state = ret.states.last_mut()
+                .ok_or_else(|| err(format!("{} outside of a state", cmd)))?;
+
+            let action = match target {
+                Some(name) => {
+                    pushes.push((n + 1, name.to_string()));
+                    Action::Push(0)
+                },
+                None if cmd == "pop" => Action::Pop,
+                None => Action::None,
+            };
+            state.rules.push(Rule{
+                regex,
+                group,
+                action,
+            });
+        }
+
+        if ret.states.is_empty() {
+            return Err((0, "no states".into()));
+        }
+
+        let mut pushes = pushes.into_iter();
+        let names: Vec<String> = ret.states.iter().map(|s| s.name.clone()).collect();
+        for rule in ret.states.iter_mut().flat_map(|s| s.rules.iter_mut()) {
+            if let Action::Push(ref mut to) = rule.action {
+                let (n, name) = pushes.next().unwrap();
+                *to = names.iter().position(|s| *s == name)
+                    .ok_or_else(|| (n, format!("unknown state: {}", name)))?;
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // the index of a group, adding it if it's new
+    fn group(&mut self, name: &str) -> Option<usize> {
+        if name == "-" {
+            return None;
+        }
+
+        match self.groups.iter().position(|g| g == name) {
+            Some(idx) => Some(idx),
+            None => {
+                self.groups.push(name.into());
+                Some(self.groups.len() - 1)
+            },
+        }
+    }
+
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    // the states a file starts in
+    pub fn start(&self) -> Vec<usize> {
+        vec![0]
+    }
+
+    // the spans of a line which starts in the states on stack,
+    // leaving there the states at the end of the line
+    pub fn highlight_line(&self, line: &str, stack: &mut Vec<usize>) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut pos = 0;
+        // an empty match is only taken once at a position, so
+        // a rule like `pop - $` can't loop
+        let mut empty_at = None;
+
+        loop {
+            let state = &self.states[*stack.last().unwrap()];
+            let found = state.rules.iter()
+                .filter_map(|rule| rule.regex.find_at(line, pos).map(|m| (m, rule)))
+                .filter(|(m, _)| !(m.is_empty() && empty_at == Some(m.start())))
+                .min_by_key(|(m, _)| m.start());
+
+            let (m, rule) = match found {
+                Some(found) => found,
+                None => break,
+            };
+
+            push_span(&mut spans, pos, m.start(), state.group);
+            push_span(&mut spans, m.start(), m.end(), rule.group);
+            if m.is_empty() {
+                empty_at = Some(m.start());
+            }
+            pos = m.end();
+
+            match rule.action {
+                Action::Push(to) => stack.push(to),
+                Action::Pop if stack.len() > 1 => {
+                    stack.pop();
+                },
+                _ => {},
+            }
+        }
+
+        let state = &self.states[*stack.last().unwrap()];
+        push_span(&mut spans, pos, line.len(), state.group);
+        spans
+    }
+}
+
+// the first word and what follows it
+fn split_word(s: &str) -> (&str, &str) {
+    match s.find(char::is_whitespace) {
+        Some(idx) => (&s[..idx], s[idx..].trim_start()),
+        None => (s, ""),
+    }
+}
+
+// adds a span, joining it to the last one when they touch and
+// have the same group
+fn push_span(spans: &mut Vec<Span>, start: usize, end: usize, group: Option<usize>) {
+    let group = match group {
+        Some(g) if start < end => g,
+        _ => return,
+    };
+
+    match spans.last_mut() {
+        Some(last) if last.end == start && last.group == group => last.end = end,
+        _ => spans.push(Span{
+            start,
+            end,
+            group,
+        }),
+    }
+}
+
+#[derive(Debug,Clone)]
+struct Cached {
+    // the states at the end of the line
+    end: Vec<usize>,
+    spans: Vec<Span>,
+    dirty: bool,
+}
+
+impl Cached {
+    fn dirty() -> Cached {
+        Cached{
+            end: Vec::new(),
+            spans: Vec::new(),
+            dirty: true,
+        }
+    }
+}
+
+// highlights the lines of a buffer with a grammar. the
+// states at the end of each line are kept, so after an edit
+// lines are only highlighted again from the edited one until
+// they end in the same states as before
+#[derive(Debug)]
+pub struct Highlighter {
+    grammar: Rc<Grammar>,
+    // by group
+    styles: Vec<Style>,
+    // lines past the end are dirty
+    lines: Vec<Cached>,
+    // every line before this one is clean
+    first_dirty: usize,
+}
+
+impl Highlighter {
+    pub fn new(grammar: Rc<Grammar>, theme: &Theme) -> Highlighter {
+        let mut ret = Highlighter{
+            grammar,
+            styles: Vec::new(),
+            lines: Vec::new(),
+            first_dirty: 0,
+        };
+        ret.set_theme(theme);
+        ret
+    }
+
+    // takes the style of each group from theme
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.styles = self.grammar.groups().iter()
+            .map(|group| theme.style(group))
+            .collect();
+    }
+
+    pub fn style(&self, span: &Span) -> Style {
+        self.styles[span.group]
+    }
+
+    // the line at idx was changed
+    pub fn edited(&mut self, idx: usize) {
+        if let Some(line) = self.lines.get_mut(idx) {
+            line.dirty = true;
+        }
+        self.first_dirty = self.first_dirty.min(idx);
+    }
+
+    // n lines were inserted before the line at idx
+    pub fn inserted(&mut self, idx: usize, n: usize) {
+        if idx <= self.lines.len() {
+            self.lines.splice(idx..idx, std::iter::repeat_n(Cached::dirty(), n));
+        }
+        self.first_dirty = self.first_dirty.min(idx);
+    }
+
+    // n lines were removed starting at idx
+    pub fn removed(&mut self, idx: usize, n: usize) {
+        if idx < self.lines.len() {
+            let end = (idx + n).min(self.lines.len());
+            self.lines.drain(idx..end);
+        }
+        if let Some(line) = self.lines.get_mut(idx) {
+            line.dirty = true;
+        }
+        self.first_dirty = self.first_dirty.min(idx);
+    }
+
+    // the first line which has to be highlighted again
+    pub fn first_dirty(&self) -> usize {
+        self.first_dirty
+    }
+
+    // highlights the dirty lines up to the one at last, lines
+    // is the text of every line from the first dirty one
+    pub fn update<'a, I>(&mut self, lines: I, last: usize)
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let from = self.first_dirty;
+        let mut stack = match from {
+            0 => self.grammar.start(),
+            n => self.lines[n - 1].end.clone(),
+        };
+
+        let mut end = from;
+        for (idx, s) in (from..=last).zip(lines) {
+            end = idx + 1;
+            if idx == self.lines.len() {
+                self.lines.push(Cached::dirty());
+            }
+
+            let line = &mut self.lines[idx];
+            if !line.dirty {
+                stack.clone_from(&line.end);
+                continue;
+            }
+
+            line.spans = self.grammar.highlight_line(s, &mut stack);
+            line.dirty = false;
+            if line.end != stack {
+                line.end.clone_from(&stack);
+                if let Some(next) = self.lines.get_mut(idx + 1) {
+                    next.dirty = true;
+                }
+            }
+        }
+
+        self.first_dirty = self.first_dirty.max(end);
+    }
+
+    // the spans of a highlighted line
+    pub fn spans(&self, idx: usize) -> &[Span] {
+        match self.lines.get(idx) {
+            Some(line) if !line.dirty && idx < self.first_dirty => &line.spans,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = r#"
+        state main
+        match Keyword \b(fn|let)\b
+        push comment Comment /\*
+        push string String "
+        state comment Comment
+        pop Comment \*/
+        state string String
+        match String.escape \\.
+        pop String "
+    "#;
+
+    fn spans<'a>(g: &'a Grammar, line: &'a str, stack: &mut Vec<usize>) -> Vec<(&'a str, &'a str)> {
+        g.highlight_line(line, stack).iter()
+            .map(|s| (&line[s.start..s.end], g.groups()[s.group].as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn states_carry_over_lines() {
+        let g = Grammar::parse(GRAMMAR).unwrap();
+        let mut stack = g.start();
+
+        assert_eq!(spans(&g, r#"let s = "a\"b"; /* x"#, &mut stack), vec![
+            ("let", "Keyword"),
+            ("\"a", "String"),
+            ("\\\"", "String.escape"),
+            ("b\"", "String"),
+            ("/* x", "Comment"),
+        ]);
+        assert_eq!(stack, vec![0, 1]);
+
+        assert_eq!(spans(&g, "fn */ fn", &mut stack), vec![
+            ("fn */", "Comment"),
+            ("fn", "Keyword"),
+        ]);
+        assert_eq!(stack, vec![0]);
+    }
+
+    #[test]
+    fn reports_bad_rules() {
+        assert_eq!(Grammar::parse("match A a").unwrap_err().0, 1);
+        assert_eq!(Grammar::parse("state a\npush b B x").unwrap_err(),
+            (2, "unknown state: b".to_string()));
+        assert_eq!(Grammar::parse("state a\nmatch A (").unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn edits_highlight_until_states_converge() {
+        let g = Rc::new(Grammar::parse(GRAMMAR).unwrap());
+        let mut hl = Highlighter::new(g, &Theme::default());
+        let mut lines = ["a", "b", "c", "d"];
+
+        hl.update(lines.iter().copied(), 3);
+        assert!(hl.spans(2).is_empty());
+
+        // opening a comment changes the lines after it
+        lines[1] = "/* b";
+        hl.edited(1);
+        hl.update(lines[1..].iter().copied(), 2);
+        assert_eq!(hl.spans(2), &[Span{ start: 0, end: 1, group: 1 }]);
+        // the rest waits until it's shown
+        assert!(hl.spans(3).is_empty());
+        assert_eq!(hl.first_dirty(), 3);
+
+        lines[2] = "*/ c";
+        hl.edited(2);
+        hl.update(lines[2..].iter().copied(), 3);
+        assert!(hl.spans(3).is_empty());
+        assert_eq!(hl.first_dirty(), 4);
+    }
+}
@@ -0,0 +1,599 @@
+use std::{
+    fmt,
+    ops::BitOr,
+};
+
+
+// modifier keys held with a key or mouse event, the bits are
+// the ones xterm and kitty use
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash,Default)]
+pub struct Mods(u8);
+
+impl Mods {
+    pub const NONE: Mods = Mods(0);
+    pub const SHIFT: Mods = Mods(1);
+    pub const ALT: Mods = Mods(2);
+    pub const CTRL: Mods = Mods(4);
+    pub const SUPER: Mods = Mods(8);
+
+    pub fn contains(&self, m: Mods) -> bool {
+        self.0 & m.0 == m.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn without(self, m: Mods) -> Mods {
+        Mods(self.0 & !m.0)
+    }
+
+    // from the `1 + bits` parameter in escape sequences
+    fn from_param(n: u32) -> Mods {
+        Mods((n.saturating_sub(1) & 0xf) as u8)
+    }
+}
+
+impl BitOr for Mods {
+    type Output = Mods;
+
+    fn bitor(self, m: Mods) -> Mods {
+        Mods(self.0 | m.0)
+    }
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash)]
+pub enum KeyCode {
+    Char(char),
+    F(u8),
+    Esc,
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub mods: Mods,
+}
+
+impl Key {
+    // shift with a letter is written as the upper case letter
+    // so `<S-a>` and `A` are the same key
+    pub fn new(code: KeyCode, mods: Mods) -> Key {
+        match code {
+            KeyCode::Char(c) if mods.contains(Mods::SHIFT) && c.is_lowercase() => {
+                let c = c.to_uppercase().next().unwrap_or(c);
+                Key{
+                    code: KeyCode::Char(c),
+                    mods: mods.without(Mods::SHIFT),
+                }
+            },
+            _ => Key{
+                code,
+                mods,
+            },
+        }
+    }
+
+    pub fn char(c: char) -> Key {
+        Key::new(KeyCode::Char(c), Mods::NONE)
+    }
+
+    pub fn ctrl(c: char) -> Key {
+        Key::new(KeyCode::Char(c), Mods::CTRL)
+    }
+
+    pub fn alt(c: char) -> Key {
+        Key::new(KeyCode::Char(c), Mods::ALT)
+    }
+
+    // the character the key types, if any
+    pub fn text(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if self.mods.without(Mods::SHIFT).is_empty() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyCode> for Key {
+    fn from(code: KeyCode) -> Key {
+        Key::new(code, Mods::NONE)
+    }
+}
+
+// written in the notation keymap::parse_keys reads
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(c) if self.mods.is_empty() => match c {
+                '<' => "lt".to_string(),
+                ' ' => "space".to_string(),
+                c => return write!(f, "{}", c),
+            },
+            KeyCode::Char(' ') => "space".into(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            KeyCode::Esc => "Esc".into(),
+            KeyCode::Enter => "CR".into(),
+            KeyCode::Tab => "Tab".into(),
+            KeyCode::Backspace => "BS".into(),
+            KeyCode::Delete => "Del".into(),
+            KeyCode::Insert => "Insert".into(),
+            KeyCode::Left => "Left".into(),
+            KeyCode::Right => "Right".into(),
+            KeyCode::Up => "Up".into(),
+            KeyCode::Down => "Down".into(),
+            KeyCode::Home => "Home".into(),
+            KeyCode::End => "End".into(),
+            KeyCode::PageUp => "PageUp".into(),
+            KeyCode::PageDown => "PageDown".into(),
+        };
+
+        write!(f, "<")?;
+        let mods = [
+            (Mods::CTRL, "C-"),
+            (Mods::ALT, "A-"),
+            (Mods::SUPER, "D-"),
+            (Mods::SHIFT, "S-"),
+        ];
+        for (m, s) in mods.iter() {
+            if self.mods.contains(*m) {
+                write!(f, "{}", s)?;
+            }
+        }
+        write!(f, "{}>", name)
+    }
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum MouseKind {
+    Press(MouseButton),
+    // moved with the button held
+    Drag(MouseButton),
+    Release(MouseButton),
+    ScrollUp,
+    ScrollDown,
+}
+
+// a mouse event at a 0-based screen position
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub struct Mouse {
+    pub kind: MouseKind,
+    pub x: usize,
+    pub y: usize,
+    pub mods: Mods,
+}
+
+#[derive(Debug,PartialEq,Eq,Clone)]
+pub enum Input {
+    Key(Key),
+    Mouse(Mouse),
+    // text from a bracketed paste, all of it at once
+    Paste(String),
+}
+
+// what the terminal is asked to send, sent on start up. mouse
+// reports with buttons and drags in sgr format, bracketed
+// paste, and a query for kitty's keyboard protocol which
+// terminals that don't know it don't answer
+pub const ENABLE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h\x1b[?2004h\x1b[?u";
+// kitty's disambiguated keys, sent once the terminal answers
+// the query
+pub const ENABLE_KITTY: &str = "\x1b[>1u";
+// undoes ENABLE and ENABLE_KITTY, popping the keyboard flags
+// does nothing if they weren't pushed
+pub const DISABLE: &str = "\x1b[<u\x1b[?2004l\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+// the result of decoding the start of the input
+enum Decoded {
+    // the input and the number of bytes it took
+    Input(Input, usize),
+    // a sequence the editor doesn't use
+    Skip(usize),
+    // the answer to the query for kitty's keyboard flags
+    KittyFlags(usize),
+    // the sequence isn't complete yet
+    Incomplete,
+}
+
+// turns the bytes read from the terminal into inputs
+#[derive(Debug,Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    // the terminal answered the kitty query
+    kitty: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    // decodes everything complete in b and what was left over
+    // from before. a lone escape at the end is kept since the
+    // rest of a sequence may not have been read yet
+    pub fn feed(&mut self, b: &[u8]) -> Vec<Input> {
+        self.buf.extend_from_slice(b);
+
+        let mut ret = Vec::new();
+        let mut at = 0;
+        while at < self.buf.len() {
+            match decode(&self.buf[at..]) {
+                Decoded::Input(i, n) => {
+                    ret.push(i);
+                    at += n;
+                },
+                Decoded::Skip(n) => at += n,
+                Decoded::KittyFlags(n) => {
+                    self.kitty = true;
+                    at += n;
+                },
+                Decoded::Incomplete => break,
+            }
+        }
+
+        self.buf.drain(..at);
+        ret
+    }
+
+    // whether a lone escape, or one and `O`, is waiting for
+    // what follows it
+    pub fn has_escape(&self) -> bool {
+        self.buf == b"\x1b" || self.buf == b"\x1bO"
+    }
+
+    // nothing followed the escape in time so it's the escape
+    // key, or alt-O
+    pub fn flush_escape(&mut self) -> Option<Input> {
+        if !self.has_escape() {
+            return None;
+        }
+        let alt = self.buf.len() == 2;
+        self.buf.clear();
+        match alt {
+            true => Some(Input::Key(Key::new(KeyCode::Char('O'), Mods::ALT))),
+            false => Some(Input::Key(KeyCode::Esc.into())),
+        }
+    }
+
+    // true once after the terminal answered the kitty query
+    pub fn take_kitty(&mut self) -> bool {
+        std::mem::take(&mut self.kitty)
+    }
+}
+
+fn decode(b: &[u8]) -> Decoded {
+    let key = |code, mods, n| Decoded::Input(Input::Key(Key::new(code, mods)), n);
+
+    match b[0] {
+        0x1b => decode_escape(b),
+        b'\r' => key(KeyCode::Enter, Mods::NONE, 1),
+        b'\t' => key(KeyCode::Tab, Mods::NONE, 1),
+        0x7f | 0x08 => key(KeyCode::Backspace, Mods::NONE, 1),
+        0x00 => key(KeyCode::Char(' '), Mods::CTRL, 1),
+        c @ 0x01..=0x1a => key(KeyCode::Char((c + 0x60) as char), Mods::CTRL, 1),
+        c @ 0x1c..=0x1f => key(KeyCode::Char((c + 0x40) as char), Mods::CTRL, 1),
+        _ => decode_char(b),
+    }
+}
+
+fn decode_char(b: &[u8]) -> Decoded {
+    let n = match b[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Decoded::Skip(1),
+    };
+    if b.len() < n {
+        return Decoded::Incomplete;
+    }
+
+    match std::str::from_utf8(&b[..n]).ok().and_then(|s| s.chars().next()) {
+        Some(c) => Decoded::Input(Input::Key(Key::char(c)), n),
+        None => Decoded::Skip(1),
+    }
+}
+
+fn decode_escape(b: &[u8]) -> Decoded {
+    match b.get(1) {
+        None => Decoded::Incomplete,
+        Some(b'[') => decode_csi(b),
+        Some(b'O') => match b.get(2) {
+            None => Decoded::Incomplete,
+            Some(c) => match final_key(*c) {
+                Some(code) => Decoded::Input(Input::Key(code.into()), 3),
+                // not ss3, alt-O and then whatever follows
+                None => Decoded::Input(Input::Key(Key::new(KeyCode::Char('O'), Mods::ALT)), 2),
+            },
+        },
+        Some(0x1b) => Decoded::Input(Input::Key(KeyCode::Esc.into()), 1),
+        // escape before a key is how alt is sent
+        Some(_) => match decode(&b[1..]) {
+            Decoded::Input(Input::Key(k), n) => {
+                Decoded::Input(Input::Key(Key::new(k.code, k.mods | Mods::ALT)), n + 1)
+            },
+            Decoded::Incomplete => Decoded::Incomplete,
+            _ => Decoded::Skip(1),
+        },
+    }
+}
+
+// the keys of sequences ending in a letter, like `\x1b[A`
+fn final_key(c: u8) -> Option<KeyCode> {
+    let code = match c {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        b'R' => KeyCode::F(3),
+        b'S' => KeyCode::F(4),
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn decode_csi(b: &[u8]) -> Decoded {
+    if b.starts_with(PASTE_START) {
+        let rest = &b[PASTE_START.len()..];
+        return match rest.windows(PASTE_END.len()).position(|w| w == PASTE_END) {
+            Some(end) => {
+                let s = String::from_utf8_lossy(&rest[..end]).into_owned();
+                let n = PASTE_START.len() + end + PASTE_END.len();
+                Decoded::Input(Input::Paste(s), n)
+            },
+            None => Decoded::Incomplete,
+        };
+    }
+
+    // parameters, then intermediates, then the final byte
+    let end = match b[2..].iter().position(|c| (0x40..=0x7e).contains(c)) {
+        Some(i) => i + 2,
+        None => return Decoded::Incomplete,
+    };
+    let n = end + 1;
+    let params = match std::str::from_utf8(&b[2..end]) {
+        Ok(s) => s,
+        Err(_) => return Decoded::Skip(n),
+    };
+
+    if let Some(params) = params.strip_prefix('<') {
+        return match decode_mouse(params, b[end]) {
+            Some(m) => Decoded::Input(Input::Mouse(m), n),
+            None => Decoded::Skip(n),
+        };
+    }
+    if params.starts_with('?') && b[end] == b'u' {
+        return Decoded::KittyFlags(n);
+    }
+    if params.starts_with(['?', '>', '=']) {
+        return Decoded::Skip(n);
+    }
+
+    // each parameter can have sub parameters after `:`
+    let nums: Vec<Vec<u32>> = params.split(';')
+        .map(|p| p.split(':').map(|s| s.parse().unwrap_or(0)).collect())
+        .collect();
+    let num = |i: usize, j: usize, default: u32| nums.get(i)
+        .and_then(|p| p.get(j).copied())
+        .filter(|n| *n != 0)
+        .unwrap_or(default);
+    let mods = Mods::from_param(num(1, 0, 1));
+
+    let code = match b[end] {
+        b'~' => match num(0, 0, 0) {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n @ 11..=15 => KeyCode::F((n - 10) as u8),
+            n @ 17..=21 => KeyCode::F((n - 11) as u8),
+            n @ 23..=24 => KeyCode::F((n - 12) as u8),
+            _ => return Decoded::Skip(n),
+        },
+        b'Z' => return Decoded::Input(Input::Key(Key::new(KeyCode::Tab, Mods::SHIFT)), n),
+        // kitty's `code;mods:event u`, releases aren't asked for
+        // but are skipped anyway
+        b'u' => {
+            if num(1, 1, 1) == 3 {
+                return Decoded::Skip(n);
+            }
+            match num(0, 0, 0) {
+                27 => KeyCode::Esc,
+                13 => KeyCode::Enter,
+                9 => KeyCode::Tab,
+                127 => KeyCode::Backspace,
+                c => match char::from_u32(c) {
+                    Some(c) if !c.is_control() => KeyCode::Char(c),
+                    _ => return Decoded::Skip(n),
+                },
+            }
+        },
+        c => match final_key(c) {
+            Some(code) => code,
+            None => return Decoded::Skip(n),
+        },
+    };
+
+    Decoded::Input(Input::Key(Key::new(code, mods)), n)
+}
+
+// sgr mouse reports, `\x1b[<button;x;yM` or `m` on release
+fn decode_mouse(params: &str, fin: u8) -> Option<Mouse> {
+    let mut nums = params.split(';').map(|s| s.parse::<usize>().ok());
+    let (b, x, y) = (nums.next()??, nums.next()??, nums.next()??);
+
+    let mut mods = Mods::NONE;
+    if b & 4 != 0 {
+        mods = mods | Mods::SHIFT;
+    }
+    if b & 8 != 0 {
+        mods = mods | Mods::ALT;
+    }
+    if b & 16 != 0 {
+        mods = mods | Mods::CTRL;
+    }
+
+    let button = match b & 3 {
+        0 => MouseButton::Left,
+        1 => MouseButton::Middle,
+        2 => MouseButton::Right,
+        _ => return None,
+    };
+
+    let kind = if b & 64 != 0 {
+        match b & 3 {
+            0 => MouseKind::ScrollUp,
+            1 => MouseKind::ScrollDown,
+            _ => return None,
+        }
+    } else if fin == b'm' {
+        MouseKind::Release(button)
+    } else if b & 32 != 0 {
+        MouseKind::Drag(button)
+    } else {
+        MouseKind::Press(button)
+    };
+
+    Some(Mouse{
+        kind,
+        x: x.saturating_sub(1),
+        y: y.saturating_sub(1),
+        mods,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(b: &[u8]) -> Vec<Input> {
+        Decoder::new().feed(b)
+    }
+
+    #[test]
+    fn legacy_keys() {
+        assert_eq!(keys(b"a\x05\r\x7f\x1b[A\x1b[1;5C\x1bx\x1b[Z"), vec![
+            Input::Key(Key::char('a')),
+            Input::Key(Key::ctrl('e')),
+            Input::Key(KeyCode::Enter.into()),
+            Input::Key(KeyCode::Backspace.into()),
+            Input::Key(KeyCode::Up.into()),
+            Input::Key(Key::new(KeyCode::Right, Mods::CTRL)),
+            Input::Key(Key::alt('x')),
+            Input::Key(Key::new(KeyCode::Tab, Mods::SHIFT)),
+        ]);
+    }
+
+    #[test]
+    fn kitty_keys() {
+        assert_eq!(keys(b"\x1b[105;5u\x1b[27u\x1b[97;6u"), vec![
+            Input::Key(Key::ctrl('i')),
+            Input::Key(KeyCode::Esc.into()),
+            Input::Key(Key::new(KeyCode::Char('A'), Mods::CTRL)),
+        ]);
+    }
+
+    #[test]
+    fn split_reads() {
+        let mut d = Decoder::new();
+        assert_eq!(d.feed(b"\x1b[1;"), vec![]);
+        assert_eq!(d.feed(b"2D\xe7"), vec![
+            Input::Key(Key::new(KeyCode::Left, Mods::SHIFT)),
+        ]);
+        assert_eq!(d.feed(b"\x95\x8c\x1b"), vec![
+            Input::Key(Key::char('界')),
+        ]);
+        // the escape waits for the rest of the sequence
+        assert!(d.has_escape());
+        assert_eq!(d.feed(b"[B"), vec![
+            Input::Key(KeyCode::Down.into()),
+        ]);
+
+        assert_eq!(d.feed(b"\x1b"), vec![]);
+        assert_eq!(d.flush_escape(), Some(Input::Key(KeyCode::Esc.into())));
+        assert_eq!(d.flush_escape(), None);
+        assert_eq!(d.feed(b"\x1b\x1b"), vec![
+            Input::Key(KeyCode::Esc.into()),
+        ]);
+        assert!(d.has_escape());
+    }
+
+    #[test]
+    fn alt_o_before_other_keys() {
+        let mut d = Decoder::new();
+        assert_eq!(d.feed(b"\x1bOx"), vec![
+            Input::Key(Key::new(KeyCode::Char('O'), Mods::ALT)),
+            Input::Key(Key::char('x')),
+        ]);
+        assert_eq!(d.feed(b"\x1bOA"), vec![Input::Key(KeyCode::Up.into())]);
+
+        assert_eq!(d.feed(b"\x1bO"), vec![]);
+        assert!(d.has_escape());
+        assert_eq!(d.flush_escape(), Some(Input::Key(Key::new(KeyCode::Char('O'), Mods::ALT))));
+        assert_eq!(d.feed(b"x"), vec![Input::Key(Key::char('x'))]);
+    }
+
+    #[test]
+    fn kitty_query_answer() {
+        let mut d = Decoder::new();
+        assert!(!d.take_kitty());
+        assert_eq!(d.feed(b"\x1b[?0ux"), vec![Input::Key(Key::char('x'))]);
+        assert!(d.take_kitty());
+        assert!(!d.take_kitty());
+    }
+
+    #[test]
+    fn mouse_and_paste() {
+        assert_eq!(keys(b"\x1b[<0;3;2M\x1b[<32;4;2M\x1b[<65;1;1M\x1b[200~a\r\nb\x1b[201~"), vec![
+            Input::Mouse(Mouse{
+                kind: MouseKind::Press(MouseButton::Left),
+                x: 2,
+                y: 1,
+                mods: Mods::NONE,
+            }),
+            Input::Mouse(Mouse{
+                kind: MouseKind::Drag(MouseButton::Left),
+                x: 3,
+                y: 1,
+                mods: Mods::NONE,
+            }),
+            Input::Mouse(Mouse{
+                kind: MouseKind::ScrollDown,
+                x: 0,
+                y: 0,
+                mods: Mods::NONE,
+            }),
+            Input::Paste("a\r\nb".into()),
+        ]);
+    }
+}
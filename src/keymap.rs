@@ -0,0 +1,452 @@
+use std::{
+    collections::HashMap,
+};
+
+use crate::{
+    input::{
+        Key,
+        KeyCode,
+        Mods,
+    },
+};
+
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash)]
+pub enum Mode {
+    Normal,
+    Insert,
+}
+
+impl Mode {
+    pub fn from_name(s: &str) -> Option<Mode> {
+        match s {
+            "normal" | "n" => Some(Mode::Normal),
+            "insert" | "i" => Some(Mode::Insert),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Insert => "insert",
+        }
+    }
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum Action {
+    Quit,
+    NormalMode,
+    CommandMode,
+    Insert,
+    InsertFirstNonSpace,
+    Append,
+    AppendEnd,
+    OpenBelow,
+    OpenAbove,
+    FirstCol,
+    NextWord,
+    PrevWord,
+    Left,
+    Right,
+    Up,
+    Down,
+    UpRow,
+    DownRow,
+    ScrollUp,
+    ScrollDown,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    ScrollCursorTop,
+    ScrollCursorCenter,
+    ScrollCursorBottom,
+    Backspace,
+    Newline,
+    Tab,
+    NextFunction,
+    PrevFunction,
+    ExpandSelection,
+    SelectFunction,
+    SelectFunctionInner,
+    SelectClass,
+    SelectClassInner,
+    SelectArgument,
+    Hover,
+    GotoDefinition,
+    References,
+    CodeAction,
+    NextLocation,
+    PrevLocation,
+    Complete,
+    CompleteNext,
+    CompletePrev,
+    CompleteAccept,
+    CompleteCancel,
+    ExpandSnippet,
+    NextTabstop,
+    PrevTabstop,
+    Reindent,
+    Undo,
+}
+
+// names used to refer to actions in the config file
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("normal-mode", Action::NormalMode),
+    ("command-mode", Action::CommandMode),
+    ("insert", Action::Insert),
+    ("insert-first-non-space", Action::InsertFirstNonSpace),
+    ("append", Action::Append),
+    ("append-end", Action::AppendEnd),
+    ("open-below", Action::OpenBelow),
+    ("open-above", Action::OpenAbove),
+    ("first-col", Action::FirstCol),
+    ("next-word", Action::NextWord),
+    ("prev-word", Action::PrevWord),
+    ("left", Action::Left),
+    ("right", Action::Right),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("up-row", Action::UpRow),
+    ("down-row", Action::DownRow),
+    ("scroll-up", Action::ScrollUp),
+    ("scroll-down", Action::ScrollDown),
+    ("half-page-up", Action::HalfPageUp),
+    ("half-page-down", Action::HalfPageDown),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("scroll-cursor-top", Action::ScrollCursorTop),
+    ("scroll-cursor-center", Action::ScrollCursorCenter),
+    ("scroll-cursor-bottom", Action::ScrollCursorBottom),
+    ("backspace", Action::Backspace),
+    ("newline", Action::Newline),
+    ("tab", Action::Tab),
+    ("next-function", Action::NextFunction),
+    ("prev-function", Action::PrevFunction),
+    ("expand-selection", Action::ExpandSelection),
+    ("select-function", Action::SelectFunction),
+    ("select-function-inner", Action::SelectFunctionInner),
+    ("select-class", Action::SelectClass),
+    ("select-class-inner", Action::SelectClassInner),
+    ("select-argument", Action::SelectArgument),
+    ("hover", Action::Hover),
+    ("goto-definition", Action::GotoDefinition),
+    ("references", Action::References),
+    ("code-action", Action::CodeAction),
+    ("next-location", Action::NextLocation),
+    ("prev-location", Action::PrevLocation),
+    ("complete", Action::Complete),
+    ("complete-next", Action::CompleteNext),
+    ("complete-prev", Action::CompletePrev),
+    ("complete-accept", Action::CompleteAccept),
+    ("complete-cancel", Action::CompleteCancel),
+    ("expand-snippet", Action::ExpandSnippet),
+    ("next-tabstop", Action::NextTabstop),
+    ("prev-tabstop", Action::PrevTabstop),
+    ("reindent", Action::Reindent),
+    ("undo", Action::Undo),
+];
+
+impl Action {
+    pub fn from_name(s: &str) -> Option<Action> {
+        ACTION_NAMES.iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, a)| *a)
+    }
+
+    pub fn name(&self) -> &'static str {
+        ACTION_NAMES.iter()
+            .find(|(_, a)| a == self)
+            .map(|(name, _)| *name)
+            .unwrap()
+    }
+}
+
+// the result of looking up a key sequence
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum Lookup {
+    // the sequence is bound to an action
+    Action(Action),
+    // the sequence is bound to an action and is also the start
+    // of a longer binding, the action runs if no more keys come
+    ActionOrPrefix(Action),
+    // the sequence is the start of a longer binding
+    Prefix,
+    None,
+}
+
+#[derive(Debug,Clone)]
+pub struct Keymap {
+    maps: HashMap<Mode, HashMap<Vec<Key>, Action>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut ret = Keymap{
+            maps: HashMap::new(),
+        };
+
+        let normal = [
+            (Key::char('q'), Action::Quit),
+            (Key::char(':'), Action::CommandMode),
+            (Key::char('i'), Action::Insert),
+            (Key::char('I'), Action::InsertFirstNonSpace),
+            (Key::char('o'), Action::OpenBelow),
+            (Key::char('O'), Action::OpenAbove),
+            (Key::char('a'), Action::Append),
+            (Key::char('A'), Action::AppendEnd),
+            (Key::char('0'), Action::FirstCol),
+            (Key::char('w'), Action::NextWord),
+            (Key::char('b'), Action::PrevWord),
+            (Key::char('h'), Action::Left),
+            (Key::char('j'), Action::Down),
+            (Key::char('k'), Action::Up),
+            (Key::char('l'), Action::Right),
+            (KeyCode::Left.into(), Action::Left),
+            (KeyCode::Down.into(), Action::Down),
+            (KeyCode::Up.into(), Action::Up),
+            (KeyCode::Right.into(), Action::Right),
+            (Key::ctrl('e'), Action::ScrollDown),
+            (Key::ctrl('y'), Action::ScrollUp),
+            (Key::ctrl('d'), Action::HalfPageDown),
+            (Key::ctrl('u'), Action::HalfPageUp),
+            (Key::ctrl('f'), Action::PageDown),
+            (Key::ctrl('b'), Action::PageUp),
+            (KeyCode::PageDown.into(), Action::PageDown),
+            (KeyCode::PageUp.into(), Action::PageUp),
+            (Key::alt('o'), Action::ExpandSelection),
+            (Key::char('K'), Action::Hover),
+            (Key::char('='), Action::Reindent),
+            (Key::char('u'), Action::Undo),
+        ];
+
+        for (k, a) in normal.iter() {
+            ret.bind(Mode::Normal, vec![*k], *a);
+        }
+
+        let normal_seqs = [
+            ("gj", Action::DownRow),
+            ("gk", Action::UpRow),
+            ("zt", Action::ScrollCursorTop),
+            ("zz", Action::ScrollCursorCenter),
+            ("zb", Action::ScrollCursorBottom),
+            ("]m", Action::NextFunction),
+            ("[m", Action::PrevFunction),
+            ("vaf", Action::SelectFunction),
+            ("vif", Action::SelectFunctionInner),
+            ("vac", Action::SelectClass),
+            ("vic", Action::SelectClassInner),
+            ("vaa", Action::SelectArgument),
+            ("gd", Action::GotoDefinition),
+            ("gr", Action::References),
+            ("ga", Action::CodeAction),
+            ("]q", Action::NextLocation),
+            ("[q", Action::PrevLocation),
+        ];
+
+        for (k, a) in normal_seqs.iter() {
+            ret.bind(Mode::Normal, k.chars().map(Key::char).collect(), *a);
+        }
+
+        let insert = [
+            (KeyCode::Esc.into(), Action::NormalMode),
+            (KeyCode::Backspace.into(), Action::Backspace),
+            (KeyCode::Enter.into(), Action::Newline),
+            (Key::ctrl('j'), Action::Newline),
+            (KeyCode::Tab.into(), Action::Tab),
+            (Key::new(KeyCode::Tab, Mods::SHIFT), Action::PrevTabstop),
+            (KeyCode::Left.into(), Action::Left),
+            (KeyCode::Down.into(), Action::Down),
+            (KeyCode::Up.into(), Action::Up),
+            (KeyCode::Right.into(), Action::Right),
+            (Key::ctrl('n'), Action::CompleteNext),
+            (Key::ctrl('p'), Action::CompletePrev),
+            (Key::ctrl('y'), Action::CompleteAccept),
+            (Key::ctrl('e'), Action::CompleteCancel),
+        ];
+
+        for (k, a) in insert.iter() {
+            ret.bind(Mode::Insert, vec![*k], *a);
+        }
+        ret.bind(Mode::Insert, vec![Key::ctrl('x'), Key::ctrl('o')], Action::Complete);
+
+        ret
+    }
+}
+
+impl Keymap {
+    pub fn bind(&mut self, mode: Mode, keys: Vec<Key>, action: Action) {
+        self.maps.entry(mode)
+            .or_default()
+            .insert(keys, action);
+    }
+
+    pub fn unbind(&mut self, mode: Mode, keys: &[Key]) -> bool {
+        self.maps.get_mut(&mode)
+            .and_then(|m| m.remove(keys))
+            .is_some()
+    }
+
+    pub fn lookup(&self, mode: Mode, keys: &[Key]) -> Lookup {
+        let m = match self.maps.get(&mode) {
+            Some(m) => m,
+            None => return Lookup::None,
+        };
+
+        let is_prefix = m.keys()
+            .any(|k| k.len() > keys.len() && k.starts_with(keys));
+
+        match m.get(keys) {
+            Some(a) if is_prefix => Lookup::ActionOrPrefix(*a),
+            Some(a) => Lookup::Action(*a),
+            None if is_prefix => Lookup::Prefix,
+            None => Lookup::None,
+        }
+    }
+
+    // the longest start of keys which is bound, with the number
+    // of keys in it
+    pub fn lookup_start(&self, mode: Mode, keys: &[Key]) -> Option<(usize, Action)> {
+        let m = self.maps.get(&mode)?;
+        (1..=keys.len()).rev()
+            .find_map(|n| m.get(&keys[..n]).map(|a| (n, *a)))
+    }
+}
+
+// parses vim style key notation, ex. `jk`, `<C-s>`, `<Esc>`
+pub fn parse_keys(s: &str) -> std::result::Result<Vec<Key>, String> {
+    let mut ret = Vec::new();
+    let mut rest = s;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                ret.push(parse_named_key(&rest[1..end])?);
+                rest = &rest[end+1..];
+                continue;
+            }
+        }
+
+        ret.push(Key::char(c));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if ret.is_empty() {
+        return Err("empty key sequence".into());
+    }
+
+    Ok(ret)
+}
+
+// a key between `<` and `>`, with modifiers like `C-` before
+// the name
+fn parse_named_key(name: &str) -> std::result::Result<Key, String> {
+    let single = |s: &str| {
+        let mut cs = s.chars();
+        match (cs.next(), cs.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    let mut mods = Mods::NONE;
+    let mut rest = name;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        mods = mods | match rest.as_bytes()[0].to_ascii_lowercase() {
+            b'c' => Mods::CTRL,
+            b'a' | b'm' => Mods::ALT,
+            b's' => Mods::SHIFT,
+            b'd' => Mods::SUPER,
+            _ => return Err(format!("unknown key: <{}>", name)),
+        };
+        rest = &rest[2..];
+    }
+
+    if let Some(c) = single(rest) {
+        // terminals can't tell ctrl with upper and lower case
+        // letters apart
+        let c = if mods == Mods::CTRL { c.to_ascii_lowercase() } else { c };
+        return Ok(Key::new(KeyCode::Char(c), mods));
+    }
+
+    let lower = rest.to_ascii_lowercase();
+
+    if let Some(n) = lower.strip_prefix('f') {
+        if let Ok(n) = n.parse::<u8>() {
+            if (1..=12).contains(&n) {
+                return Ok(Key::new(KeyCode::F(n), mods));
+            }
+        }
+    }
+
+    let code = match lower.as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "del" | "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        _ => return Err(format!("unknown key: <{}>", name)),
+    };
+
+    Ok(Key::new(code, mods))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_modifiers() {
+        assert_eq!(parse_keys("<C-S>x<C-A-Left><S-Tab><S-a>").unwrap(), vec![
+            Key::ctrl('s'),
+            Key::char('x'),
+            Key::new(KeyCode::Left, Mods::CTRL | Mods::ALT),
+            Key::new(KeyCode::Tab, Mods::SHIFT),
+            Key::char('A'),
+        ]);
+        assert!(parse_keys("<X-a>").is_err());
+    }
+
+    #[test]
+    fn bindings_which_start_longer_ones() {
+        let mut km = Keymap{
+            maps: HashMap::new(),
+        };
+        let keys = |s| parse_keys(s).unwrap();
+        km.bind(Mode::Normal, keys("g"), Action::Quit);
+        km.bind(Mode::Normal, keys("gg"), Action::ScrollCursorTop);
+        km.bind(Mode::Normal, keys("gqx"), Action::ScrollCursorBottom);
+
+        assert_eq!(km.lookup(Mode::Normal, &keys("g")), Lookup::ActionOrPrefix(Action::Quit));
+        assert_eq!(km.lookup(Mode::Normal, &keys("gg")), Lookup::Action(Action::ScrollCursorTop));
+        assert_eq!(km.lookup(Mode::Normal, &keys("gq")), Lookup::Prefix);
+        assert_eq!(km.lookup(Mode::Normal, &keys("gx")), Lookup::None);
+        assert_eq!(km.lookup(Mode::Insert, &keys("g")), Lookup::None);
+
+        assert_eq!(km.lookup_start(Mode::Normal, &keys("gqy")), Some((1, Action::Quit)));
+        assert_eq!(km.lookup_start(Mode::Normal, &keys("ggg")), Some((2, Action::ScrollCursorTop)));
+        assert_eq!(km.lookup_start(Mode::Normal, &keys("x")), None);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["a", "<C-x>", "<A-S-Up>", "<lt>", "<space>", "<F5>"].iter() {
+            let keys = parse_keys(s).unwrap();
+            assert_eq!(keys[0].to_string().to_ascii_lowercase(), s.to_ascii_lowercase());
+        }
+    }
+}
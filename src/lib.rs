@@ -10,13 +10,93 @@ mod renderer;
 pub use renderer::{
     Renderer,
     TerminalRenderer,
+    TestRenderer,
+    TerminalGuard,
+    Region,
+    Style,
+    Color,
+    ColorDepth,
+    Grid,
+    Cell,
 };
 
 mod buffer;
 pub use buffer::{
     View,
+    ViewConfig,
+    Sign,
+    SignColumn,
+    VirtualText,
+    LineChange,
     Buffer,
     Line,
     LineMut,
     LineConfig,
+    FileConfig,
+    LineEnding,
+    Charset,
+};
+
+mod options;
+pub use options::Options;
+
+pub mod input;
+pub use input::{
+    Key,
+    KeyCode,
+    Mods,
+};
+
+pub mod filetype;
+pub use filetype::FileType;
+
+pub mod highlight;
+pub use highlight::{Grammar, Highlighter};
+
+pub mod theme;
+pub use theme::Theme;
+
+pub mod lsp;
+
+pub mod complete;
+
+pub mod snippet;
+pub use snippet::{
+    Snippet,
+    Snippets,
+};
+
+pub mod float;
+pub use float::{
+    Float,
+    Floats,
+};
+
+#[cfg(feature = "tree-sitter")]
+pub mod syntax;
+#[cfg(feature = "tree-sitter")]
+pub use syntax::SyntaxTree;
+
+pub mod statusline;
+pub use statusline::{
+    StatusLine,
+    StatusInfo,
+};
+
+pub mod keymap;
+pub use keymap::Keymap;
+
+pub mod config;
+pub use config::Config;
+
+pub mod editorconfig;
+
+pub mod glob;
+
+pub mod signal;
+
+pub mod event;
+pub use event::{
+    Event,
+    EventLoop,
 };
@@ -0,0 +1,685 @@
+use std::{
+    collections::HashMap,
+    io::{
+        self,
+        BufReader,
+    },
+    mem,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        self,
+        Child,
+        ChildStdin,
+        Command,
+        Stdio,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+
+use crate::{
+    Buffer,
+    Result,
+    Sign,
+    Snippet,
+    View,
+    VirtualText,
+    event::Handle,
+};
+
+use super::{
+    CodeAction,
+    CompletionItem,
+    Diagnostic,
+    Encoding,
+    Location,
+    Position,
+    Range,
+    TextEdit,
+    WorkspaceEdit,
+    hover_text,
+    path_to_uri,
+    read_message,
+    uri_to_path,
+    workspace_edit_from_json,
+    write_message,
+};
+
+
+// the sign and virtual text group of diagnostics
+const GROUP: &str = "lsp";
+
+// how long a server gets to exit before it's killed
+const EXIT_TIMEOUT: Duration = Duration::from_millis(200);
+
+// a message from a server, sent to the event loop by the
+// thread reading its output. msg is none once the server
+// is gone
+#[derive(Debug)]
+pub struct Incoming {
+    pub server: String,
+    pub msg: Option<Value>,
+}
+
+// what a message from the server means for the editor
+#[derive(Debug,Clone,PartialEq)]
+pub enum Reply {
+    Diagnostics(PathBuf, Vec<Diagnostic>),
+    Hover(String),
+    Definition(Vec<Location>),
+    References(Vec<Location>),
+    Completion(Vec<CompletionItem>),
+    Rename(WorkspaceEdit),
+    CodeActions(Vec<CodeAction>),
+    // the server asked for edits to be made
+    ApplyEdit(WorkspaceEdit),
+    // something to show the user
+    Message(String),
+    Error(String),
+}
+
+// what a request in flight asked for
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Request {
+    Initialize,
+    Shutdown,
+    Hover,
+    Definition,
+    References,
+    Completion,
+    Rename,
+    CodeAction,
+    ExecuteCommand,
+}
+
+// how the server wants to hear about changes to documents
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Sync {
+    None,
+    Full,
+    Incremental,
+}
+
+// a language server running as a child process, talking
+// JSON-RPC over its stdin and stdout. replies come back as
+// Incoming messages through the event loop and are turned
+// into Replies by handle
+//
+// documents are sent to the server as lines ending in `\n`,
+// so every change can be sent as whole lines
+#[derive(Debug)]
+pub struct Client {
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    // messages held back until the server is initialized
+    queued: Vec<Value>,
+    initialized: bool,
+    capabilities: Value,
+    encoding: Encoding,
+    sync: Sync,
+    // versions of the open documents by uri
+    documents: HashMap<String, i64>,
+    // as the server sent them, for code actions
+    diagnostics: HashMap<String, Vec<Value>>,
+}
+
+impl Client {
+    // starts the server cmd in root, named name in the
+    // messages it sends to handle
+    pub fn start<S: AsRef<str>>(name: &str, cmd: &[S], root: &Path, handle: Handle) ->
+        Result<Client> {
+
+        let (prog, args) = cmd.split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no server command"))?;
+        let mut child = Command::new(prog.as_ref())
+            .args(args.iter().map(AsRef::as_ref))
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let server = name.to_string();
+        thread::spawn(move || {
+            let mut r = BufReader::new(stdout);
+            loop {
+                let msg = read_message(&mut r).ok().flatten();
+                let done = msg.is_none();
+                let sent = handle.send(Incoming{
+                    server: server.clone(),
+                    msg,
+                });
+                if done || !sent {
+                    break;
+                }
+            }
+        });
+
+        let mut ret = Client{
+            name: name.into(),
+            child,
+            stdin,
+            next_id: 0,
+            pending: HashMap::new(),
+            queued: Vec::new(),
+            initialized: false,
+            capabilities: Value::Null,
+            encoding: Encoding::default(),
+            sync: Sync::None,
+            documents: HashMap::new(),
+            diagnostics: HashMap::new(),
+        };
+
+        let id = ret.add_request(Request::Initialize);
+        let params = json!({
+            "processId": process::id(),
+            "rootUri": path_to_uri(root),
+            "clientInfo": {"name": "editor"},
+            "capabilities": {
+                "general": {"positionEncodings": ["utf-8", "utf-16"]},
+                "textDocument": {
+                    "synchronization": {"didSave": true},
+                    "publishDiagnostics": {},
+                    "hover": {"contentFormat": ["plaintext", "markdown"]},
+                    "completion": {"completionItem": {"snippetSupport": true}},
+                    "definition": {},
+                    "references": {},
+                    "rename": {},
+                    "codeAction": {
+                        "codeActionLiteralSupport": {
+                            "codeActionKind": {"valueSet": ["quickfix", "refactor", "source"]},
+                        },
+                    },
+                },
+                "workspace": {"applyEdit": true},
+            },
+        });
+        write_message(&mut ret.stdin, &message(id, "initialize", params))?;
+
+        Ok(ret)
+    }
+
+    fn add_request(&mut self, kind: Request) -> u64 {
+        self.next_id += 1;
+        self.pending.insert(self.next_id, kind);
+        self.next_id
+    }
+
+    // writes msg, or keeps it for when the server is ready
+    fn send(&mut self, msg: Value) -> std::result::Result<(), String> {
+        if !self.initialized {
+            self.queued.push(msg);
+            return Ok(());
+        }
+
+        write_message(&mut self.stdin, &msg)
+            .map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    fn request(&mut self, kind: Request, method: &str, params: Value) ->
+        std::result::Result<(), String> {
+
+        let id = self.add_request(kind);
+        self.send(message(id, method, params))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> std::result::Result<(), String> {
+        self.send(json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    fn respond(&mut self, id: Value, result: std::result::Result<Value, (i64, &str)>) {
+        let msg = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, msg)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": msg},
+            }),
+        };
+        // a server which went away is noticed when its output ends
+        let _ = self.send(msg);
+    }
+
+    // false when the server said it can't do what's asked, it
+    // gets the benefit of the doubt before it's initialized
+    fn supports(&self, capability: &str) -> bool {
+        if !self.initialized {
+            return true;
+        }
+
+        !matches!(self.capabilities[capability], Value::Null | Value::Bool(false))
+    }
+
+    fn check(&self, capability: &str, what: &str) -> std::result::Result<(), String> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(format!("{} doesn't support {}", self.name, what))
+        }
+    }
+
+    // opens the view's document on the server, or sends what
+    // changed since the last time
+    pub fn sync(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        let uri = document_uri(view)?;
+        let change = view.take_changes();
+
+        let version = match self.documents.get_mut(&uri) {
+            Some(v) => v,
+            None => {
+                let params = json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": view.file_config().filetype.as_deref().unwrap_or("plaintext"),
+                        "version": 0,
+                        "text": text(view.lines()),
+                    },
+                });
+                self.documents.insert(uri, 0);
+                return self.notify("textDocument/didOpen", params);
+            },
+        };
+
+        let change = match (change, self.sync) {
+            (None, _) | (_, Sync::None) => return Ok(()),
+            (Some(_), Sync::Full) => json!({"text": text(view.lines())}),
+            (Some(c), Sync::Incremental) => {
+                let start = Position{ line: c.start, character: 0 };
+                let end = Position{ line: c.old_end, character: 0 };
+                let lines = view.lines().skip(c.start).take(c.new_end - c.start);
+                json!({"range": Range{ start, end }.to_json(), "text": text(lines)})
+            },
+        };
+
+        *version += 1;
+        let params = json!({
+            "textDocument": {"uri": uri, "version": *version},
+            "contentChanges": [change],
+        });
+        self.notify("textDocument/didChange", params)
+    }
+
+    pub fn did_save(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.sync(view)?;
+        let uri = document_uri(view)?;
+        self.notify("textDocument/didSave", json!({"textDocument": {"uri": uri}}))
+    }
+
+    // the server's position of a line and byte in the view
+    fn position(&self, view: &View, (idx, i): (usize, usize)) -> Value {
+        let line = view.line(idx).unwrap_or("");
+        Position{
+            line: idx,
+            character: self.encoding.character(line, i),
+        }.to_json()
+    }
+
+    // the document and the cursor's position in it
+    fn position_params(&mut self, view: &mut View) -> std::result::Result<Value, String> {
+        self.sync(view)?;
+        Ok(json!({
+            "textDocument": {"uri": document_uri(view)?},
+            "position": self.position(view, view.cursor_byte()),
+        }))
+    }
+
+    pub fn hover(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.check("hoverProvider", "hover")?;
+        let params = self.position_params(view)?;
+        self.request(Request::Hover, "textDocument/hover", params)
+    }
+
+    pub fn definition(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.check("definitionProvider", "definitions")?;
+        let params = self.position_params(view)?;
+        self.request(Request::Definition, "textDocument/definition", params)
+    }
+
+    pub fn references(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.check("referencesProvider", "references")?;
+        let mut params = self.position_params(view)?;
+        params["context"] = json!({"includeDeclaration": true});
+        self.request(Request::References, "textDocument/references", params)
+    }
+
+    pub fn completion(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.check("completionProvider", "completion")?;
+        let mut params = self.position_params(view)?;
+        params["context"] = json!({"triggerKind": 1});
+        self.request(Request::Completion, "textDocument/completion", params)
+    }
+
+    pub fn rename(&mut self, view: &mut View, name: &str) -> std::result::Result<(), String> {
+        self.check("renameProvider", "rename")?;
+        let mut params = self.position_params(view)?;
+        params["newName"] = json!(name);
+        self.request(Request::Rename, "textDocument/rename", params)
+    }
+
+    // asks for the code actions of the selection, or the
+    // cursor's position
+    pub fn code_actions(&mut self, view: &mut View) -> std::result::Result<(), String> {
+        self.check("codeActionProvider", "code actions")?;
+        self.sync(view)?;
+        let uri = document_uri(view)?;
+
+        let (start, end) = view.selection_bytes()
+            .unwrap_or((view.cursor_byte(), view.cursor_byte()));
+
+        // the diagnostics on those lines
+        let diagnostics: Vec<Value> = self.diagnostics.get(&uri)
+            .map(|d| d.iter()
+                .filter(|d| {
+                    let line = |p: &str| d["range"][p]["line"].as_u64().unwrap_or(0) as usize;
+                    line("start") <= end.0 && line("end") >= start.0
+                })
+                .cloned()
+                .collect())
+            .unwrap_or_default();
+
+        let params = json!({
+            "textDocument": {"uri": uri},
+            "range": {"start": self.position(view, start), "end": self.position(view, end)},
+            "context": {"diagnostics": diagnostics},
+        });
+        self.request(Request::CodeAction, "textDocument/codeAction", params)
+    }
+
+    // runs a Command of a code action on the server
+    pub fn execute(&mut self, command: &Value) -> std::result::Result<(), String> {
+        let params = json!({
+            "command": command["command"],
+            "arguments": command.get("arguments").cloned().unwrap_or_else(|| json!([])),
+        });
+        self.request(Request::ExecuteCommand, "workspace/executeCommand", params)
+    }
+
+    // a message the server sent, what it means for the editor
+    // if anything
+    pub fn handle(&mut self, msg: Value) -> Option<Reply> {
+        let method = msg["method"].as_str().map(String::from);
+
+        match (msg.get("id").cloned(), method) {
+            (Some(id), Some(method)) => self.server_request(id, &method, &msg["params"]),
+            (None, Some(method)) => self.notification(&method, &msg["params"]),
+            (Some(id), None) => self.response(&id, &msg),
+            (None, None) => None,
+        }
+    }
+
+    fn response(&mut self, id: &Value, msg: &Value) -> Option<Reply> {
+        let kind = self.pending.remove(&id.as_u64()?)?;
+
+        if let Some(err) = msg.get("error") {
+            // cancelled requests and ones overtaken by edits
+            // aren't worth showing
+            if let Some(-32800) | Some(-32801) = err["code"].as_i64() {
+                return None;
+            }
+            let text = err["message"].as_str().unwrap_or("request failed");
+            return Some(Reply::Error(format!("{}: {}", self.name, text)));
+        }
+
+        let result = &msg["result"];
+        match kind {
+            Request::Initialize => {
+                self.initialize(result);
+                None
+            },
+            Request::Shutdown | Request::ExecuteCommand => None,
+            Request::Hover => Some(Reply::Hover(hover_text(&result["contents"]))),
+            Request::Definition => Some(Reply::Definition(Location::list_from_json(result))),
+            Request::References => Some(Reply::References(Location::list_from_json(result))),
+            Request::Completion => Some(Reply::Completion(CompletionItem::list_from_json(result))),
+            Request::Rename => Some(Reply::Rename(workspace_edit_from_json(result))),
+            Request::CodeAction => {
+                let actions = result.as_array()
+                    .map(|a| a.iter().filter_map(CodeAction::from_json).collect())
+                    .unwrap_or_default();
+                Some(Reply::CodeActions(actions))
+            },
+        }
+    }
+
+    fn initialize(&mut self, result: &Value) {
+        self.capabilities = result["capabilities"].clone();
+        self.encoding = self.capabilities["positionEncoding"].as_str()
+            .and_then(Encoding::from_name)
+            .unwrap_or_default();
+
+        let sync = &self.capabilities["textDocumentSync"];
+        let kind = sync.as_u64().or_else(|| sync["change"].as_u64());
+        self.sync = match kind {
+            Some(1) => Sync::Full,
+            Some(2) => Sync::Incremental,
+            _ => Sync::None,
+        };
+
+        self.initialized = true;
+        let _ = self.notify("initialized", json!({}));
+        for msg in mem::take(&mut self.queued) {
+            let _ = self.send(msg);
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Option<Reply> {
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let uri = params["uri"].as_str()?;
+                let raw = params["diagnostics"].as_array().cloned().unwrap_or_default();
+                let diagnostics = raw.iter().filter_map(Diagnostic::from_json).collect();
+                self.diagnostics.insert(uri.into(), raw);
+                Some(Reply::Diagnostics(uri_to_path(uri)?, diagnostics))
+            },
+            "window/showMessage" => {
+                let text = params["message"].as_str()?;
+                Some(Reply::Message(format!("{}: {}", self.name, text)))
+            },
+            _ => None,
+        }
+    }
+
+    fn server_request(&mut self, id: Value, method: &str, params: &Value) -> Option<Reply> {
+        match method {
+            "workspace/applyEdit" => {
+                self.respond(id, Ok(json!({"applied": true})));
+                Some(Reply::ApplyEdit(workspace_edit_from_json(&params["edit"])))
+            },
+            "workspace/configuration" => {
+                let n = params["items"].as_array().map_or(0, Vec::len);
+                self.respond(id, Ok(Value::Array(vec![Value::Null; n])));
+                None
+            },
+            "window/showMessageRequest" => {
+                self.respond(id, Ok(Value::Null));
+                let text = params["message"].as_str()?;
+                Some(Reply::Message(format!("{}: {}", self.name, text)))
+            },
+            "window/workDoneProgress/create"
+            | "client/registerCapability"
+            | "client/unregisterCapability" => {
+                self.respond(id, Ok(Value::Null));
+                None
+            },
+            _ => {
+                self.respond(id, Err((-32601, "method not found")));
+                None
+            },
+        }
+    }
+
+    // the line and byte in the view of the server's position
+    pub fn to_byte(&self, view: &View, p: Position) -> (usize, usize) {
+        let line = view.line(p.line).unwrap_or("");
+        (p.line, self.encoding.byte(line, p.character))
+    }
+
+    // shows diagnostics in the sign column and after their
+    // lines, the most severe first
+    pub fn show_diagnostics(&self, view: &mut View, diagnostics: &[Diagnostic]) {
+        view.remove_signs(GROUP);
+        view.remove_virtual_text(GROUP);
+
+        let mut sorted: Vec<&Diagnostic> = diagnostics.iter().collect();
+        sorted.sort_by_key(|d| d.severity);
+        for d in sorted {
+            let idx = d.range.start.line;
+            view.add_sign(idx, Sign{
+                group: GROUP.into(),
+                text: d.severity.sign().into(),
+                highlight: d.severity.highlight().into(),
+                priority: -(d.severity as i32),
+            });
+            view.add_virtual_text(idx, VirtualText{
+                group: GROUP.into(),
+                text: d.message.lines().next().unwrap_or("").into(),
+                highlight: d.severity.highlight().into(),
+            });
+        }
+    }
+
+    // makes edits to the view, each edit's range is in the text
+    // from before any of them
+    pub fn apply_edits(&self, view: &mut View, edits: &[TextEdit]) {
+        let mut edits: Vec<_> = edits.iter()
+            .map(|e| (self.to_byte(view, e.range.start), self.to_byte(view, e.range.end), &e.text))
+            .collect();
+
+        // from the last one so the others stay where they were
+        edits.sort_by_key(|e| std::cmp::Reverse(e.0));
+        for (start, end, text) in edits {
+            view.replace(start, end, text);
+        }
+    }
+
+    // makes the edits for the view's file to it and the rest
+    // to the files themselves, returns how many files changed
+    pub fn apply_workspace_edit(&self, view: &mut View, edit: &WorkspaceEdit) ->
+        std::result::Result<usize, String> {
+
+        for (path, edits) in edit {
+            let shown = view.file_config().path.as_deref()
+                .is_some_and(|p| same_file(p, path));
+            if shown {
+                self.apply_edits(view, edits);
+                continue;
+            }
+
+            let err = |e: crate::Error| format!("{}: {}", path.display(), e);
+            let mut buf = Buffer::open(path).map_err(err)?;
+            let mut other = View::new(&mut buf);
+            self.apply_edits(&mut other, edits);
+            other.save().map_err(err)?;
+        }
+
+        Ok(edit.len())
+    }
+
+    // inserts a completion, in place of the word before the
+    // cursor when the item has no edit of its own. snippets go
+    // in as their text, without tabstops
+    pub fn complete(&self, view: &mut View, item: &CompletionItem) {
+        let text = |t: &str| match item.snippet {
+            true => Snippet::parse(t).text(),
+            false => t.to_string(),
+        };
+
+        if let Some(edit) = &item.edit {
+            let edit = TextEdit{
+                range: edit.range,
+                text: text(&edit.text),
+            };
+            self.apply_edits(view, &[edit]);
+            return;
+        }
+
+        let (idx, i) = view.cursor_byte();
+        let start = word_start(view.line(idx).unwrap_or(""), i);
+        view.replace((idx, start), (idx, i), &text(&item.text));
+    }
+}
+
+impl Drop for Client {
+    // asks the server to exit, it's killed if it doesn't soon
+    fn drop(&mut self) {
+        if self.initialized {
+            let _ = self.request(Request::Shutdown, "shutdown", Value::Null);
+            let _ = self.notify("exit", Value::Null);
+        }
+
+        let deadline = Instant::now() + EXIT_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn message(id: u64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+// lines as the server sees them, each ending in a line break
+fn text<'a, I: Iterator<Item = &'a str>>(lines: I) -> String {
+    let mut ret = String::new();
+    for line in lines {
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    ret
+}
+
+fn document_uri(view: &View) -> std::result::Result<String, String> {
+    view.file_config().path.as_deref()
+        .map(path_to_uri)
+        .ok_or_else(|| "buffer has no file name".to_string())
+}
+
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// where the word ending at byte i of line starts
+fn word_start(line: &str, i: usize) -> usize {
+    line[..i].char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(i, |(j, _)| j)
+}
+
+// the directory a server for path runs in, the closest one
+// above it with version control or the file's own
+pub fn root_dir(path: &Path) -> PathBuf {
+    let abs = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let dir = abs.parent().unwrap_or(Path::new("."));
+
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
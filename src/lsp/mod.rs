@@ -0,0 +1,32 @@
+mod transport;
+pub use transport::{
+    read_message,
+    write_message,
+};
+
+mod types;
+pub use types::{
+    Encoding,
+    Position,
+    Range,
+    Severity,
+    Diagnostic,
+    Location,
+    TextEdit,
+    WorkspaceEdit,
+    CompletionItem,
+    CodeAction,
+    workspace_edit_from_json,
+    hover_text,
+    path_to_uri,
+    uri_to_path,
+};
+
+mod client;
+pub use client::{
+    Client,
+    Incoming,
+    Reply,
+    root_dir,
+    same_file,
+};
@@ -0,0 +1,90 @@
+use std::io::{
+    self,
+    BufRead,
+    Write,
+};
+
+use serde_json::Value;
+
+
+// the longest message read, a bad length shouldn't take all
+// the memory there is
+const MAX_LENGTH: usize = 64 << 20;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// reads a message framed with a Content-Length header, none
+// at the end of the stream
+pub fn read_message<R: BufRead>(r: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    let mut headers = false;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            match (len, headers) {
+                (Some(_), _) => break,
+                (None, true) => return Err(invalid("no content length".into())),
+                // blank lines between messages
+                (None, false) => continue,
+            }
+        }
+        headers = true;
+
+        let (name, value) = header.split_once(':')
+            .ok_or_else(|| invalid(format!("bad header: {}", header)))?;
+        if name.eq_ignore_ascii_case("content-length") {
+            let n = value.trim().parse::<usize>()
+                .map_err(|_| invalid(format!("bad content length: {}", value.trim())))?;
+            if n > MAX_LENGTH {
+                return Err(invalid(format!("message too long: {}", n)));
+            }
+            len = Some(n);
+        }
+    }
+
+    let mut body = vec![0; len.unwrap()];
+    r.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+pub fn write_message<W: Write>(w: &mut W, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn frames_messages() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({"id": 1})).unwrap();
+        write_message(&mut out, &json!("é")).unwrap();
+        assert!(out.starts_with(b"Content-Length: 8\r\n\r\n{\"id\":1}"));
+
+        let mut r = &out[..];
+        assert_eq!(read_message(&mut r).unwrap(), Some(json!({"id": 1})));
+        assert_eq!(read_message(&mut r).unwrap(), Some(json!("é")));
+        assert_eq!(read_message(&mut r).unwrap(), None);
+
+        let mut r = &b"Content-Type: x\r\n\r\n"[..];
+        assert!(read_message(&mut r).is_err());
+
+        let mut r = &b"Content-Length: 99999999999\r\n\r\n"[..];
+        assert!(read_message(&mut r).is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    env,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+
+
+// how the server counts the characters of a line
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Encoding {
+    Utf8,
+    // what servers use unless they say otherwise
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl Encoding {
+    pub fn from_name(s: &str) -> Option<Encoding> {
+        match s {
+            "utf-8" => Some(Encoding::Utf8),
+            "utf-16" => Some(Encoding::Utf16),
+            "utf-32" => Some(Encoding::Utf32),
+            _ => None,
+        }
+    }
+
+    fn len(self, c: char) -> usize {
+        match self {
+            Encoding::Utf8 => c.len_utf8(),
+            Encoding::Utf16 => c.len_utf16(),
+            Encoding::Utf32 => 1,
+        }
+    }
+
+    // the server's character for byte i of line
+    pub fn character(self, line: &str, i: usize) -> usize {
+        line[..i.min(line.len())].chars().map(|c| self.len(c)).sum()
+    }
+
+    // the byte of the server's character ch in line, one in the
+    // middle of a character is the start of it and one past the
+    // end is the end
+    pub fn byte(self, line: &str, ch: usize) -> usize {
+        let mut n = 0;
+        for (i, c) in line.char_indices() {
+            n += self.len(c);
+            if n > ch {
+                return i;
+            }
+        }
+        line.len()
+    }
+}
+
+// a place in a document as the server sees it
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Default)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    pub fn from_json(v: &Value) -> Option<Position> {
+        Some(Position{
+            line: v["line"].as_u64()? as usize,
+            character: v["character"].as_u64()? as usize,
+        })
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({"line": self.line, "character": self.character})
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn from_json(v: &Value) -> Option<Range> {
+        Some(Range{
+            start: Position::from_json(&v["start"])?,
+            end: Position::from_json(&v["end"])?,
+        })
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({"start": self.start.to_json(), "end": self.end.to_json()})
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Severity {
+    Error = 1,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    fn from_json(v: &Value) -> Severity {
+        match v.as_u64() {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            // servers which don't say mean errors
+            _ => Severity::Error,
+        }
+    }
+
+    // what's shown in the sign column
+    pub fn sign(self) -> &'static str {
+        match self {
+            Severity::Error => "E",
+            Severity::Warning => "W",
+            Severity::Information => "I",
+            Severity::Hint => "H",
+        }
+    }
+
+    pub fn highlight(self) -> &'static str {
+        match self {
+            Severity::Error => "DiagnosticError",
+            Severity::Warning => "DiagnosticWarn",
+            Severity::Information => "DiagnosticInfo",
+            Severity::Hint => "DiagnosticHint",
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn from_json(v: &Value) -> Option<Diagnostic> {
+        Some(Diagnostic{
+            range: Range::from_json(&v["range"])?,
+            severity: Severity::from_json(&v["severity"]),
+            message: v["message"].as_str()?.into(),
+        })
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub range: Range,
+}
+
+impl Location {
+    // a Location or a LocationLink
+    pub fn from_json(v: &Value) -> Option<Location> {
+        if let Some(uri) = v["targetUri"].as_str() {
+            return Some(Location{
+                path: uri_to_path(uri)?,
+                range: Range::from_json(&v["targetSelectionRange"])?,
+            });
+        }
+
+        Some(Location{
+            path: uri_to_path(v["uri"].as_str()?)?,
+            range: Range::from_json(&v["range"])?,
+        })
+    }
+
+    // one location, a list of them or null
+    pub fn list_from_json(v: &Value) -> Vec<Location> {
+        match v {
+            Value::Array(a) => a.iter().filter_map(Location::from_json).collect(),
+            Value::Null => Vec::new(),
+            v => Location::from_json(v).into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn from_json(v: &Value) -> Option<TextEdit> {
+        // an InsertReplaceEdit is used as an insert
+        let range = match v.get("insert") {
+            Some(r) => r,
+            None => &v["range"],
+        };
+
+        Some(TextEdit{
+            range: Range::from_json(range)?,
+            text: v["newText"].as_str()?.into(),
+        })
+    }
+}
+
+// the edits to make to each file
+pub type WorkspaceEdit = Vec<(PathBuf, Vec<TextEdit>)>;
+
+pub fn workspace_edit_from_json(v: &Value) -> WorkspaceEdit {
+    let edits = |v: &Value| -> Vec<TextEdit> {
+        v.as_array()
+            .map(|a| a.iter().filter_map(TextEdit::from_json).collect())
+            .unwrap_or_default()
+    };
+
+    let mut ret = Vec::new();
+    if let Some(changes) = v["changes"].as_object() {
+        for (uri, e) in changes {
+            if let Some(path) = uri_to_path(uri) {
+                ret.push((path, edits(e)));
+            }
+        }
+    }
+    // creating, renaming and deleting files is left out
+    if let Some(changes) = v["documentChanges"].as_array() {
+        for c in changes {
+            if let Some(path) = c["textDocument"]["uri"].as_str().and_then(uri_to_path) {
+                ret.push((path, edits(&c["edits"])));
+            }
+        }
+    }
+
+    ret
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    // what's inserted in place of the word before the cursor,
+    // unless there's an edit
+    pub text: String,
+    pub edit: Option<TextEdit>,
+    // the text is a snippet
+    pub snippet: bool,
+}
+
+impl CompletionItem {
+    pub fn from_json(v: &Value) -> Option<CompletionItem> {
+        let label = v["label"].as_str()?;
+
+        Some(CompletionItem{
+            label: label.into(),
+            detail: v["detail"].as_str().map(String::from),
+            documentation: Some(hover_text(&v["documentation"])).filter(|s| !s.is_empty()),
+            text: v["insertText"].as_str().unwrap_or(label).into(),
+            edit: TextEdit::from_json(&v["textEdit"]),
+            snippet: v["insertTextFormat"] == 2,
+        })
+    }
+
+    // a list of items or a CompletionList
+    pub fn list_from_json(v: &Value) -> Vec<CompletionItem> {
+        let items = match v.get("items") {
+            Some(items) => items,
+            None => v,
+        };
+
+        items.as_array()
+            .map(|a| a.iter().filter_map(CompletionItem::from_json).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct CodeAction {
+    pub title: String,
+    pub edit: WorkspaceEdit,
+    // a Command for the server to run after the edit
+    pub command: Option<Value>,
+}
+
+impl CodeAction {
+    // a CodeAction or a Command
+    pub fn from_json(v: &Value) -> Option<CodeAction> {
+        let title = v["title"].as_str()?.into();
+
+        if v["command"].is_string() {
+            return Some(CodeAction{
+                title,
+                edit: Vec::new(),
+                command: Some(v.clone()),
+            });
+        }
+
+        Some(CodeAction{
+            title,
+            edit: workspace_edit_from_json(&v["edit"]),
+            command: v.get("command").filter(|c| c.is_object()).cloned(),
+        })
+    }
+}
+
+// the text of hover contents, which is a MarkupContent, a
+// MarkedString or a list of MarkedStrings
+pub fn hover_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a.iter()
+            .map(hover_text)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Value::Object(_) => v["value"].as_str().unwrap_or("").into(),
+        _ => String::new(),
+    }
+}
+
+const URI_SAFE: &[u8] = b"-._~/";
+
+// a file:// uri for path, relative paths are made absolute
+pub fn path_to_uri(path: &Path) -> String {
+    let abs = match path.canonicalize() {
+        Ok(p) => p,
+        Err(_) if path.is_absolute() => path.to_path_buf(),
+        Err(_) => env::current_dir().unwrap_or_default().join(path),
+    };
+
+    let mut ret = String::from("file://");
+    for &b in abs.to_string_lossy().as_bytes() {
+        if b.is_ascii_alphanumeric() || URI_SAFE.contains(&b) {
+            ret.push(b as char);
+        } else {
+            ret.push_str(&format!("%{:02X}", b));
+        }
+    }
+    ret
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let s = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(s.len());
+
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == b'%' {
+            let hex = std::str::from_utf8(s.get(i + 1..i + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(s[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_characters_like_the_server() {
+        let line = "aé😀b";
+        assert_eq!(Encoding::Utf16.character(line, 7), 4);
+        assert_eq!(Encoding::Utf8.character(line, 7), 7);
+        assert_eq!(Encoding::Utf32.character(line, 7), 3);
+
+        assert_eq!(Encoding::Utf16.byte(line, 4), 7);
+        // the middle of the emoji
+        assert_eq!(Encoding::Utf16.byte(line, 3), 3);
+        assert_eq!(Encoding::Utf16.byte(line, 99), line.len());
+    }
+
+    #[test]
+    fn uris() {
+        let uri = path_to_uri(Path::new("/tmp/a b/é.rs"));
+        assert_eq!(uri, "file:///tmp/a%20b/%C3%A9.rs");
+        assert_eq!(uri_to_path(&uri).unwrap(), Path::new("/tmp/a b/é.rs"));
+        assert_eq!(uri_to_path("http://x"), None);
+    }
+}
@@ -3,156 +3,1212 @@
 #![allow(dead_code)]
 
 use std::{
+    env,
     mem,
-    io,
+    io::{
+        self,
+        ErrorKind,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    panic::{
+        self,
+        AssertUnwindSafe,
+    },
+    process,
     convert::From,
+    time::Duration,
 };
 
-use termion::{
-    input::TermRead,
-    event::Key,
-};
+use unicode_width::UnicodeWidthStr;
 
 
 use editor::{
+    Error,
     Result,
     Renderer,
     TerminalRenderer,
+    Region,
+    StatusLine,
+    StatusInfo,
     Buffer,
     View,
+    LineConfig,
+    Config,
+    Theme,
+    Float,
+    Floats,
+    Snippet,
+    Snippets,
+    Event,
+    EventLoop,
+    Key,
+    KeyCode,
+    complete::{
+        self,
+        Source,
+    },
+    config,
+    snippet,
+    filetype,
+    lsp::{
+        self,
+        Reply,
+    },
+    event::TimerId,
+    float::{
+        Anchor,
+        FloatId,
+    },
+    input::{
+        Mouse,
+        MouseButton,
+        MouseKind,
+    },
+    editorconfig::{
+        self,
+        Properties,
+    },
+    keymap::{
+        Action,
+        Lookup,
+        Mode,
+    },
 };
 
+const USAGE: &str = "usage: editor [--clean] [--dump] [-u config] [file]";
+
+struct Args {
+    // skip loading any config file
+    clean: bool,
+    // load this config instead of the default one
+    config: Option<PathBuf>,
+    // print the buffer on exit, for debugging
+    dump: bool,
+    file: Option<PathBuf>,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut args: I) ->
+        std::result::Result<Args, String> {
+
+        let mut ret = Args{
+            clean: false,
+            config: None,
+            dump: false,
+            file: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--clean" => ret.clean = true,
+                "--dump" => ret.dump = true,
+                "-u" => {
+                    let p = args.next()
+                        .ok_or_else(|| "-u requires a path".to_string())?;
+                    ret.config = Some(p.into());
+                },
+                "-h" | "--help" => return Err(USAGE.into()),
+                _ if arg.starts_with('-') || ret.file.is_some() => {
+                    return Err(format!("unknown argument: {}\n{}", arg, USAGE));
+                },
+                _ => ret.file = Some(arg.into()),
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // the config file to load, if any
+    fn config_path(&self) -> Option<PathBuf> {
+        if self.config.is_some() {
+            return self.config.clone();
+        }
+
+        if self.clean {
+            return None;
+        }
+
+        Config::default_path().filter(|p| p.exists())
+    }
+}
+
+struct State {
+    mode: Mode,
+    // the text typed after `:`
+    cmdline: Option<String>,
+    // shown in the status line until the next key
+    message: Option<String>,
+    // keys which are a prefix of a mapping
+    pending: Vec<Key>,
+    // goes off when it's time to stop waiting for the rest
+    // of the pending keys
+    pending_timer: Option<TimerId>,
+    // applied over the options whenever they change
+    editorconfig: Properties,
+    quit: bool,
+    // asked to exit by a signal, unsaved changes get a
+    // recovery copy
+    terminated: bool,
+    // the language server for the buffer's filetype
+    lsp: Option<lsp::Client>,
+    // what the last definition or references request found,
+    // and which of them the cursor was sent to
+    locations: Vec<lsp::Location>,
+    location: usize,
+    // the code actions offered last, picked with :codeaction
+    code_actions: Vec<lsp::CodeAction>,
+    // the insert mode completion menu, when it's open
+    completion: Option<complete::Menu>,
+    // where the menu's candidates come from, besides the
+    // language server
+    sources: Vec<Box<dyn complete::Source>>,
+    // the snippets for the buffer's filetype, and the one being
+    // filled in
+    snippets: Snippets,
+    snippet: Option<snippet::Session>,
+    // windows drawn over the view
+    floats: Floats,
+    // the float showing hover text and where the cursor was
+    // when it was asked for, it closes when the cursor moves
+    hover: Option<(FloatId, (usize, usize))>,
+    // the float the completion menu is drawn in
+    menu: Option<FloatId>,
+    // the floats are to be taken off the screen without
+    // drawing the rest again
+    dismiss: bool,
+    // `=` was typed, the lines the next motion moves over are
+    // reindented
+    reindent: bool,
+}
+
+fn load_config(args: &Args) -> (Config, Option<String>) {
+    match args.config_path() {
+        Some(p) => {
+            let (cfg, err) = Config::load(p);
+            (cfg, err.map(|e| e.to_string()))
+        },
+        None => (Config::default(), None),
+    }
+}
 
 fn main() {
-    let mut buf = Buffer::from("hello\nworld");
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}", msg);
+            process::exit(2);
+        },
+    };
+
+    let (mut config, mut message) = load_config(&args);
+
+    let mut buf = match &args.file {
+        Some(path) => match Buffer::open(path) {
+            Ok(buf) => buf,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                process::exit(1);
+            },
+        },
+        None => Buffer::from("hello\nworld"),
+    };
+
+    let ec = match &args.file {
+        Some(path) => editorconfig::lookup(path).unwrap_or_else(|e| {
+            message.get_or_insert_with(|| format!(".editorconfig: {}", e));
+            Properties::default()
+        }),
+        None => Properties::default(),
+    };
+
+    buf.detect_filetype(&config.filetypes);
+    config.options.apply_line_config(buf.line_config_mut());
+    if let Some(ft) = buf.file_config().filetype.as_deref().and_then(filetype::get) {
+        ft.apply_line_config(buf.line_config_mut());
+    }
+    ec.apply_line_config(buf.line_config_mut());
+    ec.apply_file_config(buf.file_config_mut());
 
     let stdout = io::stdout();
-    let stdin = io::stdin();
 
     let mut view0 = View::new(&mut buf);
-    let mut r = TerminalRenderer::new(stdout, None, None).unwrap();
+    config.options.apply_view_config(view0.view_config_mut());
+    if let Err(msg) = config.load_highlighter(&mut view0) {
+        message.get_or_insert(msg);
+    }
+    let mut r = match TerminalRenderer::new(stdout, None, None, config.options.alt_screen) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("editor: {}", e);
+            process::exit(1);
+        },
+    };
+    let mut events = EventLoop::new();
+    if let Err(e) = events.listen() {
+        // exit skips drops, the terminal is restored first
+        drop(r);
+        eprintln!("editor: {}", e);
+        process::exit(1);
+    }
+    let lsp = start_lsp(&config, &view0, &events).unwrap_or_else(|msg| {
+        message.get_or_insert(msg);
+        None
+    });
+    let snippets = load_snippets(&config, &view0).unwrap_or_else(|msg| {
+        message.get_or_insert(msg);
+        Snippets::default()
+    });
 
-    r.set_xy(0, 0).unwrap();
+    let mut state = State{
+        mode: Mode::Normal,
+        cmdline: None,
+        message,
+        pending: Vec::new(),
+        pending_timer: None,
+        editorconfig: ec,
+        quit: false,
+        terminated: false,
+        lsp,
+        locations: Vec::new(),
+        location: 0,
+        code_actions: Vec::new(),
+        completion: None,
+        sources: vec![Box::new(complete::Words), Box::new(complete::Paths)],
+        snippets,
+        snippet: None,
+        floats: Floats::default(),
+        hover: None,
+        menu: None,
+        dismiss: false,
+        reindent: false,
+    };
 
-    let mut edit_mode = false;
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        run(&mut r, &mut events, &mut state, &mut config, &args, &mut view0)
+    }));
+    // restores the terminal, if a panic didn't already
+    mem::drop(r);
 
-    for c in stdin.keys() {
-        if edit_mode {
-            match c.unwrap() {
-                Key::Esc => {
-                    edit_mode = false;
-                },
-                Key::Backspace => {
-                    view0.backspace();
-                },
-                Key::Char(c) => {
-                    match c {
-                        '\n' | '\r' => {
-                            view0.insert_line_below();
-                            view0.first_col();
-                            view0.scroll_rel(1);
-                        },
-                        '\t' => {
-                            view0.tab();
-                        }
-                        _ => {
-                            let mut buf: [u8;8] = [0;8];
-                            let cs = c.encode_utf8(&mut buf);
-                            view0.insert_str(cs);
-                        }
-                    }
+    let failed = match res {
+        Ok(Ok(())) => state.terminated,
+        Ok(Err(e)) => {
+            eprintln!("editor: {}", e);
+            true
+        },
+        Err(_) => true,
+    };
+    if failed {
+        match view0.write_recovery() {
+            Ok(Some(path)) => eprintln!("editor: unsaved changes written to {}", path.display()),
+            Ok(None) => {},
+            Err(e) => eprintln!("editor: writing recovery copy: {}", e),
+        }
+        process::exit(1);
+    }
+
+    if args.dump {
+        println!("buffer: ");
+        buf.collate(&mut io::stdout()).unwrap();
+    }
+}
+
+fn run(r: &mut TerminalRenderer, events: &mut EventLoop, state: &mut State,
+       config: &mut Config, args: &Args, view: &mut View) -> Result<()> {
+
+    sync_lsp(state, view);
+    draw(r, view, state, config)?;
+
+    while let Some(ev) = events.wait() {
+        match ev {
+            Event::Key(key) => {
+                state.message = None;
+                if let Some(id) = state.pending_timer.take() {
+                    events.cancel_timer(id);
                 }
-                _ => {},
-            }
-        } else {
-            match c.unwrap() {
-                Key::Char('q') => break,
-                // move and insert
-                Key::Char('i') => {
-                    edit_mode = true;
-                    //r.debug("going to insert mode");
-                },
-                Key::Char('I') => {
-                    view0.first_non_space_col();
-                    edit_mode = true;
-                },
-                Key::Char('o') => {
-                    view0.insert_line_below();
-                    view0.scroll_rel(1);
-                    edit_mode = true;
-                },
-                Key::Char('O') => {
-                    view0.insert_line_above();
-                    view0.scroll_rel(-1);
-                    edit_mode = true;
+
+                if state.cmdline.is_some() {
+                    command_key(state, config, args, events, view, key);
+                } else {
+                    state.pending.push(key);
+                    dispatch(state, config, view, false);
                 }
-                Key::Char('a') => {
-                    view0.next_col();
-                    edit_mode = true;
-                },
-                Key::Char('A') => {
-                    view0.last_col();
-                    edit_mode = true;
-                },
-                // movement
-                Key::Char('0') => {
-                    view0.first_col();
-                },
-                Key::Char('w') => {
-                    view0.next_word();
-                },
-                Key::Char('b') => {
-                    view0.prev_word();
-                },
-                Key::Char('h') => {
-                    // left
-                    view0.prev_col();
-                },
-                Key::Char('j') => {
-                    // down
-                    view0.scroll_rel(1);
-                },
-                Key::Char('k') => {
-                    // down
-                    view0.scroll_rel(-1);
-                },
-                Key::Char('l') => {
-                    // right
-                    view0.next_col();
+
+                if !state.pending.is_empty() {
+                    let after = Duration::from_millis(config.options.timeout_len);
+                    state.pending_timer = Some(events.set_timer(after));
+                }
+            },
+            Event::Mouse(m) => mouse(state, view, m),
+            Event::Paste(text) => {
+                state.message = None;
+                match state.cmdline.as_mut() {
+                    Some(cmdline) => cmdline.push_str(text.lines().next().unwrap_or("")),
+                    None => {
+                        clear_placeholder(state, view);
+                        view.insert_text(&text);
+                    },
+                }
+            },
+            Event::Resize => {
+                r.resize()?;
+            },
+            Event::Timer(id) => {
+                if state.pending_timer == Some(id) {
+                    state.pending_timer = None;
+                    dispatch(state, config, view, true);
+                }
+            },
+            Event::Terminate => {
+                state.terminated = true;
+                state.quit = true;
+            },
+            Event::Message(msg) => {
+                if let Ok(inc) = msg.downcast::<lsp::Incoming>() {
+                    lsp_message(state, view, *inc);
                 }
-                _ => {}
+            },
+        }
+
+        if state.quit {
+            break;
+        }
+
+        update_snippet(state, view);
+        update_completion(state, view);
+        sync_lsp(state, view);
+        if state.dismiss {
+            state.dismiss = false;
+            dismiss_floats(r, state, config)?;
+            continue;
+        }
+        if let Some((id, at)) = state.hover {
+            if view.cursor() != at {
+                state.floats.remove(id);
+                state.hover = None;
             }
         }
+        draw(r, view, state, config)?;
+    }
+
+    Ok(())
+}
+
+// the window takes the screen but the last row, which has
+// the command line, and has its status line in its last row
+fn draw(r: &mut TerminalRenderer, view: &mut View, state: &mut State,
+        config: &Config) -> Result<()> {
 
-        r.clear().unwrap();
+    r.clear()?;
 
-        r.set_xy(0, 0).unwrap();
-        r.goto().unwrap();
-        view0.render(&mut r).unwrap();
+    let (width, height) = (r.width(), r.height());
+    let status = height >= 3;
+    let rows = height.saturating_sub(1 + status as usize);
 
-        let cur = r.save_curs();
-        r.set_xy(0, (r.height-1) as usize).unwrap();
-        r.goto().unwrap();
-        status_line(&mut r, edit_mode).unwrap();
+    r.set_xy(0, 0)?;
+    view.render(&mut Region::new(r, width, rows))?;
+    let cur = r.save_curs();
+
+    show_completion(state, view);
+
+    if status {
+        r.set_xy(0, rows)?;
+        let filetype = view.file_config().filetype.clone();
+        let mode = state.mode.name().to_uppercase();
+        let info = StatusInfo::new(view, &mode, filetype.as_deref());
+        StatusLine::new(&config.options.status_line).render(r, &info, &config.theme)?;
+    }
+
+    r.set_xy(0, height.saturating_sub(1))?;
+    command_line(r, state, &config.theme)?;
+
+    // the view is at the top left, so its positions are the
+    // screen's
+    state.floats.render(r.grid_mut(), &config.theme, |idx, col| view.screen_pos(idx, col));
+
+    if state.cmdline.is_none() {
         r.load_curs(cur);
+    }
 
-        r.flush().unwrap();
+    r.flush()
+}
+
+// keeps the completion menu's float in step with the menu, it
+// starts under the start of the word
+fn show_completion(state: &mut State, view: &View) {
+    let float = state.completion.as_ref().filter(|m| !m.is_empty()).map(|menu| {
+        let (col, idx) = view.cursor();
+        let (_, i) = view.cursor_byte();
+        let word = view.line(idx)
+            .and_then(|l| l.get(menu.start.1..i))
+            .map_or(0, UnicodeWidthStr::width);
+        menu.float(Anchor::Buffer(idx, col.saturating_sub(word)))
+    });
+
+    match (state.menu.and_then(|id| state.floats.get_mut(id)), float) {
+        (Some(f), Some(float)) => *f = float,
+        (None, Some(float)) => state.menu = Some(state.floats.open(float)),
+        (_, None) => if let Some(id) = state.menu.take() {
+            state.floats.remove(id);
+        },
     }
+}
 
-    mem::drop(r);
+// takes the floats off the last frame, which leaves what was
+// under them, and sends only those cells
+fn dismiss_floats(r: &mut TerminalRenderer, state: &mut State, config: &Config) -> Result<()> {
+    let cur = r.save_curs();
+    state.floats.close_all(r.grid_mut());
+    state.hover = None;
+    state.menu = None;
+
+    // the message went with the key
+    r.set_xy(0, r.height().saturating_sub(1))?;
+    command_line(r, state, &config.theme)?;
+    r.load_curs(cur);
+    r.flush()
+}
+
+// resolves the pending keys against the keymap. with timeout
+// set, keys which only start a mapping are not waited on
+fn dispatch(state: &mut State, config: &Config, view: &mut View, timeout: bool) {
+    while !state.pending.is_empty() {
+        match config.keymap.lookup(state.mode, &state.pending) {
+            Lookup::Action(a) => {
+                state.pending.clear();
+                run_action(state, view, a);
+                return;
+            },
+            // a longer binding could still come
+            Lookup::ActionOrPrefix(_) | Lookup::Prefix if !timeout => return,
+            Lookup::ActionOrPrefix(a) => {
+                state.pending.clear();
+                run_action(state, view, a);
+                return;
+            },
+            Lookup::Prefix | Lookup::None => {},
+        }
+
+        // the keys run the longest binding they start with, like
+        // `g` waiting for `gg` and getting `gx`
+        if let Some((n, a)) = config.keymap.lookup_start(state.mode, &state.pending) {
+            state.pending.drain(..n);
+            run_action(state, view, a);
+            continue;
+        }
+
+        // the first key can't start a mapping, handle it on its
+        // own and try again with the rest
+        let key = state.pending.remove(0);
+        if state.mode == Mode::Insert {
+            if let Some(c) = key.text() {
+                let mut buf: [u8;8] = [0;8];
+                clear_placeholder(state, view);
+                view.insert_str(c.encode_utf8(&mut buf));
+                view.reindent_closer();
+            }
+        }
+    }
+}
+
+fn run_action(state: &mut State, view: &mut View, action: Action) {
+    // after `=` a motion, or `=` again for the cursor line
+    if mem::take(&mut state.reindent) && (is_motion(action) || action == Action::Reindent) {
+        let from = view.cursor().1;
+        if action != Action::Reindent {
+            run_action(state, view, action);
+        }
+        let to = view.cursor().1;
+        view.reindent(from.min(to), from.max(to));
+        view.commit_undo();
+        return;
+    }
+
+    // the arrows and enter pick from the completion menu while
+    // it has something in it
+    let action = match (action, &state.completion) {
+        (Action::Up, Some(menu)) if !menu.is_empty() => Action::CompletePrev,
+        (Action::Down, Some(menu)) if !menu.is_empty() => Action::CompleteNext,
+        (Action::Newline, Some(menu)) if !menu.is_empty() => Action::CompleteAccept,
+        (a, _) => a,
+    };
+    // tab moves between a snippet's tabstops, or expands the
+    // snippet named by the word before the cursor
+    let action = match action {
+        Action::Tab if state.snippet.is_some() => Action::NextTabstop,
+        Action::Tab if trigger(state, view).is_some() => Action::ExpandSnippet,
+        a => a,
+    };
+
+    match action {
+        Action::Quit => state.quit = true,
+        // escape in normal mode closes the floats
+        Action::NormalMode if state.mode == Mode::Normal && !state.floats.is_empty() => {
+            state.dismiss = true;
+        },
+        Action::NormalMode => {
+            state.completion = None;
+            state.snippet = None;
+            view.clear_anchor();
+            state.mode = Mode::Normal;
+        },
+        Action::CommandMode => state.cmdline = Some(String::new()),
+        Action::Insert => state.mode = Mode::Insert,
+        Action::InsertFirstNonSpace => {
+            view.first_non_space_col();
+            state.mode = Mode::Insert;
+        },
+        Action::Append => {
+            view.next_col();
+            state.mode = Mode::Insert;
+        },
+        Action::AppendEnd => {
+            view.last_col();
+            state.mode = Mode::Insert;
+        },
+        Action::OpenBelow => {
+            view.open_below();
+            state.mode = Mode::Insert;
+        },
+        Action::OpenAbove => {
+            view.open_above();
+            state.mode = Mode::Insert;
+        },
+        Action::FirstCol => view.first_col(),
+        Action::NextWord => view.next_word(),
+        Action::PrevWord => view.prev_word(),
+        Action::Left => view.prev_col(),
+        Action::Right => view.next_col(),
+        Action::Up => view.scroll_rel(-1),
+        Action::Down => view.scroll_rel(1),
+        Action::UpRow => view.prev_row(),
+        Action::DownRow => view.next_row(),
+        // scrolling moves the text of the top float, while
+        // there is one
+        Action::ScrollUp | Action::ScrollDown if !state.floats.is_empty() => {
+            let n = if action == Action::ScrollDown { 1 } else { -1 };
+            if let Some(f) = state.floats.top_mut() {
+                f.scroll(n);
+            }
+        },
+        Action::ScrollUp => view.scroll_view(-1),
+        Action::ScrollDown => view.scroll_view(1),
+        Action::HalfPageUp => view.scroll_half_page(-1),
+        Action::HalfPageDown => view.scroll_half_page(1),
+        Action::PageUp => view.scroll_page(-1),
+        Action::PageDown => view.scroll_page(1),
+        Action::ScrollCursorTop => view.scroll_cursor_top(),
+        Action::ScrollCursorCenter => view.scroll_cursor_center(),
+        Action::ScrollCursorBottom => view.scroll_cursor_bottom(),
+        // backspace takes out a selected placeholder
+        Action::Backspace if state.snippet.as_ref().is_some_and(|s| s.is_fresh()) => {
+            clear_placeholder(state, view);
+        },
+        Action::Backspace => view.backspace(),
+        Action::Newline => view.newline(),
+        Action::Tab => view.tab(),
+        Action::NextFunction => structural(state, view.goto_object("function.outer", true)),
+        Action::PrevFunction => structural(state, view.goto_object("function.outer", false)),
+        Action::ExpandSelection => structural(state, view.expand_selection()),
+        Action::SelectFunction => structural(state, view.select_object("function.outer")),
+        Action::SelectFunctionInner => structural(state, view.select_object("function.inner")),
+        Action::SelectClass => structural(state, view.select_object("class.outer")),
+        Action::SelectClassInner => structural(state, view.select_object("class.inner")),
+        Action::SelectArgument => structural(state, view.select_object("parameter.outer")),
+        Action::Hover => lsp_request(state, view, lsp::Client::hover),
+        Action::GotoDefinition => lsp_request(state, view, lsp::Client::definition),
+        Action::References => lsp_request(state, view, lsp::Client::references),
+        Action::CodeAction => lsp_request(state, view, lsp::Client::code_actions),
+        Action::Complete => open_completion(state, view),
+        Action::CompleteNext | Action::CompletePrev => {
+            let n = if action == Action::CompleteNext { 1 } else { -1 };
+            if state.completion.is_none() {
+                open_completion(state, view);
+                // the first candidate is picked going forward
+                // and the last going back
+                if n > 0 {
+                    return;
+                }
+            }
+            if let Some(menu) = state.completion.as_mut() {
+                menu.select(n);
+            }
+        },
+        Action::CompleteAccept => accept_completion(state, view),
+        Action::CompleteCancel => state.completion = None,
+        Action::NextLocation => next_location(state, view, 1),
+        Action::PrevLocation => next_location(state, view, -1),
+        Action::ExpandSnippet => match trigger(state, view) {
+            Some((start, body)) => {
+                view.replace(start, view.cursor_byte(), "");
+                expand_snippet(state, view, &body);
+            },
+            None => state.message = Some("no snippet".into()),
+        },
+        // reindents the selection, or waits for a motion
+        Action::Reindent => match view.selection() {
+            Some(((first, _), (last, _))) => {
+                view.reindent(first, last);
+                view.clear_anchor();
+            },
+            None => state.reindent = true,
+        },
+        Action::NextTabstop | Action::PrevTabstop => {
+            let n = if action == Action::NextTabstop { 1 } else { -1 };
+            if let Some(session) = state.snippet.as_mut() {
+                if !session.jump(view, n) {
+                    state.snippet = None;
+                }
+                open_choices(state);
+            }
+        },
+        Action::Undo => {
+            if !view.undo() {
+                state.message = Some("nothing to undo".into());
+            }
+        },
+    }
+
+    // what's typed in insert mode is undone together, anything
+    // else on its own
+    if state.mode != Mode::Insert {
+        view.commit_undo();
+    }
+}
 
-    println!("{}", "buffer: ");
-    buf.collate(&mut io::stdout()).unwrap();
+// the actions which only move the cursor
+fn is_motion(action: Action) -> bool {
+    matches!(action,
+        Action::FirstCol | Action::NextWord | Action::PrevWord | Action::Left | Action::Right |
+        Action::Up | Action::Down | Action::UpRow | Action::DownRow | Action::HalfPageUp |
+        Action::HalfPageDown | Action::PageUp | Action::PageDown | Action::NextFunction |
+        Action::PrevFunction)
 }
 
-fn status_line<R: Renderer>(r: &mut R, edit_mode : bool) -> Result<()> {
-    if edit_mode {
-        r.write("edit")?;
+// the start of the word before the cursor and the body of the
+// snippet it's the trigger of
+fn trigger(state: &State, view: &View) -> Option<((usize, usize), String)> {
+    let start = view.word_start();
+    let (idx, i) = view.cursor_byte();
+    let word = view.line(idx)?.get(start.1..i)?;
+    state.snippets.body(word).map(|body| (start, body.to_string()))
+}
+
+fn expand_snippet(state: &mut State, view: &mut View, body: &str) {
+    state.snippet = Snippet::parse(body).expand(view);
+    open_choices(state);
+}
+
+// the choices at the current tabstop are offered in the
+// completion menu
+fn open_choices(state: &mut State) {
+    state.completion = None;
+    let session = match &state.snippet {
+        Some(session) if !session.choices().is_empty() => session,
+        _ => return,
+    };
+    let items = session.choices().iter()
+        .map(|c| complete::Candidate::new(c, "choice"))
+        .collect();
+    state.completion = Some(complete::Menu::new(session.start(), "", items));
+}
+
+// a snippet's selected placeholder goes before anything is
+// typed over it
+fn clear_placeholder(state: &mut State, view: &mut View) {
+    if let Some(session) = state.snippet.as_mut() {
+        session.clear_placeholder(view);
+    }
+}
+
+// the session follows edits in the current tabstop, and ends
+// when the cursor leaves the snippet
+fn update_snippet(state: &mut State, view: &mut View) {
+    let alive = match state.snippet.as_mut() {
+        Some(session) => session.update(view),
+        None => return,
+    };
+    if !alive {
+        state.snippet = None;
+    }
+}
+
+// snippets are only there when the file is
+fn load_snippets(config: &Config, view: &View) -> std::result::Result<Snippets, String> {
+    let snippets = view.file_config().filetype.as_deref()
+        .and_then(|ft| config.snippet_path(ft))
+        .filter(|p| p.exists())
+        .map(Snippets::load)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    Ok(snippets.unwrap_or_default())
+}
+
+// motions on the syntax tree say so when they can't move
+fn structural(state: &mut State, moved: bool) {
+    if !moved {
+        state.message = Some("no syntax node".into());
+    }
+}
+
+// starts the language server for the view's filetype, if
+// there is one
+fn start_lsp(config: &Config, view: &View, events: &EventLoop) ->
+    std::result::Result<Option<lsp::Client>, String> {
+
+    let (ft, path) = match &view.file_config().filetype {
+        Some(ft) => match &view.file_config().path {
+            Some(path) => (ft.clone(), path.clone()),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let cmd = match config.server(&ft) {
+        Some(cmd) => cmd,
+        None => return Ok(None),
+    };
+
+    match lsp::Client::start(&ft, &cmd, &lsp::root_dir(&path), events.handle()) {
+        Ok(client) => Ok(Some(client)),
+        // the filetype's own server is only used when it's
+        // installed
+        Err(Error::Io(e)) if e.kind() == ErrorKind::NotFound && !config.has_server(&ft) => {
+            Ok(None)
+        },
+        Err(e) => Err(format!("{}: {}", cmd[0], e)),
+    }
+}
+
+fn lsp_client(state: &mut State) -> std::result::Result<&mut lsp::Client, String> {
+    state.lsp.as_mut().ok_or_else(|| "no language server".to_string())
+}
+
+// tells the server about edits since the last time
+fn sync_lsp(state: &mut State, view: &mut View) {
+    if let Some(client) = state.lsp.as_mut() {
+        if let Err(msg) = client.sync(view) {
+            state.message.get_or_insert(msg);
+        }
+    }
+}
+
+// sends a request, the reply comes back as a message later
+fn lsp_request(state: &mut State, view: &mut View,
+               request: fn(&mut lsp::Client, &mut View) -> std::result::Result<(), String>) {
+
+    if let Err(msg) = lsp_client(state).and_then(|c| request(c, view)) {
+        state.message = Some(msg);
+    }
+}
+
+// handles a message from the language server
+fn lsp_message(state: &mut State, view: &mut View, inc: lsp::Incoming) {
+    let client = match state.lsp.as_mut() {
+        Some(client) if client.name == inc.server => client,
+        _ => return,
+    };
+    let reply = match inc.msg {
+        Some(msg) => client.handle(msg),
+        None => {
+            state.lsp = None;
+            state.message = Some(format!("{}: server exited", inc.server));
+            return;
+        },
+    };
+
+    match reply {
+        Some(Reply::Diagnostics(path, diagnostics)) => {
+            let shown = view.file_config().path.as_deref()
+                .is_some_and(|p| lsp::same_file(p, &path));
+            if shown {
+                client.show_diagnostics(view, &diagnostics);
+            }
+        },
+        Some(Reply::Hover(text)) => {
+            // without the lines around code blocks
+            let text: Vec<&str> = text.lines()
+                .filter(|l| !l.trim_start().starts_with("```"))
+                .collect();
+            let text = text.join("\n");
+            if text.trim().is_empty() {
+                state.message = Some("no information".into());
+                return;
+            }
+
+            if let Some((id, _)) = state.hover.take() {
+                state.floats.remove(id);
+            }
+            let (col, idx) = view.cursor();
+            let id = state.floats.open(Float::new(Anchor::Buffer(idx, col), &text));
+            state.hover = Some((id, (col, idx)));
+        },
+        Some(Reply::Definition(locations)) | Some(Reply::References(locations)) => {
+            state.locations = locations;
+            state.location = 0;
+            goto_location(state, view);
+        },
+        Some(Reply::Completion(items)) => {
+            if let Some(menu) = state.completion.as_mut() {
+                let range = |e: &lsp::TextEdit| {
+                    (client.to_byte(view, e.range.start), client.to_byte(view, e.range.end))
+                };
+                let items = items.iter()
+                    .map(|item| complete::Candidate::from_lsp(item, item.edit.as_ref().map(range)))
+                    .collect();
+                menu.add(items);
+                if menu.is_empty() {
+                    state.completion = None;
+                    state.message = Some("no completions".into());
+                }
+            }
+        },
+        Some(Reply::Rename(edit)) | Some(Reply::ApplyEdit(edit)) => {
+            state.message = match client.apply_workspace_edit(view, &edit) {
+                Ok(n) => Some(format!("{} files changed", n)),
+                Err(msg) => Some(msg),
+            };
+        },
+        Some(Reply::CodeActions(actions)) => match actions.len() {
+            0 => state.message = Some("no code actions".into()),
+            1 => apply_code_action(state, view, &actions[0]),
+            _ => {
+                let titles: Vec<String> = actions.iter()
+                    .enumerate()
+                    .map(|(i, a)| format!("{}: {}", i + 1, a.title))
+                    .collect();
+                state.message = Some(format!("{} (:codeaction N)", titles.join(", ")));
+                state.code_actions = actions;
+            },
+        },
+        Some(Reply::Message(msg)) | Some(Reply::Error(msg)) => state.message = Some(msg),
+        None => {},
+    }
+}
+
+// opens the completion menu with what the sources have for
+// the word before the cursor, the language server's candidates
+// are added when they come
+fn open_completion(state: &mut State, view: &mut View) {
+    let start = view.word_start();
+    let (idx, i) = view.cursor_byte();
+    let line = view.line(idx).unwrap_or("");
+    let dir = view.file_config().path.as_deref()
+        .and_then(Path::parent)
+        .filter(|d| !d.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .or_else(|| env::current_dir().ok())
+        .unwrap_or_default();
+
+    let ctx = complete::Context{
+        lines: view.lines().collect(),
+        before: &line[..i],
+        word: &line[start.1..i],
+        dir,
+    };
+    // snippets come first, the menu leaves out words with the
+    // same text
+    let mut items = state.snippets.candidates(&ctx);
+    items.extend(state.sources.iter_mut().flat_map(|s| s.candidates(&ctx)));
+    let menu = complete::Menu::new(start, ctx.word, items);
+
+    let waiting = match state.lsp.as_mut() {
+        Some(client) => client.completion(view).is_ok(),
+        None => false,
+    };
+    if menu.is_empty() && !waiting {
+        state.message = Some("no completions".into());
+        return;
+    }
+    state.completion = Some(menu);
+}
+
+// the menu follows what's typed of the word, and closes when
+// the cursor leaves it
+fn update_completion(state: &mut State, view: &View) {
+    let menu = match state.completion.as_mut() {
+        Some(menu) => menu,
+        None => return,
+    };
+
+    let start = view.word_start();
+    if state.mode != Mode::Insert || start != menu.start {
+        state.completion = None;
+        return;
+    }
+    let (idx, i) = view.cursor_byte();
+    menu.set_query(view.line(idx).and_then(|l| l.get(start.1..i)).unwrap_or(""));
+}
+
+// the selected candidate replaces the word before the cursor,
+// undone on its own apart from the typing around it
+fn accept_completion(state: &mut State, view: &mut View) {
+    let menu = match state.completion.take() {
+        Some(menu) => menu,
+        None => return,
+    };
+
+    // a choice replaces the placeholder
+    clear_placeholder(state, view);
+    view.commit_undo();
+    if let Some(body) = menu.accept(view) {
+        expand_snippet(state, view, &body);
+    }
+    view.commit_undo();
+}
+
+fn apply_code_action(state: &mut State, view: &mut View, action: &lsp::CodeAction) {
+    let client = match state.lsp.as_mut() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let res = client.apply_workspace_edit(view, &action.edit)
+        .and_then(|_| match &action.command {
+            Some(command) => client.execute(command),
+            None => Ok(()),
+        });
+    if let Err(msg) = res {
+        state.message = Some(msg);
+    }
+}
+
+// moves the cursor to the current location, ones in other
+// files are only shown
+fn goto_location(state: &mut State, view: &mut View) {
+    let (loc, client) = match (state.locations.get(state.location), &state.lsp) {
+        (Some(loc), Some(client)) => (loc, client),
+        _ => {
+            state.message = Some("no locations".into());
+            return;
+        },
+    };
+
+    let which = format!("{} of {}", state.location + 1, state.locations.len());
+    let shown = view.file_config().path.as_deref()
+        .is_some_and(|p| lsp::same_file(p, &loc.path));
+    state.message = if shown {
+        let (idx, i) = client.to_byte(view, loc.range.start);
+        view.goto_byte(idx, i);
+        Some(which)
     } else {
-        r.write("insert")?;
+        Some(format!("{}: {}:{}", which, loc.path.display(), loc.range.start.line + 1))
+    };
+}
+
+fn next_location(state: &mut State, view: &mut View, n: isize) {
+    let len = state.locations.len() as isize;
+    if len > 0 {
+        state.location = (state.location as isize + n).rem_euclid(len) as usize;
     }
+    goto_location(state, view);
+}
+
+// the line config is built up again from the options, the
+// filetype and .editorconfig, so nothing from an old filetype
+// is left over
+fn apply_options(state: &State, config: &Config, view: &mut View) {
+    *view.line_config_mut() = LineConfig::default();
+    config.options.apply_line_config(view.line_config_mut());
+    if let Some(ft) = view.file_config().filetype.as_deref().and_then(filetype::get) {
+        ft.apply_line_config(view.line_config_mut());
+    }
+    state.editorconfig.apply_line_config(view.line_config_mut());
+    config.options.apply_view_config(view.view_config_mut());
+    view.set_theme(&config.theme);
+}
+
+// handles a key typed at the `:` prompt
+fn command_key(state: &mut State, config: &mut Config, args: &Args,
+               events: &EventLoop, view: &mut View, key: Key) {
+
+    let cmdline = state.cmdline.as_mut().unwrap();
+
+    match key.code {
+        KeyCode::Esc => state.cmdline = None,
+        KeyCode::Backspace => {
+            if cmdline.pop().is_none() {
+                state.cmdline = None;
+            }
+        },
+        KeyCode::Enter => {
+            let line = state.cmdline.take().unwrap();
+            if let Err(msg) = run_command(state, config, args, events, view, &line) {
+                state.message = Some(msg);
+            }
+            apply_options(state, config, view);
+        },
+        _ => {
+            if let Some(c) = key.text() {
+                cmdline.push(c);
+            }
+        },
+    }
+}
+
+// clicking places the cursor, dragging selects and the wheel
+// scrolls
+fn mouse(state: &mut State, view: &mut View, m: Mouse) {
+    let (_, rows) = view.size();
+    if m.y >= rows && !matches!(m.kind, MouseKind::ScrollUp | MouseKind::ScrollDown) {
+        return;
+    }
+
+    match m.kind {
+        MouseKind::Press(MouseButton::Left) => {
+            let (idx, col) = view.pos_at(m.x, m.y);
+            view.clear_anchor();
+            view.goto(idx, col);
+        },
+        MouseKind::Drag(MouseButton::Left) => {
+            if view.selection().is_none() {
+                view.set_anchor();
+            }
+            let (idx, col) = view.pos_at(m.x, m.y);
+            view.goto(idx, col);
+        },
+        MouseKind::ScrollUp => view.scroll_view(-3),
+        MouseKind::ScrollDown => view.scroll_view(3),
+        _ => {},
+    }
+}
 
+fn run_command(state: &mut State, config: &mut Config, args: &Args,
+               events: &EventLoop, view: &mut View, line: &str) -> std::result::Result<(), String> {
+
+    match config::parse_command(line) {
+        Some(("q", _)) | Some(("quit", _)) => {
+            state.quit = true;
+            Ok(())
+        },
+        Some(("w", arg)) | Some(("write", arg)) | Some(("wq", arg)) => {
+            let res = if arg.is_empty() {
+                view.save()
+            } else {
+                view.save_as(config::expand_home(arg))
+            };
+            res.map_err(|e| e.to_string())?;
+            if let Some(client) = state.lsp.as_mut() {
+                client.did_save(view)?;
+            }
+
+            state.quit = line.trim().starts_with("wq");
+            Ok(())
+        },
+        Some(("set", arg)) | Some(("se", arg)) if is_filetype_option(arg) => {
+            match arg.split_once('=') {
+                Some((_, ft)) => {
+                    set_filetype(state, config, events, view, ft.trim())?;
+                },
+                None => {
+                    let ft = view.file_config().filetype.as_deref().unwrap_or("");
+                    state.message = Some(format!("filetype={}", ft));
+                },
+            }
+            Ok(())
+        },
+        Some(("colorscheme", "")) | Some(("colo", "")) => {
+            state.message = Some(config.theme.name.clone());
+            Ok(())
+        },
+        Some(("rename", "")) => Err("rename requires a name".into()),
+        Some(("rename", name)) => lsp_client(state)?.rename(view, name),
+        Some(("codeaction", "")) => lsp_client(state)?.code_actions(view),
+        Some(("codeaction", arg)) => {
+            let action = arg.parse::<usize>().ok()
+                .and_then(|n| state.code_actions.get(n.wrapping_sub(1)))
+                .cloned()
+                .ok_or_else(|| format!("no code action {}", arg))?;
+            apply_code_action(state, view, &action);
+            Ok(())
+        },
+        Some(("source", "")) | Some(("so", "")) => {
+            let path = config.path.clone()
+                .or_else(|| args.config_path())
+                .ok_or_else(|| "no config file".to_string())?;
+
+            let (cfg, err) = Config::load(path);
+            *config = cfg;
+            match err {
+                Some(e) => Err(e.to_string()),
+                None => Ok(()),
+            }
+        },
+        Some(("source", arg)) | Some(("so", arg)) => {
+            config.source(config::expand_home(arg))
+                .map_err(|e| e.to_string())
+        },
+        _ => config.exec(line),
+    }
+}
+
+// everything which follows from the filetype is set up again
+// for the new one, its line config goes between the options
+// and .editorconfig like at startup
+fn set_filetype(state: &mut State, config: &Config, events: &EventLoop,
+                view: &mut View, ft: &str) -> std::result::Result<(), String> {
+
+    view.file_config_mut().filetype = Some(ft.to_string())
+        .filter(|ft| !ft.is_empty());
+    apply_options(state, config, view);
+    config.load_highlighter(view)?;
+    state.snippets = load_snippets(config, view)?;
+
+    // the old server's diagnostics go with it
+    if let Some(client) = state.lsp.take() {
+        client.show_diagnostics(view, &[]);
+    }
+    state.lsp = start_lsp(config, view, events)?;
     Ok(())
 }
 
+// the filetype is set per buffer, not in the options
+fn is_filetype_option(arg: &str) -> bool {
+    let name = arg.split('=').next().unwrap_or("").trim();
+    name == "filetype" || name == "ft"
+}
+
+fn command_line<R: Renderer>(r: &mut R, state: &State, theme: &Theme) -> Result<()> {
+    let normal = theme.style("Normal");
+    let text = match (&state.cmdline, &state.message) {
+        (Some(cmdline), _) => format!(":{}", cmdline),
+        (None, Some(msg)) => msg.clone(),
+        (None, None) => String::new(),
+    };
 
+    // padded so a background fills the row, the cursor is left
+    // after the text
+    let pad = r.width().saturating_sub(UnicodeWidthStr::width(text.as_str()));
+    r.write_styled(&text, normal)?;
+    r.write_styled(&" ".repeat(pad), normal)?;
+    r.move_x(-(pad as isize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<Args, String> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn arguments() {
+        let args = parse(&["--dump", "a.txt"]).unwrap();
+        assert!(args.dump && !args.clean);
+        assert_eq!(args.file, Some("a.txt".into()));
+
+        let args = parse(&["--clean"]).unwrap();
+        assert!(args.clean && !args.dump);
+        assert_eq!(args.config_path(), None);
+
+        // -u wins over --clean
+        let args = parse(&["--clean", "-u", "my.conf"]).unwrap();
+        assert_eq!(args.config_path(), Some("my.conf".into()));
+
+        assert!(parse(&["-u"]).is_err());
+        assert!(parse(&["--frob"]).is_err());
+        assert!(parse(&["a.txt", "b.txt"]).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{
+    LineConfig,
+    SignColumn,
+    ViewConfig,
+    statusline,
+};
+
+// editor wide options, set with `set` in the config file
+// or on the command line
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub tab_width: u8,
+    pub indent_size: u8,
+    pub expand_tab: bool,
+    pub wrap: bool,
+    pub line_break: bool,
+    pub break_indent: bool,
+    pub scroll_off: usize,
+    pub number: bool,
+    pub relative_number: bool,
+    pub sign_column: SignColumn,
+    // milliseconds to wait for the rest of a mapped key
+    // sequence
+    pub timeout_len: u64,
+    // draw on the terminal's alternate screen, read when the
+    // editor starts
+    pub alt_screen: bool,
+    // the format of the status line under each window
+    pub status_line: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        let lcfg = LineConfig::default();
+        let vcfg = ViewConfig::default();
+
+        Options{
+            tab_width: lcfg.tab_width,
+            indent_size: lcfg.indent_size,
+            expand_tab: lcfg.expand_tab,
+            wrap: vcfg.wrap,
+            line_break: vcfg.line_break,
+            break_indent: vcfg.break_indent,
+            scroll_off: vcfg.scroll_off,
+            number: vcfg.number,
+            relative_number: vcfg.relative_number,
+            sign_column: vcfg.sign_column,
+            timeout_len: 1000,
+            alt_screen: true,
+            status_line: statusline::DEFAULT_FORMAT.into(),
+        }
+    }
+}
+
+impl Options {
+    // sets an option from a `name=value`, `name` or `noname`
+    // argument
+    pub fn set(&mut self, arg: &str) -> std::result::Result<(), String> {
+        let (name, value) = match arg.find('=') {
+            Some(idx) => (&arg[..idx], Some(&arg[idx+1..])),
+            None => (arg, None),
+        };
+
+        match name {
+            "tabwidth" | "ts" => {
+                self.tab_width = parse_num(name, value)?;
+            },
+            "indentsize" | "sw" => {
+                self.indent_size = parse_num(name, value)?;
+            },
+            "expandtab" | "et" | "noexpandtab" | "noet" => {
+                self.expand_tab = parse_bool(name, value)?;
+            },
+            "wrap" | "nowrap" => {
+                self.wrap = parse_bool(name, value)?;
+            },
+            "linebreak" | "lbr" | "nolinebreak" | "nolbr" => {
+                self.line_break = parse_bool(name, value)?;
+            },
+            "breakindent" | "bri" | "nobreakindent" | "nobri" => {
+                self.break_indent = parse_bool(name, value)?;
+            },
+            "scrolloff" | "so" => {
+                self.scroll_off = parse_num(name, value)?;
+            },
+            "number" | "nu" | "nonumber" | "nonu" => {
+                self.number = parse_bool(name, value)?;
+            },
+            "relativenumber" | "rnu" | "norelativenumber" | "nornu" => {
+                self.relative_number = parse_bool(name, value)?;
+            },
+            "signcolumn" | "scl" => {
+                let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+                self.sign_column = SignColumn::from_name(value)
+                    .ok_or_else(|| format!("invalid value for {}: {}", name, value))?;
+            },
+            "timeoutlen" | "tm" => {
+                self.timeout_len = parse_num(name, value)?;
+            },
+            "altscreen" | "noaltscreen" => {
+                self.alt_screen = parse_bool(name, value)?;
+            },
+            "statusline" | "stl" => {
+                let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+                self.status_line = value.into();
+            },
+            _ => return Err(format!("unknown option: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    pub fn apply_line_config(&self, lcfg: &mut LineConfig) {
+        lcfg.tab_width = self.tab_width;
+        lcfg.indent_size = self.indent_size;
+        lcfg.expand_tab = self.expand_tab;
+    }
+
+    pub fn apply_view_config(&self, vcfg: &mut ViewConfig) {
+        vcfg.wrap = self.wrap;
+        vcfg.line_break = self.line_break;
+        vcfg.break_indent = self.break_indent;
+        vcfg.scroll_off = self.scroll_off;
+        vcfg.number = self.number;
+        vcfg.relative_number = self.relative_number;
+        vcfg.sign_column = self.sign_column;
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(name: &str, value: Option<&str>) ->
+    std::result::Result<T, String> {
+
+    let value = value.ok_or_else(|| format!("{} requires a value", name))?;
+    value.parse()
+        .map_err(|_| format!("invalid value for {}: {}", name, value))
+}
+
+fn parse_bool(name: &str, value: Option<&str>) ->
+    std::result::Result<bool, String> {
+
+    let negated = name.starts_with("no");
+
+    match value {
+        None => Ok(!negated),
+        Some(_) if negated => Err(format!("{} takes no value", name)),
+        Some("true") | Some("on") => Ok(true),
+        Some("false") | Some("off") => Ok(false),
+        Some(v) => Err(format!("invalid value for {}: {}", name, v)),
+    }
+}
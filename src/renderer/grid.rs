@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+
+use unicode_segmentation::{
+    UnicodeSegmentation,
+};
+
+use unicode_width::{
+    UnicodeWidthStr,
+};
+
+use super::{
+    Style,
+};
+
+
+// a character cell on screen, a wide grapheme takes its
+// cell and the following ones which are left empty with
+// a width of 0
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Cell {
+    pub text: String,
+    pub width: u8,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell{
+            text: " ".into(),
+            width: 1,
+            style: Style::default(),
+        }
+    }
+}
+
+impl Cell {
+    fn continuation() -> Cell {
+        Cell{
+            text: String::new(),
+            width: 0,
+            style: Style::default(),
+        }
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.width == 0
+    }
+}
+
+// the contents of the screen as a grid of cells
+#[derive(Debug,Clone)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Grid {
+        Grid{
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<&Cell> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.cells.get(y * self.width + x)
+    }
+
+    pub fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.width..(y + 1) * self.width]
+    }
+
+    // puts back cells taken from row y, for restoring what was
+    // under something drawn over it
+    pub fn set_row(&mut self, y: usize, cells: &[Cell]) {
+        let n = cells.len().min(self.width);
+        self.cells[y * self.width..y * self.width + n].clone_from_slice(&cells[..n]);
+    }
+
+    // gives the cells from x on for width columns the style,
+    // keeping their text
+    pub fn set_style(&mut self, x: usize, y: usize, width: usize, style: Style) {
+        if y >= self.height {
+            return;
+        }
+        let row = y * self.width;
+        let (a, b) = (x.min(self.width), (x + width).min(self.width));
+        for c in &mut self.cells[row + a..row + b] {
+            c.style = style;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for c in self.cells.iter_mut() {
+            *c = Cell::default();
+        }
+    }
+
+    // writes s starting at x, y and returns the number of
+    // columns written, measuring graphemes like Line::cols.
+    // text past the end of the row is dropped, a wide grapheme
+    // which doesn't fit leaves a blank in the last column.
+    // control characters are drawn escaped so they never reach
+    // the terminal
+    pub fn write(&mut self, x: usize, y: usize, s: &str, style: Style) -> usize {
+        if y >= self.height || x >= self.width {
+            return 0;
+        }
+
+        let s = escape_controls(s);
+        let mut col = x;
+        for g in UnicodeSegmentation::graphemes(s.as_ref(), true) {
+            let w = UnicodeWidthStr::width(g);
+            if w == 0 {
+                self.attach(col, y, g);
+                continue;
+            }
+            if col + w > self.width {
+                if col < self.width {
+                    self.put(col, y, " ", 1, style);
+                    col += 1;
+                }
+                break;
+            }
+
+            self.put(col, y, g, w, style);
+            col += w;
+        }
+
+        col - x
+    }
+
+    // a zero width grapheme like a lone combining mark joins
+    // the grapheme before x, there is nothing to join at the
+    // start of a row
+    fn attach(&mut self, x: usize, y: usize, g: &str) {
+        let row = y * self.width;
+        let mut start = x;
+        while start > 0 {
+            start -= 1;
+            if !self.cells[row + start].is_continuation() {
+                self.cells[row + start].text.push_str(g);
+                return;
+            }
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, g: &str, w: usize, style: Style) {
+        // writing over part of a wide grapheme blanks the rest
+        // of it
+        self.break_wide(x, y);
+        self.break_wide(x + w, y);
+
+        let idx = y * self.width + x;
+        self.cells[idx] = Cell{
+            text: g.into(),
+            width: w as u8,
+            style,
+        };
+        for i in 1..w {
+            self.cells[idx + i] = Cell{
+                style,
+                ..Cell::continuation()
+            };
+        }
+    }
+
+    // blanks the wide grapheme covering x if x is not its
+    // first cell
+    fn break_wide(&mut self, x: usize, y: usize) {
+        if x >= self.width || !self.cells[y * self.width + x].is_continuation() {
+            return;
+        }
+
+        let row = y * self.width;
+        let mut start = x;
+        while start > 0 && self.cells[row + start].is_continuation() {
+            start -= 1;
+        }
+
+        let end = start + self.cells[row + start].width.max(1) as usize;
+        for i in start..end.min(self.width) {
+            self.cells[row + i] = Cell::default();
+        }
+    }
+}
+// control characters in caret notation, ^[ for ESC and ^? for
+// DEL, and the C1 ones as the replacement character
+pub fn escape_controls(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(char::is_control) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut ret = String::with_capacity(s.len() + 1);
+    for c in s.chars() {
+        match c {
+            '\0'..='\x1f' => {
+                ret.push('^');
+                ret.push((c as u8 + 0x40) as char);
+            },
+            '\x7f' => ret.push_str("^?"),
+            '\u{80}'..='\u{9f}' => ret.push('\u{fffd}'),
+            _ => ret.push(c),
+        }
+    }
+
+    Cow::Owned(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(g: &Grid, y: usize) -> String {
+        g.row(y).iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        let mut g = Grid::new(10, 1);
+
+        assert_eq!(g.write(0, 0, "a\x1b[2Jb\r\u{85}", Style::default()), 10);
+        assert_eq!(text(&g, 0), "a^[[2Jb^M\u{fffd}");
+        assert!(g.row(0).iter().all(|c| !c.text.contains(char::is_control)));
+    }
+
+    #[test]
+    fn advances_by_display_width() {
+        let mut g = Grid::new(8, 1);
+
+        assert_eq!(g.write(0, 0, "e\u{301}界🙂", Style::default()), 5);
+        assert_eq!(text(&g, 0), "e\u{301}界🙂   ");
+    }
+
+    #[test]
+    fn zero_width_joins_previous_cell() {
+        let mut g = Grid::new(4, 1);
+        g.write(0, 0, "界", Style::default());
+
+        assert_eq!(g.write(2, 0, "\u{301}", Style::default()), 0);
+        assert_eq!(g.get(0, 0).unwrap().text, "界\u{301}");
+    }
+
+    #[test]
+    fn wide_grapheme_at_right_edge() {
+        let mut g = Grid::new(4, 1);
+        g.write(0, 0, "abcd", Style::default());
+
+        assert_eq!(g.write(1, 0, "界界", Style::default()), 3);
+        assert_eq!(text(&g, 0), "a界 ");
+        assert_eq!(g.write(4, 0, "x", Style::default()), 0);
+    }
+}
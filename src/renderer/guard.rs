@@ -0,0 +1,105 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    panic,
+    sync::{
+        Mutex,
+        Once,
+    },
+};
+
+use crate::{
+    Result,
+    input,
+};
+
+
+// the terminal settings from before raw mode and whether the
+// alternate screen is in use, None when the terminal is
+// already restored
+static SAVED: Mutex<Option<(libc::termios, bool)>> = Mutex::new(None);
+static HOOK: Once = Once::new();
+
+const ENTER_ALT_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_ALT_SCREEN: &str = "\x1b[?1049l";
+
+// keeps the terminal in raw mode, optionally on the alternate
+// screen so the shell's output is still there afterwards, and
+// puts it back the way it was when dropped or when the program
+// panics
+#[derive(Debug)]
+pub struct TerminalGuard {
+    _private: (),
+}
+
+impl TerminalGuard {
+    pub fn new(alt_screen: bool) -> Result<TerminalGuard> {
+        let fd = libc::STDOUT_FILENO;
+
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            let mut t: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut t) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            let orig = t;
+
+            libc::cfmakeraw(&mut t);
+            if libc::tcsetattr(fd, libc::TCSADRAIN, &t) != 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+            *saved = Some((orig, alt_screen));
+        }
+        drop(saved);
+
+        // restore before the panic message is printed so it
+        // ends up on the normal screen
+        HOOK.call_once(|| {
+            let prev = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore();
+                prev(info);
+            }));
+        });
+
+        let mut out = io::stdout();
+        if alt_screen {
+            write!(out, "{}", ENTER_ALT_SCREEN)?;
+        }
+        write!(out, "{}", input::ENABLE)?;
+        out.flush()?;
+
+        Ok(TerminalGuard{
+            _private: (),
+        })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// leaves raw mode and the alternate screen, doing nothing if
+// that already happened
+fn restore() {
+    let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+    let (orig, alt_screen) = match saved.take() {
+        Some(saved) => saved,
+        None => return,
+    };
+
+    let mut out = io::stdout();
+    let _ = write!(out, "{}", input::DISABLE);
+    if alt_screen {
+        let _ = write!(out, "{}", LEAVE_ALT_SCREEN);
+    }
+    let _ = out.flush();
+
+    unsafe {
+        libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSADRAIN, &orig);
+    }
+}
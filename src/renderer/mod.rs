@@ -5,12 +5,41 @@ use crate::{
 mod terminal_renderer;
 pub use terminal_renderer::TerminalRenderer;
 
+mod guard;
+pub use guard::TerminalGuard;
+
+mod test_renderer;
+pub use test_renderer::TestRenderer;
+
+mod region;
+pub use region::Region;
+
+mod style;
+pub use style::{
+    Style,
+    Color,
+    ColorDepth,
+};
+
+mod grid;
+pub use grid::{
+    Grid,
+    Cell,
+    escape_controls,
+};
+
 pub trait Renderer {
     fn height(&self) -> usize;
     fn width(&self) -> usize;
 
     // write the string
-    fn write(&mut self, s: &str) -> Result<()>;
+    fn write(&mut self, s: &str) -> Result<()> {
+        self.write_styled(s, Style::default())
+    }
+
+    // write the string with colors and attributes, renderers
+    // which can't show a style ignore it
+    fn write_styled(&mut self, s: &str, style: Style) -> Result<()>;
 
     // move the cursor to the first column
     fn ret(&mut self) -> Result<()>;
@@ -0,0 +1,108 @@
+use unicode_segmentation::{
+    UnicodeSegmentation,
+};
+
+use unicode_width::{
+    UnicodeWidthStr,
+};
+
+use crate::{
+    Error,
+    Renderer,
+    Result,
+    Style,
+};
+
+
+// a rectangle of another renderer, starting where its cursor
+// is when the region is made. text past the right edge is cut
+// off, so views can be laid out next to and above each other
+#[derive(Debug)]
+pub struct Region<'r, R: Renderer> {
+    r: &'r mut R,
+    width: usize,
+    height: usize,
+
+    // cursor, relative to the region
+    x: usize,
+    y: usize,
+}
+
+impl<'r, R: Renderer> Region<'r, R> {
+    pub fn new(r: &'r mut R, width: usize, height: usize) -> Region<'r, R> {
+        Region{
+            r,
+            width,
+            height,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+}
+
+impl<'r, R: Renderer> Renderer for Region<'r, R> {
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn write_styled(&mut self, s: &str, style: Style) -> Result<()> {
+        let room = self.width.saturating_sub(self.x);
+        let mut end = 0;
+        let mut cols = 0;
+
+        for (i, g) in s.grapheme_indices(true) {
+            let w = UnicodeWidthStr::width(g);
+            if cols + w > room {
+                break;
+            }
+            cols += w;
+            end = i + g.len();
+        }
+
+        self.r.write_styled(&s[..end], style)?;
+        self.x += cols;
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<()> {
+        self.r.move_x(-(self.x as isize))?;
+        self.x = 0;
+        Ok(())
+    }
+
+    fn vret(&mut self) -> Result<()> {
+        self.r.move_y(-(self.y as isize))?;
+        self.y = 0;
+        Ok(())
+    }
+
+    fn move_x(&mut self, n: isize) -> Result<()> {
+        let x = self.x as isize + n;
+        if x < 0 {
+            return Err(Error::InvalidCursorSize(x.unsigned_abs()));
+        }
+
+        self.r.move_x(n)?;
+        self.x = x as usize;
+        Ok(())
+    }
+
+    fn move_y(&mut self, n: isize) -> Result<()> {
+        let y = self.y as isize + n;
+        if y < 0 {
+            return Err(Error::InvalidCursorSize(y.unsigned_abs()));
+        }
+
+        self.r.move_y(n)?;
+        self.y = y as usize;
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    env,
+    fmt::{
+        self,
+        Write,
+    },
+};
+
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash,Default)]
+pub enum Color {
+    // the terminal's own foreground or background
+    #[default]
+    Default,
+    // the 16 basic colors, 8-15 are the bright ones
+    Ansi(u8),
+    // the 256 color palette
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const COLOR_NAMES: &[&str] = &[
+    "black", "red", "green", "yellow",
+    "blue", "magenta", "cyan", "white",
+];
+
+// the rgb values xterm uses for the 16 basic colors
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+impl Color {
+    // parses a color name like `red` or `brightred`, a palette
+    // index or `#rrggbb`
+    pub fn parse(s: &str) -> Option<Color> {
+        let s = s.to_ascii_lowercase();
+
+        if s == "default" || s == "none" {
+            return Some(Color::Default);
+        }
+
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 {
+                return None;
+            }
+            let n = u32::from_str_radix(hex, 16).ok()?;
+            return Some(Color::Rgb((n >> 16) as u8, (n >> 8) as u8, n as u8));
+        }
+
+        if let Ok(n) = s.parse::<u8>() {
+            return Some(Color::Indexed(n));
+        }
+
+        let (bright, name) = match s.strip_prefix("bright") {
+            Some(name) => (8, name),
+            None => (0, s.as_str()),
+        };
+        let name = if name == "grey" || name == "gray" { "white" } else { name };
+
+        COLOR_NAMES.iter()
+            .position(|n| *n == name)
+            .map(|i| Color::Ansi(i as u8 + bright))
+    }
+
+    fn rgb(&self) -> Option<(u8, u8, u8)> {
+        match *self {
+            Color::Default => None,
+            Color::Ansi(n) => Some(ANSI_RGB[n as usize % 16]),
+            Color::Indexed(n) if n < 16 => Some(ANSI_RGB[n as usize]),
+            Color::Indexed(n) if n < 232 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let n = n - 16;
+                Some((level(n / 36), level(n / 6 % 6), level(n % 6)))
+            },
+            Color::Indexed(n) => {
+                let v = 8 + (n - 232) * 10;
+                Some((v, v, v))
+            },
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+        }
+    }
+
+    // the closest color the terminal can show
+    pub fn fallback(&self, depth: ColorDepth) -> Color {
+        match (*self, depth) {
+            (Color::Default, _) | (_, ColorDepth::TrueColor) => *self,
+            (Color::Ansi(_), _) => *self,
+            (Color::Indexed(_), ColorDepth::Indexed256) => *self,
+            (Color::Indexed(n), ColorDepth::Ansi16) if n < 16 => Color::Ansi(n),
+            (Color::Rgb(r, g, b), ColorDepth::Indexed256) => {
+                Color::Indexed(nearest(&(r, g, b), 16..=255))
+            },
+            (c, ColorDepth::Ansi16) => {
+                Color::Ansi(nearest(&c.rgb().unwrap(), 0..=15))
+            },
+        }
+    }
+
+    // appends the sgr parameters for the color, base is 30 for
+    // foreground and 40 for background
+    fn sgr(&self, base: u8, out: &mut String) {
+        let _ = match *self {
+            Color::Default => write!(out, ";{}", base + 9),
+            Color::Ansi(n) if n < 8 => write!(out, ";{}", base + n),
+            Color::Ansi(n) => write!(out, ";{}", base + 60 + n % 8),
+            Color::Indexed(n) => write!(out, ";{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => write!(out, ";{};2;{};{};{}", base + 8, r, g, b),
+        };
+    }
+}
+
+// written the way Color::parse reads it
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Color::Default => write!(f, "default"),
+            Color::Ansi(n) if n < 8 => write!(f, "{}", COLOR_NAMES[n as usize]),
+            Color::Ansi(n) => write!(f, "bright{}", COLOR_NAMES[n as usize % 8]),
+            Color::Indexed(n) => write!(f, "{}", n),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+// the palette index in range with the closest rgb value
+fn nearest(rgb: &(u8, u8, u8), range: std::ops::RangeInclusive<u8>) -> u8 {
+    let dist = |n: u8| {
+        let (r, g, b) = Color::Indexed(n).rgb().unwrap();
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+
+    range.min_by_key(|n| dist(*n)).unwrap()
+}
+
+// the colors a terminal supports
+#[derive(Debug,PartialEq,Eq,Clone,Copy)]
+pub enum ColorDepth {
+    Ansi16,
+    Indexed256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    // guesses from $COLORTERM and $TERM
+    pub fn detect() -> ColorDepth {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+
+        let term = env::var("TERM").unwrap_or_default();
+        if term.contains("256color") {
+            return ColorDepth::Indexed256;
+        }
+
+        ColorDepth::Ansi16
+    }
+}
+
+#[derive(Debug,PartialEq,Eq,Clone,Copy,Hash,Default)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn fg(mut self, c: Color) -> Style {
+        self.fg = c;
+        self
+    }
+
+    pub fn bg(mut self, c: Color) -> Style {
+        self.bg = c;
+        self
+    }
+
+    pub fn bold(mut self) -> Style {
+        self.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Style {
+        self.italic = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Style {
+        self.underline = true;
+        self
+    }
+
+    pub fn reverse(mut self) -> Style {
+        self.reverse = true;
+        self
+    }
+
+    // the style drawn on top of base, colors left as the
+    // terminal's are base's
+    pub fn on(mut self, base: Style) -> Style {
+        if self.fg == Color::Default {
+            self.fg = base.fg;
+        }
+        if self.bg == Color::Default {
+            self.bg = base.bg;
+        }
+        self
+    }
+
+    // parses attributes like `fg=red bg=#202020 bold`
+    pub fn parse<S: AsRef<str>>(attrs: &[S]) -> std::result::Result<Style, String> {
+        let mut ret = Style::default();
+
+        for attr in attrs.iter() {
+            let attr = attr.as_ref();
+            match attr.find('=') {
+                Some(idx) => {
+                    let (k, v) = (&attr[..idx], &attr[idx+1..]);
+                    let c = Color::parse(v)
+                        .ok_or_else(|| format!("unknown color: {}", v))?;
+                    match k {
+                        "fg" => ret.fg = c,
+                        "bg" => ret.bg = c,
+                        _ => return Err(format!("unknown color attribute: {}", k)),
+                    }
+                },
+                None => match attr {
+                    "bold" => ret.bold = true,
+                    "italic" => ret.italic = true,
+                    "underline" => ret.underline = true,
+                    "reverse" => ret.reverse = true,
+                    "none" => ret = Style::default(),
+                    _ => return Err(format!("unknown color attribute: {}", attr)),
+                },
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // the escape sequence which sets the terminal to this
+    // style, starting from a reset
+    pub fn sgr(&self, depth: ColorDepth) -> String {
+        let mut ret = String::from("\x1b[0");
+
+        if self.bold {
+            ret.push_str(";1");
+        }
+        if self.italic {
+            ret.push_str(";3");
+        }
+        if self.underline {
+            ret.push_str(";4");
+        }
+        if self.reverse {
+            ret.push_str(";7");
+        }
+        if self.fg != Color::Default {
+            self.fg.fallback(depth).sgr(30, &mut ret);
+        }
+        if self.bg != Color::Default {
+            self.bg.fallback(depth).sgr(40, &mut ret);
+        }
+
+        ret.push('m');
+        ret
+    }
+}
+
+// written the way Style::parse reads it
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut attrs = Vec::new();
+
+        if self.fg != Color::Default {
+            attrs.push(format!("fg={}", self.fg));
+        }
+        if self.bg != Color::Default {
+            attrs.push(format!("bg={}", self.bg));
+        }
+
+        let flags = [
+            (self.bold, "bold"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+            (self.reverse, "reverse"),
+        ];
+        for (set, name) in flags.iter() {
+            if *set {
+                attrs.push(name.to_string());
+            }
+        }
+
+        if attrs.is_empty() {
+            return write!(f, "none");
+        }
+
+        write!(f, "{}", attrs.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_palette_colors() {
+        assert_eq!(nearest(&(255, 0, 0), 0..=15), 9);
+        assert_eq!(nearest(&(205, 0, 0), 16..=255), 160);
+        // the gray ramp is closer than the cube
+        assert_eq!(nearest(&(128, 128, 128), 16..=255), 244);
+    }
+
+    #[test]
+    fn fallback_to_the_color_depth() {
+        let red = Color::Rgb(205, 0, 0);
+        assert_eq!(red.fallback(ColorDepth::TrueColor), red);
+        assert_eq!(red.fallback(ColorDepth::Indexed256), Color::Indexed(160));
+        assert_eq!(red.fallback(ColorDepth::Ansi16), Color::Ansi(1));
+
+        assert_eq!(Color::Indexed(196).fallback(ColorDepth::Indexed256), Color::Indexed(196));
+        assert_eq!(Color::Indexed(196).fallback(ColorDepth::Ansi16), Color::Ansi(9));
+        assert_eq!(Color::Indexed(12).fallback(ColorDepth::Ansi16), Color::Ansi(12));
+        assert_eq!(Color::Ansi(3).fallback(ColorDepth::Ansi16), Color::Ansi(3));
+        assert_eq!(Color::Default.fallback(ColorDepth::Ansi16), Color::Default);
+    }
+
+    #[test]
+    fn sgr_at_each_depth() {
+        let s = Style::default().fg(Color::Rgb(205, 0, 0)).bg(Color::Ansi(12)).bold().reverse();
+        assert_eq!(s.sgr(ColorDepth::TrueColor), "\x1b[0;1;7;38;2;205;0;0;104m");
+        assert_eq!(s.sgr(ColorDepth::Indexed256), "\x1b[0;1;7;38;5;160;104m");
+        assert_eq!(s.sgr(ColorDepth::Ansi16), "\x1b[0;1;7;31;104m");
+
+        assert_eq!(Style::default().sgr(ColorDepth::Ansi16), "\x1b[0m");
+        let s = Style::default().bg(Color::Indexed(250)).italic().underline();
+        assert_eq!(s.sgr(ColorDepth::Indexed256), "\x1b[0;3;4;48;5;250m");
+        assert_eq!(s.sgr(ColorDepth::Ansi16), "\x1b[0;3;4;47m");
+    }
+}
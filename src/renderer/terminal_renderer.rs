@@ -5,23 +5,30 @@ use std::{
     },
 };
 
-use termion::{
-    raw::IntoRawMode,
-};
-
-
 use crate::{
     Error,
     Renderer,
     Result,
+    Style,
+    ColorDepth,
+};
+
+use super::{
+    grid::Grid,
+    TerminalGuard,
 };
 
 
 
 pub struct TerminalRenderer{
-    stdout: termion::raw::RawTerminal<io::Stdout>,
-    pub height: u16,
-    pub width: u16,
+    stdout: io::Stdout,
+    // dropped after everything else, leaving the terminal as
+    // it was
+    _guard: TerminalGuard,
+    height: u16,
+    width: u16,
+    // the size asked for in new, the whole terminal if None
+    want: (Option<usize>, Option<usize>),
 
     // cursor
     cx: u16,
@@ -30,6 +37,16 @@ pub struct TerminalRenderer{
     // origin offset
     ox: u16,
     oy: u16,
+
+    // the frame being drawn and the one on the terminal
+    back: Grid,
+    front: Grid,
+    // the front grid doesn't match the terminal, redraw
+    // everything on the next flush
+    stale: bool,
+    // colors are reduced to what the terminal supports
+    pub depth: ColorDepth,
+    alt_screen: bool,
 }
 
 
@@ -45,12 +62,13 @@ impl std::fmt::Debug for TerminalRenderer {
 }
 
 impl TerminalRenderer {
-    pub fn new(stdout: io::Stdout, h: Option<usize>, w : Option<usize>) ->
-        Result<TerminalRenderer> {
+    // draws on the alternate screen if alt_screen is set,
+    // otherwise over the shell's output
+    pub fn new(stdout: io::Stdout, h: Option<usize>, w : Option<usize>,
+               alt_screen: bool) -> Result<TerminalRenderer> {
 
 
-        let (tw, th) = termion::terminal_size()
-            .map(|(x, y)| (x as usize, y as usize))?;
+        let (tw, th) = terminal_size()?;
 
         let height = h.unwrap_or(th);
 
@@ -64,20 +82,26 @@ impl TerminalRenderer {
             return Err(Error::InvalidRendererSize(width))
         }
 
-        let mut stdout = stdout.into_raw_mode()?;
-        write!(stdout, "{}", termion::clear::All).unwrap();
+        let guard = TerminalGuard::new(alt_screen)?;
 
         let mut ret = TerminalRenderer{
-            stdout: stdout,
+            stdout,
+            _guard: guard,
             height: height as u16,
             width: width as u16,
+            want: (h, w),
             cx: 1,
             cy: 1,
             ox: 1,
             oy: 1,
+            back: Grid::new(width, height),
+            front: Grid::new(width, height),
+            stale: true,
+            depth: ColorDepth::detect(),
+            alt_screen,
         };
 
-        ret.goto()?;
+        ret.flush()?;
 
         Ok(ret)
     }
@@ -90,12 +114,56 @@ impl TerminalRenderer {
         self.load_curs(save);
     }
 
+    // sends the cells which changed since the last flush and
+    // moves the terminal cursor to the renderer's cursor
     pub fn flush(&mut self) -> Result<()> {
+        let mut out = Vec::new();
+
+        if self.stale {
+            write!(out, "{}", termion::clear::All)?;
+            self.front.clear();
+            self.stale = false;
+        }
+
+        diff(&self.front, &self.back, self.depth, &mut out)?;
+        self.front.clone_from(&self.back);
+
+        write!(out, "{}", termion::cursor::Goto(self.cx, self.cy))?;
+        self.stdout.write_all(&out)?;
         self.stdout.flush()?;
 
         Ok(())
     }
 
+    // picks up a new terminal size, returning whether it
+    // changed. the next flush redraws everything and the
+    // cursor is kept, moved inside the screen if it shrank
+    pub fn resize(&mut self) -> Result<bool> {
+        let (tw, th) = terminal_size()?;
+        let height = self.want.0.unwrap_or(th).min(th);
+        let width = self.want.1.unwrap_or(tw).min(tw);
+
+        if height == self.height as usize && width == self.width as usize {
+            return Ok(false);
+        }
+
+        self.height = height as u16;
+        self.width = width as u16;
+        self.back = Grid::new(width, height);
+        self.front = Grid::new(width, height);
+        self.cx = self.cx.min(self.width.max(1));
+        self.cy = self.cy.min(self.height.max(1));
+        self.stale = true;
+
+        Ok(true)
+    }
+
+    // forces a full redraw on the next flush, for when the
+    // terminal was changed by something else
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
     pub fn save_curs(&self) -> (u16, u16) {
         (self.cx, self.cy)
     }
@@ -104,22 +172,18 @@ impl TerminalRenderer {
         let (cx, cy) = cur;
         self.cx = cx;
         self.cy = cy;
-        self.goto().unwrap();
     }
 
+    // clears the frame being drawn, nothing is sent to the
+    // terminal until the next flush
     pub fn clear(&mut self) -> Result<()> {
-        write!(self.stdout, "{}", termion::clear::All)?;
+        self.back.clear();
         Ok(())
     }
 
-    pub fn goto(&mut self) -> Result<()> {
-        write!(self.stdout, "{}",
-               termion::cursor::Goto(
-                   self.cx,
-                   self.cy,
-                   )
-               )?;
-        Ok(())
+    // the frame being drawn, for drawing over directly
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.back
     }
 
     pub fn set_xy(&mut self, x: usize, y: usize) -> Result<()> {
@@ -147,6 +211,16 @@ impl TerminalRenderer {
     }
 }
 
+impl Drop for TerminalRenderer {
+    fn drop(&mut self) {
+        // leave the shell prompt below what was drawn
+        if !self.alt_screen {
+            let _ = write!(self.stdout, "{}\r\n", termion::cursor::Goto(1, self.height.max(1)));
+            let _ = self.stdout.flush();
+        }
+    }
+}
+
 impl Renderer for TerminalRenderer {
     fn height(&self) -> usize {
         self.height as usize
@@ -156,15 +230,14 @@ impl Renderer for TerminalRenderer {
         self.width as usize
     }
 
-    fn write(&mut self, s: &str) -> Result<()> {
-        assert!(s.len() < u16::MAX as usize);
-
-        write!(self.stdout, "{}", termion::clear::CurrentLine)?;
-
-        // TODO(ear7h): trim the string if too long
-        write!(self.stdout, "{}", s)?;
-        // TODO(ear7h): use unicode length
-        self.cx += s.len() as u16;
+    fn write_styled(&mut self, s: &str, style: Style) -> Result<()> {
+        let n = self.back.write(
+            (self.cx - 1) as usize,
+            (self.cy - 1) as usize,
+            s,
+            style);
+        // n is at most the width, which fits in a u16
+        self.cx = self.cx.saturating_add(n as u16);
 
         Ok(())
     }
@@ -172,29 +245,183 @@ impl Renderer for TerminalRenderer {
     // move to leftmost column
     fn ret(&mut self) -> Result<()> {
         self.cx = self.ox;
-        self.goto()
+        Ok(())
     }
 
     // move to upermost row
     fn vret(&mut self) -> Result<()> {
         self.cy = self.oy;
-        self.goto()
+        Ok(())
     }
 
     fn move_x(&mut self, n: isize) -> Result<()> {
-        let tmp = self.cx as isize + n;
-        assert!(tmp > 0 && tmp < u16::MAX as isize);
-        self.cx = tmp as u16;
-        self.goto()
+        self.cx = moved(self.cx, n)?;
+        Ok(())
     }
 
     fn move_y(&mut self, n: isize) -> Result<()> {
-        let tmp = self.cy as isize + n;
-        assert!(tmp > 0 && tmp < u16::MAX as isize);
+        self.cy = moved(self.cy, n)?;
+        Ok(())
+    }
+
+}
+
+fn terminal_size() -> Result<(usize, usize)> {
+    let (w, h) = termion::terminal_size()?;
+    Ok((w as usize, h as usize))
+}
+
+// the 1-based position p moved by n, an error if it leaves
+// the range the terminal can address
+fn moved(p: u16, n: isize) -> Result<u16> {
+    let to = p as isize + n;
+    if to < 1 || to >= u16::MAX as isize {
+        return Err(Error::InvalidCursorSize(to.unsigned_abs()));
+    }
+
+    Ok(to as u16)
+}
+
+// writes the cells of back which aren't the same in front, the
+// frame on the terminal, and leaves the style reset
+fn diff(front: &Grid, back: &Grid, depth: ColorDepth, out: &mut Vec<u8>) -> Result<()> {
+    // where the terminal cursor is, None when unknown
+    let mut at = None;
+    let mut style = Style::default();
+    write!(out, "{}", style.sgr(depth))?;
+
+    for y in 0..back.height() {
+        let mut x = 0;
+        while x < back.width() {
+            let cell = back.get(x, y).unwrap();
+            if cell.is_continuation() || Some(cell) == front.get(x, y) {
+                x += 1;
+                continue;
+            }
+
+            move_to(front, out, at, style, (x, y))?;
+            if cell.style != style {
+                style = cell.style;
+                write!(out, "{}", style.sgr(depth))?;
+            }
+            out.extend_from_slice(cell.text.as_bytes());
+
+            x += cell.width as usize;
+            // the cursor doesn't move past the last column
+            at = if x < back.width() {
+                Some((x, y))
+            } else {
+                None
+            };
+        }
+    }
 
-        self.cy = tmp as u16;
-        self.goto()
+    if style != Style::default() {
+        write!(out, "{}", Style::default().sgr(depth))?;
     }
 
+    Ok(())
 }
 
+// writes the shortest sequence moving the terminal cursor
+// from at to the cell to
+fn move_to(front: &Grid, out: &mut Vec<u8>, at: Option<(usize, usize)>,
+           style: Style, to: (usize, usize)) -> Result<()> {
+
+    let (x, y) = to;
+    let goto = format!("{}", termion::cursor::Goto(x as u16 + 1, y as u16 + 1));
+
+    let (ax, ay) = match at {
+        Some(at) if at == to => return Ok(()),
+        Some(at) => at,
+        None => {
+            out.extend_from_slice(goto.as_bytes());
+            return Ok(());
+        },
+    };
+
+    let mut best = goto;
+
+    if ay == y && ax < x {
+        let right = format!("{}", termion::cursor::Right((x - ax) as u16));
+        if right.len() < best.len() {
+            best = right;
+        }
+
+        // rewriting unchanged cells in the current style can
+        // be shorter than moving over them
+        let gap: Option<String> = (ax..x)
+            .map(|i| front.get(i, y).unwrap())
+            .try_fold(String::new(), |mut s, c| {
+                if c.is_continuation() || c.width > 1 || c.style != style {
+                    return None;
+                }
+                s.push_str(&c.text);
+                Some(s)
+            });
+        if let Some(gap) = gap {
+            if gap.len() < best.len() {
+                best = gap;
+            }
+        }
+    } else if x == 0 && y == ay + 1 {
+        best = "\r\n".into();
+    } else if x == 0 && y == ay {
+        best = "\r".into();
+    }
+
+    out.extend_from_slice(best.as_bytes());
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(front: &Grid, back: &Grid) -> String {
+        let mut out = Vec::new();
+        diff(front, back, ColorDepth::TrueColor, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn only_changed_cells_are_sent() {
+        let mut front = Grid::new(10, 2);
+        front.write(0, 0, "hello", Style::default());
+        front.write(0, 1, "world", Style::default());
+        let mut back = front.clone();
+        assert_eq!(frame(&front, &back), "\x1b[0m");
+
+        back.write(1, 0, "a", Style::default());
+        // the d is already there
+        back.write(4, 1, "d!", Style::default());
+        assert_eq!(frame(&front, &back), "\x1b[0m\x1b[1;2Ha\x1b[2;6H!");
+    }
+
+    #[test]
+    fn gaps_are_rewritten_when_shorter() {
+        let mut front = Grid::new(10, 1);
+        front.write(0, 0, "abcdef", Style::default());
+        let mut back = front.clone();
+        back.write(0, 0, "x", Style::default());
+        back.write(2, 0, "y", Style::default());
+
+        // the unchanged b is sent again instead of moving over it
+        assert_eq!(frame(&front, &back), "\x1b[0m\x1b[1;1Hxby");
+    }
+
+    #[test]
+    fn wide_graphemes_replaced_by_narrow_ones() {
+        let mut front = Grid::new(4, 1);
+        front.write(0, 0, "界b", Style::default());
+
+        // the continuation cell changes too and is sent
+        let mut back = Grid::new(4, 1);
+        back.write(0, 0, "a b", Style::default());
+        assert_eq!(frame(&front, &back), "\x1b[0m\x1b[1;1Ha ");
+
+        // and the wide grapheme covers both cells again
+        assert_eq!(frame(&back, &front), "\x1b[0m\x1b[1;1H界");
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    Error,
+    Renderer,
+    Result,
+    Style,
+};
+
+use super::{
+    grid::Grid,
+};
+
+
+// a renderer which draws into a grid in memory, for tests
+// and anything else that needs a screen without a terminal
+#[derive(Debug,Clone)]
+pub struct TestRenderer {
+    grid: Grid,
+
+    // cursor
+    cx: usize,
+    cy: usize,
+}
+
+impl TestRenderer {
+    pub fn new(width: usize, height: usize) -> TestRenderer {
+        TestRenderer{
+            grid: Grid::new(width, height),
+            cx: 0,
+            cy: 0,
+        }
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cx, self.cy)
+    }
+
+    pub fn set_xy(&mut self, x: usize, y: usize) {
+        self.cx = x;
+        self.cy = y;
+    }
+
+    // a new empty screen of the given size, like a terminal
+    // after a resize
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.grid = Grid::new(width, height);
+        self.cx = self.cx.min(width);
+        self.cy = self.cy.min(height);
+    }
+
+    pub fn clear(&mut self) {
+        self.grid.clear();
+    }
+
+    // the text on screen, one line per row with trailing
+    // spaces removed
+    pub fn dump(&self) -> String {
+        let mut ret = String::new();
+
+        for y in 0..self.grid.height() {
+            let mut line = String::new();
+            for c in self.grid.row(y).iter() {
+                line.push_str(&c.text);
+            }
+
+            ret.push_str(line.trim_end());
+            ret.push('\n');
+        }
+
+        ret
+    }
+
+    // like dump, with text in a style other than the default
+    // wrapped in `<style>` and `</>`
+    pub fn dump_styled(&self) -> String {
+        let mut ret = String::new();
+
+        for y in 0..self.grid.height() {
+            let mut line = String::new();
+            let mut style = Style::default();
+
+            for c in self.grid.row(y).iter() {
+                if c.style != style && !c.is_continuation() {
+                    if style != Style::default() {
+                        line.push_str("</>");
+                    }
+                    if c.style != Style::default() {
+                        line.push_str(&format!("<{}>", c.style));
+                    }
+                    style = c.style;
+                }
+                line.push_str(&c.text);
+            }
+
+            if style != Style::default() {
+                line.push_str("</>");
+            }
+
+            ret.push_str(line.trim_end());
+            ret.push('\n');
+        }
+
+        ret
+    }
+}
+
+impl Renderer for TestRenderer {
+    fn height(&self) -> usize {
+        self.grid.height()
+    }
+
+    fn width(&self) -> usize {
+        self.grid.width()
+    }
+
+    fn write_styled(&mut self, s: &str, style: Style) -> Result<()> {
+        self.cx += self.grid.write(self.cx, self.cy, s, style);
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<()> {
+        self.cx = 0;
+        Ok(())
+    }
+
+    fn vret(&mut self) -> Result<()> {
+        self.cy = 0;
+        Ok(())
+    }
+
+    fn move_x(&mut self, n: isize) -> Result<()> {
+        let x = self.cx as isize + n;
+        if x < 0 {
+            return Err(Error::InvalidCursorSize(x.unsigned_abs()));
+        }
+
+        self.cx = x as usize;
+        Ok(())
+    }
+
+    fn move_y(&mut self, n: isize) -> Result<()> {
+        let y = self.cy as isize + n;
+        if y < 0 {
+            return Err(Error::InvalidCursorSize(y.unsigned_abs()));
+        }
+
+        self.cy = y as usize;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    #[test]
+    fn dump_trims_rows() {
+        let mut r = TestRenderer::new(6, 2);
+        r.write("ab").unwrap();
+        r.move_y(1).unwrap();
+        r.ret().unwrap();
+        r.write("c").unwrap();
+
+        assert_eq!(r.dump(), "ab\nc\n");
+        assert_eq!(r.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn dump_styled_marks_runs() {
+        let mut r = TestRenderer::new(10, 1);
+        r.write("a").unwrap();
+        r.write_styled("bc", Style::default().fg(Color::Ansi(1)).bold()).unwrap();
+        r.write_styled("d", Style::default().reverse()).unwrap();
+
+        assert_eq!(r.dump_styled(), "a<fg=red bold>bc</><reverse>d</>\n");
+    }
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut r = TestRenderer::new(5, 1);
+        r.write("a界b界").unwrap();
+
+        // the last one doesn't fit and leaves a blank
+        assert_eq!(r.dump(), "a界b\n");
+        assert_eq!(r.cursor(), (5, 0));
+
+        r.ret().unwrap();
+        r.move_x(2).unwrap();
+        r.write("x").unwrap();
+        assert_eq!(r.dump(), "a xb\n");
+    }
+}
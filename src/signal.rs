@@ -0,0 +1,106 @@
+use std::{
+    io::{
+        self,
+        Read,
+    },
+    fs::File,
+    os::unix::io::FromRawFd,
+    sync::{
+        Mutex,
+        Once,
+        atomic::{
+            AtomicI32,
+            Ordering,
+        },
+    },
+    thread,
+};
+
+pub use libc::{
+    c_int,
+    SIGWINCH,
+    SIGTERM,
+    SIGHUP,
+};
+
+
+type Callback = Box<dyn Fn(c_int) + Send>;
+
+// the write end of the pipe the handler reports signals on
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+static INIT: Once = Once::new();
+static WATCHERS: Mutex<Vec<(c_int, Callback)>> = Mutex::new(Vec::new());
+
+extern "C" fn handler(sig: c_int) {
+    // writing to a pipe is one of the few things a signal
+    // handler may do
+    let fd = PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        let b = sig as u8;
+        unsafe {
+            libc::write(fd, &b as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+// calls f from a background thread whenever one of signals
+// arrives, instead of from the signal handler, so f can do
+// anything
+pub fn watch<F>(signals: &[c_int], f: F) -> io::Result<()>
+where
+    F: Fn(c_int) + Send + Clone + 'static,
+{
+    let mut err = None;
+    INIT.call_once(|| {
+        if let Err(e) = start() {
+            err = Some(e);
+        }
+    });
+    if let Some(e) = err {
+        return Err(e);
+    }
+    if PIPE.load(Ordering::Relaxed) < 0 {
+        return Err(io::Error::other("signal pipe not available"));
+    }
+
+    let mut watchers = WATCHERS.lock().unwrap();
+    for sig in signals.iter() {
+        unsafe {
+            let mut sa: libc::sigaction = std::mem::zeroed();
+            sa.sa_sigaction = handler as extern "C" fn(c_int) as libc::sighandler_t;
+            sa.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut sa.sa_mask);
+            if libc::sigaction(*sig, &sa, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        watchers.push((*sig, Box::new(f.clone())));
+    }
+
+    Ok(())
+}
+
+// creates the pipe and the thread which reads it
+fn start() -> io::Result<()> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut rx = unsafe { File::from_raw_fd(fds[0]) };
+    PIPE.store(fds[1], Ordering::Relaxed);
+
+    thread::spawn(move || {
+        let mut b = [0u8; 1];
+        while let Ok(1) = rx.read(&mut b) {
+            let sig = b[0] as c_int;
+            for (s, f) in WATCHERS.lock().unwrap().iter() {
+                if *s == sig {
+                    f(sig);
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,884 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    mem,
+    path::Path,
+    ptr,
+};
+
+use crate::{
+    Error,
+    Result,
+    View,
+    complete::{
+        Candidate,
+        Context,
+        Source,
+    },
+};
+
+
+// a piece of a snippet's body
+#[derive(Debug,Clone,PartialEq,Eq)]
+enum Node {
+    Text(String),
+    // a tabstop, its placeholder and the choices offered there
+    Stop(usize, Vec<Node>, Vec<String>),
+    // a variable and what's used when it's empty
+    Var(String, Vec<Node>),
+}
+
+// a snippet in the TextMate and LSP syntax:
+//
+//     $1 ${2:placeholder} ${3|one,two|} $TM_FILENAME ${NAME:default} $0
+//
+// tabstops with the same number mirror the first one with a
+// placeholder. transforms are read but not applied, what
+// isn't valid syntax is text
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Snippet {
+    nodes: Vec<Node>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    i: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.i).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.i += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> Option<()> {
+        match self.peek() {
+            Some(p) if p == c => {
+                self.i += 1;
+                Some(())
+            },
+            _ => None,
+        }
+    }
+
+    // nodes up to the end, or inside a placeholder up to the
+    // } closing it
+    fn nodes(&mut self, nested: bool) -> Vec<Node> {
+        let mut ret = Vec::new();
+        let mut text = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '}' if nested => break,
+                '\\' => {
+                    self.i += 1;
+                    match self.peek() {
+                        Some(c @ ('$' | '}' | '\\')) => {
+                            text.push(c);
+                            self.i += 1;
+                        },
+                        _ => text.push('\\'),
+                    }
+                },
+                '$' => match self.dollar() {
+                    Some(node) => {
+                        if !text.is_empty() {
+                            ret.push(Node::Text(mem::take(&mut text)));
+                        }
+                        ret.push(node);
+                    },
+                    None => {
+                        text.push('$');
+                        self.i += 1;
+                    },
+                },
+                c => {
+                    text.push(c);
+                    self.i += 1;
+                },
+            }
+        }
+
+        if !text.is_empty() {
+            ret.push(Node::Text(text));
+        }
+        ret
+    }
+
+    // the tabstop or variable at a $, nothing is used up when
+    // there isn't one
+    fn dollar(&mut self) -> Option<Node> {
+        let start = self.i;
+        self.i += 1;
+        let ret = self.tabstop_or_variable();
+        if ret.is_none() {
+            self.i = start;
+        }
+        ret
+    }
+
+    fn tabstop_or_variable(&mut self) -> Option<Node> {
+        if let Some(n) = self.number() {
+            return Some(Node::Stop(n, Vec::new(), Vec::new()));
+        }
+        if let Some(name) = self.name() {
+            return Some(Node::Var(name, Vec::new()));
+        }
+
+        self.eat('{')?;
+        if let Some(n) = self.number() {
+            return match self.next()? {
+                '}' => Some(Node::Stop(n, Vec::new(), Vec::new())),
+                ':' => {
+                    let nodes = self.nodes(true);
+                    self.eat('}')?;
+                    Some(Node::Stop(n, nodes, Vec::new()))
+                },
+                '|' => {
+                    let choices = self.choices()?;
+                    self.eat('}')?;
+                    let first = vec![Node::Text(choices[0].clone())];
+                    Some(Node::Stop(n, first, choices))
+                },
+                '/' => {
+                    self.transform()?;
+                    Some(Node::Stop(n, Vec::new(), Vec::new()))
+                },
+                _ => None,
+            };
+        }
+
+        let name = self.name()?;
+        match self.next()? {
+            '}' => Some(Node::Var(name, Vec::new())),
+            ':' => {
+                let nodes = self.nodes(true);
+                self.eat('}')?;
+                Some(Node::Var(name, nodes))
+            },
+            '/' => {
+                self.transform()?;
+                Some(Node::Var(name, Vec::new()))
+            },
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.i;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.i += 1;
+        }
+        let s: String = self.chars[start..self.i].iter().collect();
+        s.parse().ok()
+    }
+
+    fn name(&mut self) -> Option<String> {
+        if !self.peek().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let start = self.i;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.i += 1;
+        }
+        Some(self.chars[start..self.i].iter().collect())
+    }
+
+    // the choices after ${1| up to the closing |
+    fn choices(&mut self) -> Option<Vec<String>> {
+        let mut ret = Vec::new();
+        let mut choice = String::new();
+        loop {
+            match self.next()? {
+                '\\' => {
+                    let c = self.next()?;
+                    if !",|\\$}".contains(c) {
+                        choice.push('\\');
+                    }
+                    choice.push(c);
+                },
+                ',' => ret.push(mem::take(&mut choice)),
+                '|' => {
+                    ret.push(choice);
+                    return Some(ret);
+                },
+                c => choice.push(c),
+            }
+        }
+    }
+
+    // the regex, format and options of a transform up to the
+    // closing }, the first / has been read
+    fn transform(&mut self) -> Option<()> {
+        let mut slashes = 1;
+        loop {
+            match self.next()? {
+                '\\' => {
+                    self.next()?;
+                },
+                '/' => slashes += 1,
+                '}' if slashes == 3 => return Some(()),
+                _ => {},
+            }
+        }
+    }
+}
+
+// where a tabstop's text is, the line and byte of its start
+// and end
+pub type Range = ((usize, usize), (usize, usize));
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+struct Stop {
+    n: usize,
+    // the first is where the text is typed, the rest mirror it
+    ranges: Vec<Range>,
+    choices: Vec<String>,
+}
+
+// writes out a snippet's text, keeping where its tabstops end
+// up
+struct Writer<'a> {
+    text: String,
+    // where the next character goes
+    pos: (usize, usize),
+    // put after line breaks, unless the line stays empty
+    indent: &'a str,
+    pending_indent: bool,
+    // what tabs at the start of lines become
+    unit: &'a str,
+    line_start: bool,
+    vars: &'a dyn Fn(&str) -> Option<String>,
+    stops: Vec<Stop>,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, s: &str) {
+        for c in s.chars() {
+            if c == '\n' {
+                self.text.push('\n');
+                self.pos = (self.pos.0 + 1, 0);
+                self.pending_indent = true;
+                self.line_start = true;
+                continue;
+            }
+
+            self.flush_indent();
+            if c == '\t' && self.line_start {
+                self.text.push_str(self.unit);
+                self.pos.1 += self.unit.len();
+            } else {
+                self.text.push(c);
+                self.pos.1 += c.len_utf8();
+                self.line_start = false;
+            }
+        }
+    }
+
+    fn flush_indent(&mut self) {
+        if self.pending_indent {
+            self.text.push_str(self.indent);
+            self.pos.1 += self.indent.len();
+            self.pending_indent = false;
+        }
+    }
+
+    // with record unset tabstops are written as text only, for
+    // mirrors and defaults
+    fn write(&mut self, nodes: &[Node], defs: &HashMap<usize, &[Node]>, record: bool) {
+        for node in nodes {
+            match node {
+                Node::Text(s) => self.push(s),
+                Node::Stop(n, placeholder, choices) => {
+                    self.flush_indent();
+                    let start = self.pos;
+                    match placeholder.is_empty() {
+                        true => self.write(defs.get(n).copied().unwrap_or(&[]), defs, false),
+                        false => self.write(placeholder, defs, record),
+                    }
+                    if record {
+                        self.add(*n, (start, self.pos), choices);
+                    }
+                },
+                Node::Var(name, default) => {
+                    let value = (self.vars)(name);
+                    match value.as_deref() {
+                        Some(v) if !v.is_empty() => self.push(v),
+                        _ if !default.is_empty() => self.write(default, defs, record),
+                        // unknown ones are their name
+                        None => self.push(name),
+                        Some(_) => {},
+                    }
+                },
+            }
+        }
+    }
+
+    fn add(&mut self, n: usize, range: Range, choices: &[String]) {
+        match self.stops.iter_mut().find(|s| s.n == n) {
+            Some(stop) => stop.ranges.push(range),
+            None => self.stops.push(Stop{
+                n,
+                ranges: vec![range],
+                choices: choices.to_vec(),
+            }),
+        }
+    }
+}
+
+// the placeholder that each tabstop's mirrors repeat, the first
+// one given
+fn placeholders<'a>(nodes: &'a [Node], defs: &mut HashMap<usize, &'a [Node]>) {
+    for node in nodes {
+        match node {
+            Node::Stop(n, placeholder, _) if !placeholder.is_empty() => {
+                defs.entry(*n).or_insert(placeholder);
+                placeholders(placeholder, defs);
+            },
+            Node::Var(_, default) => placeholders(default, defs),
+            _ => {},
+        }
+    }
+}
+
+impl Snippet {
+    pub fn parse(body: &str) -> Snippet {
+        let mut p = Parser{
+            chars: body.chars().collect(),
+            i: 0,
+        };
+        Snippet{
+            nodes: p.nodes(false),
+        }
+    }
+
+    // writes the snippet out as if at the start of a buffer,
+    // tabstops ordered $1 up and $0 last
+    fn write(&self, at: (usize, usize), indent: &str, unit: &str,
+             vars: &dyn Fn(&str) -> Option<String>) -> (String, Vec<Stop>) {
+
+        let mut defs = HashMap::new();
+        placeholders(&self.nodes, &mut defs);
+
+        let mut w = Writer{
+            text: String::new(),
+            pos: at,
+            indent,
+            pending_indent: false,
+            unit,
+            line_start: false,
+            vars,
+            stops: Vec::new(),
+        };
+        w.write(&self.nodes, &defs, true);
+
+        // without a $0 the cursor ends up after the snippet
+        if !w.stops.iter().any(|s| s.n == 0) {
+            w.flush_indent();
+            w.add(0, (w.pos, w.pos), &[]);
+        }
+        w.stops.sort_by_key(|s| (s.n == 0, s.n));
+        (w.text, w.stops)
+    }
+
+    // the text with placeholders and without variables, for
+    // where the tabstops can't be used
+    pub fn text(&self) -> String {
+        self.write((0, 0), "", "\t", &|_| None).0
+    }
+
+    // inserts the snippet at the cursor, lines after the first
+    // get the cursor line's indent. the cursor goes to the
+    // first tabstop, there's a session while there are more
+    pub fn expand(&self, view: &mut View) -> Option<Session> {
+        let at = view.cursor_byte();
+        let line = view.line(at.0).unwrap_or("");
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let lcfg = view.line_config();
+        let unit = match lcfg.expand_tab {
+            true => " ".repeat(lcfg.indent_size as usize),
+            false => "\t".into(),
+        };
+
+        let vars = |name: &str| variable(view, name);
+        let (text, stops) = self.write(at, indent, &unit, &vars);
+        view.insert_text(&text);
+
+        let mut session = Session{
+            stops,
+            current: 0,
+            fresh: false,
+            lines: 0,
+            first: at.0,
+            lens: Vec::new(),
+        };
+        match session.stops.len() {
+            1 => {
+                session.jump(view, 0);
+                None
+            },
+            _ => {
+                session.enter(view);
+                Some(session)
+            },
+        }
+    }
+}
+
+// the value of a variable at the cursor, none for ones that
+// aren't known
+pub fn variable(view: &View, name: &str) -> Option<String> {
+    let path = view.file_config().path.as_deref();
+    let part = |f: fn(&Path) -> Option<&OsStr>| {
+        path.and_then(f).map_or(String::new(), |s| s.to_string_lossy().into_owned())
+    };
+    let (idx, i) = view.cursor_byte();
+    let line = view.line(idx).unwrap_or("");
+
+    let ret = match name {
+        "TM_FILENAME" => part(Path::file_name),
+        "TM_FILENAME_BASE" => part(Path::file_stem),
+        "TM_DIRECTORY" => part(|p| p.parent().map(Path::as_os_str)),
+        "TM_FILEPATH" => part(|p| Some(p.as_os_str())),
+        "TM_LINE_INDEX" => idx.to_string(),
+        "TM_LINE_NUMBER" => (idx + 1).to_string(),
+        "TM_CURRENT_LINE" => line.into(),
+        "TM_CURRENT_WORD" => line[view.word_start().1..i].into(),
+        "TM_SELECTED_TEXT" => match view.selection_bytes() {
+            Some(((l0, b0), (l1, b1))) => {
+                let lines: Vec<&str> = (l0..=l1)
+                    .map(|l| {
+                        let s = view.line(l).unwrap_or("");
+                        let end = if l == l1 { b1.min(s.len()) } else { s.len() };
+                        let start = if l == l0 { b0.min(end) } else { 0 };
+                        &s[start..end]
+                    })
+                    .collect();
+                lines.join("\n")
+            },
+            None => String::new(),
+        },
+        _ => return date(name),
+    };
+    Some(ret)
+}
+
+// the date variables, in local time
+fn date(name: &str) -> Option<String> {
+    // SAFETY: localtime_r only writes to tm
+    let (secs, tm) = unsafe {
+        let secs = libc::time(ptr::null_mut());
+        let mut tm: libc::tm = mem::zeroed();
+        libc::localtime_r(&secs, &mut tm);
+        (secs, tm)
+    };
+
+    Some(match name {
+        "CURRENT_YEAR" => (tm.tm_year + 1900).to_string(),
+        "CURRENT_YEAR_SHORT" => format!("{:02}", tm.tm_year % 100),
+        "CURRENT_MONTH" => format!("{:02}", tm.tm_mon + 1),
+        "CURRENT_DATE" => format!("{:02}", tm.tm_mday),
+        "CURRENT_HOUR" => format!("{:02}", tm.tm_hour),
+        "CURRENT_MINUTE" => format!("{:02}", tm.tm_min),
+        "CURRENT_SECOND" => format!("{:02}", tm.tm_sec),
+        "CURRENT_SECONDS_UNIX" => secs.to_string(),
+        _ => return None,
+    })
+}
+
+// a snippet being filled in. typing in a tabstop's first range
+// is copied to the rest, and the session ends when the cursor
+// leaves the snippet or gets to $0
+#[derive(Debug,Clone)]
+pub struct Session {
+    // $1 up, then $0
+    stops: Vec<Stop>,
+    current: usize,
+    // the cursor is at the start of the current placeholder,
+    // which is selected, and typing replaces it
+    fresh: bool,
+    // the line count, and the first line of the snippet and the
+    // lengths of its lines when the cursor last moved, to see
+    // what was edited
+    lines: usize,
+    first: usize,
+    lens: Vec<usize>,
+}
+
+impl Session {
+    fn range(&self) -> Range {
+        self.stops[self.current].ranges[0]
+    }
+
+    // where the current tabstop's text starts
+    pub fn start(&self) -> (usize, usize) {
+        self.range().0
+    }
+
+    // the choices offered at the current tabstop
+    pub fn choices(&self) -> &[String] {
+        &self.stops[self.current].choices
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.fresh
+    }
+
+    // the cursor goes to the current tabstop, and its
+    // placeholder is selected
+    fn enter(&mut self, view: &mut View) {
+        let (start, end) = self.range();
+        view.clear_anchor();
+        self.fresh = start != end;
+        if self.fresh {
+            view.goto_byte(end.0, end.1);
+            view.prev_col();
+            view.set_anchor();
+        }
+        view.goto_byte(start.0, start.1);
+        self.remember(view);
+    }
+
+    // moves by n tabstops, false when that was to $0 and the
+    // session is over
+    pub fn jump(&mut self, view: &mut View, n: isize) -> bool {
+        let last = self.stops.len() - 1;
+        self.current = (self.current as isize + n).clamp(0, last as isize) as usize;
+        if self.current < last {
+            self.enter(view);
+            return true;
+        }
+
+        view.clear_anchor();
+        let (_, end) = self.range();
+        view.goto_byte(end.0, end.1);
+        false
+    }
+
+    // takes out the selected placeholder, before what's typed
+    // goes in its place
+    pub fn clear_placeholder(&mut self, view: &mut View) {
+        if !self.fresh {
+            return;
+        }
+        self.fresh = false;
+        view.clear_anchor();
+
+        let (start, end) = self.range();
+        view.replace(start, end, "");
+        if start.0 == end.0 {
+            self.resize(end.1, start.1);
+            self.mirror(view);
+        }
+        self.remember(view);
+    }
+
+    // follows the edits since the last call, false when the
+    // session is over
+    pub fn update(&mut self, view: &mut View) -> bool {
+        let alive = self.follow(view);
+        if !alive && self.fresh {
+            view.clear_anchor();
+        }
+        alive
+    }
+
+    fn follow(&mut self, view: &mut View) -> bool {
+        if view.line_count() != self.lines {
+            return false;
+        }
+
+        // only edits in the current tabstop are followed, in
+        // its line when it's on one
+        let ((l0, b0), (l1, b1)) = self.range();
+        let cursor = view.cursor_byte();
+        let changed: Vec<(usize, isize)> = self.lens.iter()
+            .enumerate()
+            .map(|(i, &len)| {
+                let idx = self.first + i;
+                (idx, view.line(idx).map_or(0, str::len) as isize - len as isize)
+            })
+            .filter(|&(_, delta)| delta != 0)
+            .collect();
+        match changed[..] {
+            [] => {},
+            [(idx, delta)] => {
+                let end = b1 as isize + delta;
+                if idx != l0 || l0 != l1 || cursor.0 != l0 || end < b0 as isize
+                    || cursor.1 < b0 || cursor.1 as isize > end {
+                    return false;
+                }
+                self.resize(b1, end as usize);
+                self.mirror(view);
+            },
+            _ => return false,
+        }
+
+        let (first, last) = self.span();
+        if cursor.0 < first || cursor.0 > last {
+            return false;
+        }
+        if self.fresh && cursor != (l0, b0) {
+            self.fresh = false;
+            view.clear_anchor();
+        }
+        self.remember(view);
+        true
+    }
+
+    // the current tabstop's first range now ends at to instead
+    // of from. what was inside it is gone, so tabstops there
+    // end up at its end
+    fn resize(&mut self, from: usize, to: usize) {
+        let ((line, start), _) = self.range();
+        let current = self.current;
+
+        for (si, stop) in self.stops.iter_mut().enumerate() {
+            for (ri, r) in stop.ranges.iter_mut().enumerate() {
+                if (si, ri) == (current, 0) || r.0.0 != line || r.1.0 != line {
+                    continue;
+                }
+                let inside = r.0.1 >= start && r.1.1 <= from
+                    && !(r.0 == r.1 && (r.0.1 == start || r.0.1 == from));
+                if inside {
+                    *r = ((line, from), (line, from));
+                }
+            }
+        }
+
+        self.shift(line, from, to as isize - from as isize, (current, 0));
+        self.stops[current].ranges[0].1.1 = to;
+    }
+
+    // positions in line at or after byte move by delta, but
+    // those of the range at skip
+    fn shift(&mut self, line: usize, byte: usize, delta: isize, skip: (usize, usize)) {
+        for (si, stop) in self.stops.iter_mut().enumerate() {
+            for (ri, r) in stop.ranges.iter_mut().enumerate() {
+                if (si, ri) == skip {
+                    continue;
+                }
+                for p in [&mut r.0, &mut r.1] {
+                    if p.0 == line && p.1 >= byte {
+                        p.1 = (p.1 as isize + delta) as usize;
+                    }
+                }
+            }
+        }
+    }
+
+    // copies the current tabstop's text to its mirrors
+    fn mirror(&mut self, view: &mut View) {
+        let ((line, start), (_, end)) = self.range();
+        let text = view.line(line).map_or("", |l| &l[start..end]).to_string();
+
+        for ri in 1..self.stops[self.current].ranges.len() {
+            let (a, b) = self.stops[self.current].ranges[ri];
+            if a.0 != b.0 || view.line(a.0).and_then(|l| l.get(a.1..b.1)) == Some(text.as_str()) {
+                continue;
+            }
+
+            view.replace(a, b, &text);
+            let delta = text.len() as isize - (b.1 - a.1) as isize;
+            self.shift(a.0, b.1, delta, (self.current, ri));
+            self.stops[self.current].ranges[ri].1 = (a.0, a.1 + text.len());
+        }
+    }
+
+    // the first and last lines of the snippet
+    fn span(&self) -> (usize, usize) {
+        let lines = self.stops.iter().flat_map(|s| &s.ranges).flat_map(|r| [r.0.0, r.1.0]);
+        let first = lines.clone().min().unwrap_or(0);
+        (first, lines.max().unwrap_or(0))
+    }
+
+    fn remember(&mut self, view: &View) {
+        let (first, last) = self.span();
+        self.lines = view.line_count();
+        self.first = first;
+        self.lens = (first..=last).map(|i| view.line(i).map_or(0, str::len)).collect();
+    }
+}
+
+#[derive(Debug,Clone)]
+struct Def {
+    trigger: String,
+    description: String,
+    body: String,
+}
+
+// the snippets for a filetype, from a file like
+//
+//     # comment
+//     snippet fn a function
+//     	fn ${1:name}() {
+//     		$0
+//     	}
+//
+// the body is the tab indented lines after `snippet`, less
+// one tab
+#[derive(Debug,Clone,Default)]
+pub struct Snippets {
+    defs: Vec<Def>,
+}
+
+impl Snippets {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snippets> {
+        let path = path.as_ref();
+        let err = |line, msg| Error::Config{
+            path: path.to_path_buf(),
+            line,
+            msg,
+        };
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| err(0, e.to_string()))?;
+        Snippets::parse(&text).map_err(|(line, msg)| err(line, msg))
+    }
+
+    // errors come with their line number
+    pub fn parse(text: &str) -> std::result::Result<Snippets, (usize, String)> {
+        let mut ret = Snippets::default();
+        let mut body: Option<Vec<&str>> = None;
+
+        for (n, line) in text.lines().enumerate() {
+            if let Some(rest) = line.strip_prefix('\t') {
+                match body.as_mut() {
+                    Some(body) => body.push(rest),
+                    None => return Err((n + 1, "indented line outside a snippet".into())),
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                if let Some(body) = body.as_mut() {
+                    body.push("");
+                }
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let rest = match line.strip_prefix("snippet") {
+                Some(rest) if rest.is_empty() || rest.starts_with(' ') => rest.trim(),
+                _ => return Err((n + 1, format!("expected a snippet: {}", line))),
+            };
+            let (trigger, description) = rest.split_once(' ').unwrap_or((rest, ""));
+            if trigger.is_empty() {
+                return Err((n + 1, "snippet requires a trigger".into()));
+            }
+
+            ret.finish(body.take());
+            body = Some(Vec::new());
+            ret.defs.push(Def{
+                trigger: trigger.into(),
+                description: description.trim().into(),
+                body: String::new(),
+            });
+        }
+
+        ret.finish(body);
+        Ok(ret)
+    }
+
+    // sets the body of the last snippet, without blank lines
+    // at the end
+    fn finish(&mut self, body: Option<Vec<&str>>) {
+        if let (Some(mut body), Some(def)) = (body, self.defs.last_mut()) {
+            while body.last() == Some(&"") {
+                body.pop();
+            }
+            def.body = body.join("\n");
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    // the body of the snippet with a trigger, the last one
+    // when there are more
+    pub fn body(&self, trigger: &str) -> Option<&str> {
+        self.defs.iter().rev().find(|d| d.trigger == trigger).map(|d| d.body.as_str())
+    }
+}
+
+impl Source for Snippets {
+    fn candidates(&mut self, _ctx: &Context) -> Vec<Candidate> {
+        self.defs.iter()
+            .map(|d| {
+                let text = Snippet::parse(&d.body).text();
+                let doc = match d.description.as_str() {
+                    "" => text,
+                    desc => format!("{}\n\n{}", desc, text),
+                };
+                Candidate{
+                    doc: Some(doc),
+                    snippet: Some(d.body.clone()),
+                    ..Candidate::new(&d.trigger, "snippet")
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tabstops_variables_and_escapes() {
+        let s = Snippet::parse(
+            "a $1 ${2:b ${3:c}} ${4|x,y\\,z|} $TM_X ${Y:d} \\$5 ${1/(.)/$1/g} $ ${");
+        assert_eq!(s.nodes, vec![
+            Node::Text("a ".into()),
+            Node::Stop(1, vec![], vec![]),
+            Node::Text(" ".into()),
+            Node::Stop(2, vec![
+                Node::Text("b ".into()),
+                Node::Stop(3, vec![Node::Text("c".into())], vec![]),
+            ], vec![]),
+            Node::Text(" ".into()),
+            Node::Stop(4, vec![Node::Text("x".into())], vec!["x".into(), "y,z".into()]),
+            Node::Text(" ".into()),
+            Node::Var("TM_X".into(), vec![]),
+            Node::Text(" ".into()),
+            Node::Var("Y".into(), vec![Node::Text("d".into())]),
+            Node::Text(" $5 ".into()),
+            Node::Stop(1, vec![], vec![]),
+            Node::Text(" $ ${".into()),
+        ]);
+    }
+
+    #[test]
+    fn writes_mirrors_and_indents() {
+        let s = Snippet::parse("fn ${1:name}() {\n\t$0\n}\n// $1 $NOPE ${EMPTY:none}");
+        let vars = |name: &str| (name == "EMPTY").then(String::new);
+        let (text, stops) = s.write((3, 2), "  ", "    ", &vars);
+
+        assert_eq!(text, "fn name() {\n      \n  }\n  // name NOPE none");
+        assert_eq!(stops, vec![
+            Stop{n: 1, ranges: vec![((3, 5), (3, 9)), ((6, 5), (6, 9))], choices: vec![]},
+            Stop{n: 0, ranges: vec![((4, 6), (4, 6))], choices: vec![]},
+        ]);
+    }
+
+    #[test]
+    fn snippet_files() {
+        let text = "# rust\nsnippet fn a function\n\tfn $1() {\n\t\t$0\n\t}\n\nsnippet t\n\ttest\n";
+        let snippets = Snippets::parse(text).unwrap();
+        assert_eq!(snippets.body("fn"), Some("fn $1() {\n\t$0\n}"));
+        assert_eq!(snippets.body("t"), Some("test"));
+        assert_eq!(snippets.body("x"), None);
+
+        assert_eq!(Snippets::parse("\tbody").unwrap_err().0, 1);
+        assert_eq!(Snippets::parse("snippet\n").unwrap_err().0, 1);
+        assert_eq!(Snippets::parse("snippets x\n").unwrap_err().0, 1);
+    }
+}
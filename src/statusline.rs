@@ -0,0 +1,179 @@
+use unicode_width::{
+    UnicodeWidthStr,
+};
+
+use crate::{
+    Renderer,
+    Result,
+    Theme,
+    View,
+};
+
+
+pub const DEFAULT_FORMAT: &str = " %m  %f%M%r%= %y  %E  %e  %l:%c  %p%% ";
+
+// what the status line shows about a window
+#[derive(Debug,Clone,Default)]
+pub struct StatusInfo {
+    pub mode: String,
+    pub path: Option<String>,
+    pub modified: bool,
+    pub read_only: bool,
+    // 0-based, shown 1-based
+    pub line: usize,
+    pub col: usize,
+    pub lines: usize,
+    pub filetype: Option<String>,
+    pub line_ending: String,
+    pub encoding: String,
+}
+
+impl StatusInfo {
+    pub fn new(view: &View<'_>, mode: &str, filetype: Option<&str>) -> StatusInfo {
+        let (col, line) = view.cursor();
+        let fcfg = view.file_config();
+
+        StatusInfo{
+            mode: mode.into(),
+            path: fcfg.path.as_ref().map(|p| p.display().to_string()),
+            modified: view.is_modified(),
+            read_only: fcfg.read_only,
+            line,
+            col,
+            lines: view.line_count(),
+            filetype: filetype.map(String::from),
+            line_ending: fcfg.line_ending.name().into(),
+            encoding: fcfg.charset.name().into(),
+        }
+    }
+}
+
+// a line of text under each window, written with a format
+// string where `%` items are replaced:
+//
+//   %m mode         %f file path    %t file name
+//   %M [+] if modified              %r [RO] if read-only
+//   %l line         %c column       %L number of lines
+//   %p percentage through the file  %y file type
+//   %e line ending  %E encoding
+//   %= what follows is aligned right
+//   %% a `%`
+//
+// it's drawn in the StatusLine highlight group
+#[derive(Debug,Clone)]
+pub struct StatusLine {
+    pub format: String,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        StatusLine::new(DEFAULT_FORMAT)
+    }
+}
+
+impl StatusLine {
+    pub fn new(format: &str) -> StatusLine {
+        StatusLine{
+            format: format.into(),
+        }
+    }
+
+    // the text aligned left and right
+    pub fn expand(&self, info: &StatusInfo) -> (String, String) {
+        let mut left = String::new();
+        let mut right = String::new();
+        let mut out = &mut left;
+        let mut chars = self.format.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('m') => out.push_str(&info.mode),
+                Some('f') => out.push_str(info.path.as_deref().unwrap_or("[No Name]")),
+                Some('t') => {
+                    let name = info.path.as_deref()
+                        .map(|p| p.rsplit('/').next().unwrap_or(p))
+                        .unwrap_or("[No Name]");
+                    out.push_str(name);
+                },
+                Some('M') => if info.modified {
+                    out.push_str(" [+]");
+                },
+                Some('r') => if info.read_only {
+                    out.push_str(" [RO]");
+                },
+                Some('l') => out.push_str(&(info.line + 1).to_string()),
+                Some('c') => out.push_str(&(info.col + 1).to_string()),
+                Some('L') => out.push_str(&info.lines.to_string()),
+                Some('p') => {
+                    let p = (info.line + 1) * 100 / info.lines.max(1);
+                    out.push_str(&p.to_string());
+                },
+                Some('y') => out.push_str(info.filetype.as_deref().unwrap_or("")),
+                Some('e') => out.push_str(&info.line_ending),
+                Some('E') => out.push_str(&info.encoding),
+                Some('=') => out = &mut right,
+                Some('%') => out.push('%'),
+                Some(c) => {
+                    out.push('%');
+                    out.push(c);
+                },
+                None => out.push('%'),
+            }
+        }
+
+        (left, right)
+    }
+
+    // draws the whole row at the renderer's cursor, the left
+    // part wins when both don't fit
+    pub fn render<R: Renderer>(&self, r: &mut R, info: &StatusInfo, theme: &Theme) ->
+        Result<()> {
+
+        let width = r.width();
+        let (left, right) = self.expand(info);
+        let lw = UnicodeWidthStr::width(left.as_str());
+        let rw = UnicodeWidthStr::width(right.as_str());
+
+        let mut text = left;
+        if lw + rw <= width {
+            text.push_str(&" ".repeat(width - lw - rw));
+            text.push_str(&right);
+        } else if lw < width {
+            text.push_str(&" ".repeat(width - lw));
+        }
+
+        r.write_styled(&text, theme.style("StatusLine"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_items() {
+        let info = StatusInfo{
+            mode: "NORMAL".into(),
+            path: Some("src/main.rs".into()),
+            modified: true,
+            line: 9,
+            col: 0,
+            lines: 40,
+            filetype: Some("rust".into()),
+            line_ending: "lf".into(),
+            encoding: "utf-8".into(),
+            ..StatusInfo::default()
+        };
+
+        let sl = StatusLine::new("%m %t%M%r %l:%c %p%%%=%y %e %E %x");
+        assert_eq!(sl.expand(&info), (
+            "NORMAL main.rs [+] 10:1 25%".to_string(),
+            "rust lf utf-8 %x".to_string(),
+        ));
+    }
+}
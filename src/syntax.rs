@@ -0,0 +1,312 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+use tree_sitter::{
+    InputEdit,
+    Language,
+    Parser,
+    Point,
+    Query,
+    QueryCursor,
+    StreamingIterator,
+    Tree,
+};
+
+use crate::{
+    LineChange,
+    Style,
+    Theme,
+};
+
+
+// a position in the text, as line and byte in the line
+pub type Pos = (usize, usize);
+
+// the languages built in, with their default highlights and
+// text objects queries
+fn language(filetype: &str) -> Option<(Language, &'static str, &'static str)> {
+    match filetype {
+        "rust" => Some((
+            tree_sitter_rust::LANGUAGE.into(),
+            tree_sitter_rust::HIGHLIGHTS_QUERY,
+            include_str!("../syntax/rust/textobjects.scm"),
+        )),
+        _ => None,
+    }
+}
+
+// a buffer parsed with tree-sitter. edits are collected as
+// ranges of lines and the tree is updated from them the next
+// time it's used
+//
+// highlights come from a query whose capture names are the
+// highlight groups, text objects from a query with captures
+// like `@function.outer`, `@class.inner` or `@parameter.outer`
+pub struct SyntaxTree {
+    parser: Parser,
+    tree: Option<Tree>,
+    highlights: Query,
+    textobjects: Query,
+    // by capture of the highlights query
+    styles: Vec<Style>,
+    // the text the tree was parsed from and its line lengths
+    text: String,
+    lens: Vec<usize>,
+    // what changed since the text was parsed
+    edit: Option<LineChange>,
+}
+
+impl std::fmt::Debug for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyntaxTree")
+            .field("tree", &self.tree)
+            .field("edit", &self.edit)
+            .finish()
+    }
+}
+
+impl SyntaxTree {
+    pub fn new(language: Language, highlights: &str, textobjects: &str) ->
+        std::result::Result<SyntaxTree, String> {
+
+        let mut parser = Parser::new();
+        parser.set_language(&language).map_err(|e| e.to_string())?;
+        let highlights = Query::new(&language, highlights)
+            .map_err(|e| format!("highlights query: {}", e))?;
+        let textobjects = Query::new(&language, textobjects)
+            .map_err(|e| format!("textobjects query: {}", e))?;
+
+        Ok(SyntaxTree{
+            parser,
+            tree: None,
+            styles: vec![Style::default(); highlights.capture_names().len()],
+            highlights,
+            textobjects,
+            text: String::new(),
+            lens: Vec::new(),
+            edit: None,
+        })
+    }
+
+    // the tree for a filetype with a built in language, the
+    // queries in `dir/highlights.scm` and `dir/textobjects.scm`
+    // replace the default ones
+    pub fn for_filetype(filetype: &str, dir: Option<&Path>) ->
+        std::result::Result<Option<SyntaxTree>, String> {
+
+        let (lang, highlights, textobjects) = match language(filetype) {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+
+        let read = |name: &str, default: &str| match dir.map(|d| d.join(name)) {
+            Some(p) if p.exists() => fs::read_to_string(&p)
+                .map_err(|e| format!("{}: {}", p.display(), e)),
+            _ => Ok(default.to_string()),
+        };
+
+        let highlights = read("highlights.scm", highlights)?;
+        let textobjects = read("textobjects.scm", textobjects)?;
+        SyntaxTree::new(lang, &highlights, &textobjects).map(Some)
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.styles = self.highlights.capture_names().iter()
+            .map(|name| theme.style(name))
+            .collect();
+    }
+
+    // old lines starting at line were replaced with new ones
+    pub fn edited(&mut self, line: usize, old: usize, new: usize) {
+        self.edit = Some(LineChange::merge(self.edit, line, old, new));
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.tree.is_none() || self.edit.is_some()
+    }
+
+    // parses the text again, reusing what didn't change
+    pub fn update<'a, I>(&mut self, lines: I)
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut text = String::new();
+        let mut lens = Vec::new();
+        for (i, line) in lines.enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            text.push_str(line);
+            lens.push(line.len());
+        }
+
+        if let (Some(tree), Some(edit)) = (self.tree.as_mut(), self.edit) {
+            let (start_byte, start_position) = line_start(&self.lens, edit.start);
+            let (old_end_byte, old_end_position) = line_start(&self.lens, edit.old_end);
+            let (new_end_byte, new_end_position) = line_start(&lens, edit.new_end);
+
+            tree.edit(&InputEdit{
+                start_byte,
+                old_end_byte,
+                new_end_byte,
+                start_position,
+                old_end_position,
+                new_end_position,
+            });
+        }
+
+        self.tree = self.parser.parse(&text, self.tree.as_ref());
+        self.text = text;
+        self.lens = lens;
+        self.edit = None;
+    }
+
+    // the highlighted spans of n lines from first, in bytes of
+    // each line
+    pub fn spans(&self, first: usize, n: usize) -> Vec<Vec<(usize, usize, Style)>> {
+        let mut ret = vec![Vec::new(); n];
+        let last = (first + n).min(self.lens.len());
+        let tree = match &self.tree {
+            Some(tree) if first < last => tree,
+            _ => return ret,
+        };
+
+        let (start, _) = line_start(&self.lens, first);
+        let (end, _) = line_start(&self.lens, last);
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(start..end);
+        let mut captures = cursor.captures(&self.highlights, tree.root_node(), self.text.as_bytes());
+
+        let mut prev = None;
+        while let Some((m, i)) = captures.next() {
+            let cap = m.captures[*i];
+            let node = cap.node;
+
+            // the first pattern which matches a node wins
+            if prev == Some(node.byte_range()) {
+                continue;
+            }
+            prev = Some(node.byte_range());
+
+            let style = self.styles[cap.index as usize];
+            if style == Style::default() {
+                continue;
+            }
+
+            let (a, b) = (node.start_position(), node.end_position());
+            for row in a.row.max(first)..=b.row.min(last - 1) {
+                let s = if row == a.row { a.column } else { 0 };
+                let e = if row == b.row { b.column } else { self.lens[row] };
+                if s < e {
+                    ret[row - first].push((s, e, style));
+                }
+            }
+        }
+
+        ret
+    }
+
+    // the ranges of the text objects captured as name, in the
+    // order they start
+    pub fn objects(&self, name: &str) -> Vec<(Pos, Pos)> {
+        let (tree, idx) = match (&self.tree, self.textobjects.capture_index_for_name(name)) {
+            (Some(tree), Some(idx)) => (tree, idx),
+            _ => return Vec::new(),
+        };
+
+        let mut ret = Vec::new();
+        let mut cursor = QueryCursor::new();
+        let mut matches = cursor.matches(&self.textobjects, tree.root_node(), self.text.as_bytes());
+        while let Some(m) = matches.next() {
+            for cap in m.captures.iter().filter(|c| c.index == idx) {
+                ret.push((pos(cap.node.start_position()), pos(cap.node.end_position())));
+            }
+        }
+
+        ret.sort_unstable();
+        ret.dedup();
+        ret
+    }
+
+    // the smallest node around start..end which is bigger
+    pub fn expand(&self, start: Pos, end: Pos) -> Option<(Pos, Pos)> {
+        let root = self.tree.as_ref()?.root_node();
+        let mut node = root.named_descendant_for_point_range(point(start), point(end))?;
+
+        while pos(node.start_position()) == start && pos(node.end_position()) == end {
+            node = node.parent()?;
+        }
+
+        Some((pos(node.start_position()), pos(node.end_position())))
+    }
+}
+
+fn pos(p: Point) -> Pos {
+    (p.row, p.column)
+}
+
+fn point(p: Pos) -> Point {
+    Point::new(p.0, p.1)
+}
+
+// the byte and point where a line starts in text with lines
+// of lens bytes, lines past the end start at the end
+fn line_start(lens: &[usize], line: usize) -> (usize, Point) {
+    if line < lens.len() {
+        let byte = lens[..line].iter().sum::<usize>() + line;
+        return (byte, Point::new(line, 0));
+    }
+
+    match lens.len() {
+        0 => (0, Point::new(0, 0)),
+        n => {
+            let byte = lens.iter().sum::<usize>() + n - 1;
+            (byte, Point::new(n - 1, lens[n - 1]))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "fn a(x: u8) {\n}\n\nstruct B;\nfn c() {}";
+
+    fn tree(text: &str) -> SyntaxTree {
+        let mut ret = SyntaxTree::for_filetype("rust", None).unwrap().unwrap();
+        ret.set_theme(&Theme::parse("test", "hi Keyword bold").unwrap());
+        ret.update(text.lines());
+        ret
+    }
+
+    #[test]
+    fn objects_from_the_tree() {
+        let t = tree(TEXT);
+
+        assert_eq!(t.objects("function.outer"), vec![((0, 0), (1, 1)), ((4, 0), (4, 9))]);
+        assert_eq!(t.objects("class.outer"), vec![((3, 0), (3, 9))]);
+        assert_eq!(t.objects("parameter.outer"), vec![((0, 5), (0, 10))]);
+        assert_eq!(t.expand((0, 5), (0, 10)), Some(((0, 4), (0, 11))));
+    }
+
+    #[test]
+    fn edits_update_the_tree() {
+        let mut t = tree(TEXT);
+        let bold = Style::default().bold();
+        assert_eq!(t.spans(3, 1), vec![vec![(0, 6, bold)]]);
+
+        // struct B; becomes two lines of functions
+        let text = TEXT.replace("struct B;", "fn b() {\n}");
+        t.edited(3, 1, 1);
+        t.edited(4, 0, 1);
+        assert!(t.is_stale());
+        t.update(text.lines());
+
+        assert_eq!(t.spans(3, 3), vec![vec![(0, 2, bold)], vec![], vec![(0, 2, bold)]]);
+        assert_eq!(t.objects("function.outer").len(), 3);
+        assert_eq!(t.tree.as_ref().unwrap().root_node().to_sexp(),
+            tree(&text).tree.unwrap().root_node().to_sexp());
+    }
+}
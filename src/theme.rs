@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    path::Path,
+};
+
+use crate::{
+    Error,
+    Result,
+    Style,
+    config,
+};
+
+
+// the colorscheme the editor starts with
+const DEFAULT: &str = include_str!("../colors/default");
+
+// links followed from a group before giving up, so a loop of
+// links ends
+const MAX_LINKS: usize = 16;
+
+// the styles of named highlight groups, read from a
+// colorscheme file of `highlight` commands
+//
+//     highlight Comment fg=blue italic
+//     highlight link Keyword Statement
+//     highlight clear
+//
+// a group has a style or links to another group. groups are
+// matched ignoring case, so tree-sitter's `keyword` is
+// `Keyword`, and `String.escape` falls back to `String` when
+// it has no style of its own
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    groups: Vec<(String, Group)>,
+}
+
+#[derive(Debug, Clone)]
+enum Group {
+    Style(Style),
+    Link(String),
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::parse("default", DEFAULT).unwrap()
+    }
+}
+
+impl Theme {
+    pub fn load<P: AsRef<Path>>(name: &str, path: P) -> Result<Theme> {
+        let path = path.as_ref();
+        let err = |line, msg| Error::Config{
+            path: path.to_path_buf(),
+            line,
+            msg,
+        };
+
+        let text = fs::read_to_string(path)
+            .map_err(|e| err(0, e.to_string()))?;
+        Theme::parse(name, &text).map_err(|(line, msg)| err(line, msg))
+    }
+
+    // errors come with their line number
+    pub fn parse(name: &str, text: &str) -> std::result::Result<Theme, (usize, String)> {
+        let mut ret = Theme{
+            name: name.into(),
+            groups: Vec::new(),
+        };
+
+        for (n, line) in text.lines().enumerate() {
+            match config::parse_command(line) {
+                Some(("highlight", arg)) | Some(("hi", arg)) => {
+                    ret.highlight(arg).map_err(|msg| (n + 1, msg))?;
+                },
+                Some((cmd, _)) => return Err((n + 1, format!("unknown command: {}", cmd))),
+                None => {},
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // runs the arguments of a `highlight` command
+    pub fn highlight(&mut self, arg: &str) -> std::result::Result<(), String> {
+        let mut args = arg.split_whitespace();
+        let group = args.next()
+            .ok_or_else(|| "highlight requires a group".to_string())?;
+
+        match group {
+            "clear" => self.groups.clear(),
+            "link" => {
+                let from = args.next()
+                    .ok_or_else(|| "link requires two groups".to_string())?;
+                let to = args.next()
+                    .ok_or_else(|| "link requires two groups".to_string())?;
+                self.set(from, Group::Link(to.into()));
+            },
+            _ => {
+                let attrs: Vec<&str> = args.collect();
+                let style = Style::parse(&attrs)?;
+                self.set(group, Group::Style(style));
+            },
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, name: &str, group: Group) {
+        self.groups.retain(|(g, _)| !g.eq_ignore_ascii_case(name));
+        self.groups.push((name.into(), group));
+    }
+
+    fn get(&self, name: &str) -> Option<&Group> {
+        self.groups.iter()
+            .find(|(g, _)| g.eq_ignore_ascii_case(name))
+            .map(|(_, group)| group)
+    }
+
+    // the style of a group, following links
+    pub fn style(&self, group: &str) -> Style {
+        let mut name = group;
+
+        for _ in 0..MAX_LINKS {
+            match self.get(name) {
+                Some(Group::Style(style)) => return *style,
+                Some(Group::Link(to)) => name = to,
+                None => match name.rfind('.') {
+                    Some(idx) => name = &name[..idx],
+                    None => break,
+                },
+            }
+        }
+
+        Style::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    #[test]
+    fn links_and_fallbacks() {
+        let mut t = Theme::parse("test", r#"
+            " a comment
+            highlight Statement fg=yellow
+            hi link Keyword Statement
+            hi link A B
+            hi link B A
+        "#).unwrap();
+
+        let yellow = Style::default().fg(Color::Ansi(3));
+        assert_eq!(t.style("Keyword"), yellow);
+        assert_eq!(t.style("keyword.function"), yellow);
+        assert_eq!(t.style("A"), Style::default());
+
+        t.highlight("Keyword bold").unwrap();
+        assert_eq!(t.style("keyword"), Style::default().bold());
+
+        assert_eq!(Theme::parse("bad", "hi Normal\nset x").unwrap_err().0, 2);
+        assert_eq!(Theme::default().style("Visual"), Style::default().reverse());
+    }
+}
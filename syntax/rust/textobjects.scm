@@ -0,0 +1,19 @@
+; the nodes structural motions and text objects work on
+
+(function_item) @function.outer
+(function_item body: (block) @function.inner)
+(closure_expression) @function.outer
+(closure_expression body: (_) @function.inner)
+
+(struct_item) @class.outer
+(struct_item body: (_) @class.inner)
+(enum_item) @class.outer
+(enum_item body: (_) @class.inner)
+(trait_item) @class.outer
+(trait_item body: (_) @class.inner)
+(impl_item) @class.outer
+(impl_item body: (_) @class.inner)
+
+(parameters (_) @parameter.outer)
+(arguments (_) @parameter.outer)
+(type_arguments (_) @parameter.outer)
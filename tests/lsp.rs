@@ -0,0 +1,200 @@
+use std::{
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    time::Duration,
+};
+
+use editor::{
+    Buffer,
+    Event,
+    EventLoop,
+    TestRenderer,
+    View,
+    lsp::{
+        Client,
+        Incoming,
+        Reply,
+    },
+};
+
+
+// the fake server in examples, which cargo builds for tests
+fn fake_server() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    let dir = exe.parent().unwrap().parent().unwrap();
+    dir.join("examples").join("fake_lsp")
+}
+
+fn temp_file(name: &str, text: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("editor-lsp-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.rs");
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn start(events: &EventLoop, path: &Path) -> Client {
+    let server = fake_server();
+    Client::start("rust", &[server.to_str().unwrap()], path.parent().unwrap(), events.handle())
+        .unwrap()
+}
+
+// the next reply from the server
+fn reply(events: &mut EventLoop, client: &mut Client) -> Reply {
+    let timer = events.set_timer(Duration::from_secs(10));
+    loop {
+        match events.wait() {
+            Some(Event::Message(msg)) => {
+                let inc = msg.downcast::<Incoming>().unwrap();
+                let msg = inc.msg.expect("server exited");
+                if let Some(reply) = client.handle(msg) {
+                    events.cancel_timer(timer);
+                    return reply;
+                }
+            },
+            Some(Event::Timer(_)) => panic!("no reply from server"),
+            ev => panic!("unexpected event: {:?}", ev),
+        }
+    }
+}
+
+fn diagnostics(events: &mut EventLoop, client: &mut Client, view: &mut View) -> usize {
+    match reply(events, client) {
+        Reply::Diagnostics(_, d) => {
+            client.show_diagnostics(view, &d);
+            d.len()
+        },
+        r => panic!("expected diagnostics: {:?}", r),
+    }
+}
+
+#[test]
+fn diagnostics_follow_incremental_edits() {
+    let path = temp_file("sync", "fn main() {\n    let x = 1; // TODO\n}\n");
+    let mut buf = Buffer::open(&path).unwrap();
+    let mut view = View::new(&mut buf);
+    let mut events = EventLoop::new();
+    let mut client = start(&events, &path);
+
+    client.sync(&mut view).unwrap();
+    assert_eq!(diagnostics(&mut events, &mut client, &mut view), 1);
+
+    let mut r = TestRenderer::new(30, 3);
+    view.render(&mut r).unwrap();
+    assert_eq!(r.dump(), "  fn main() {\nW     let x = 1; // TODO  TODO\n  }\n");
+
+    // a new line above moves the warning, an edit in a line
+    // adds an error
+    view.insert_line_above();
+    view.scroll_rel(-1);
+    view.insert_str("// XXX é");
+    view.scroll_rel(2);
+    view.last_col();
+    view.insert_str(" again TODO");
+    client.sync(&mut view).unwrap();
+    assert_eq!(diagnostics(&mut events, &mut client, &mut view), 3);
+
+    assert_eq!(view.signs(0)[0].text, "E");
+    assert!(view.signs(1).is_empty());
+    assert_eq!(view.signs(2)[0].text, "W");
+    assert_eq!(view.virtual_text(2).len(), 2);
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn requests_at_the_cursor() {
+    let path = temp_file("requests", "let café = 1;\nlet b = café + café;\nca");
+    let mut buf = Buffer::open(&path).unwrap();
+    let mut view = View::new(&mut buf);
+    let mut events = EventLoop::new();
+    let mut client = start(&events, &path);
+
+    client.sync(&mut view).unwrap();
+    diagnostics(&mut events, &mut client, &mut view);
+
+    // the second café on the second line, after a two byte é
+    view.goto_byte(1, 17);
+    client.hover(&mut view).unwrap();
+    assert_eq!(reply(&mut events, &mut client), Reply::Hover("`café` used 3 times".into()));
+
+    client.definition(&mut view).unwrap();
+    let locs = match reply(&mut events, &mut client) {
+        Reply::Definition(locs) => locs,
+        r => panic!("expected a definition: {:?}", r),
+    };
+    assert_eq!(locs.len(), 1);
+    view.goto_byte(locs[0].range.start.line, client.to_byte(&view, locs[0].range.start).1);
+    assert_eq!(view.cursor_byte(), (0, 4));
+
+    client.references(&mut view).unwrap();
+    match reply(&mut events, &mut client) {
+        Reply::References(locs) => {
+            let starts: Vec<_> = locs.iter().map(|l| client.to_byte(&view, l.range.start)).collect();
+            assert_eq!(starts, vec![(0, 4), (1, 8), (1, 16)]);
+        },
+        r => panic!("expected references: {:?}", r),
+    }
+
+    view.goto(2, 2);
+    client.completion(&mut view).unwrap();
+    match reply(&mut events, &mut client) {
+        Reply::Completion(items) => {
+            assert_eq!(items.len(), 1);
+            client.complete(&mut view, &items[0]);
+        },
+        r => panic!("expected completions: {:?}", r),
+    }
+    assert_eq!(view.line(2), Some("café"));
+    assert_eq!(view.cursor_byte(), (2, 5));
+    client.sync(&mut view).unwrap();
+    diagnostics(&mut events, &mut client, &mut view);
+
+    client.rename(&mut view, "cafe").unwrap();
+    match reply(&mut events, &mut client) {
+        Reply::Rename(edit) => {
+            assert_eq!(client.apply_workspace_edit(&mut view, &edit), Ok(1));
+        },
+        r => panic!("expected a rename: {:?}", r),
+    }
+    let lines: Vec<&str> = view.lines().collect();
+    assert_eq!(lines, vec!["let cafe = 1;", "let b = cafe + cafe;", "cafe"]);
+    assert_eq!(view.cursor_byte(), (2, 4));
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn code_actions_fix_diagnostics() {
+    let path = temp_file("actions", "a\nb // TODO\n");
+    let mut buf = Buffer::open(&path).unwrap();
+    let mut view = View::new(&mut buf);
+    let mut events = EventLoop::new();
+    let mut client = start(&events, &path);
+
+    client.sync(&mut view).unwrap();
+    diagnostics(&mut events, &mut client, &mut view);
+
+    view.goto(1, 0);
+    client.code_actions(&mut view).unwrap();
+    let actions = match reply(&mut events, &mut client) {
+        Reply::CodeActions(actions) => actions,
+        r => panic!("expected code actions: {:?}", r),
+    };
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].title, "Mark done");
+
+    client.apply_workspace_edit(&mut view, &actions[0].edit).unwrap();
+    assert_eq!(view.line(1), Some("b // DONE"));
+
+    // the server sees the fix and clears the warning
+    client.sync(&mut view).unwrap();
+    assert_eq!(diagnostics(&mut events, &mut client, &mut view), 0);
+    assert!(view.signs(1).is_empty());
+
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
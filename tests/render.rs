@@ -0,0 +1,462 @@
+use std::{
+    fs,
+    rc::Rc,
+};
+
+use editor::{
+    Buffer,
+    Config,
+    Float,
+    Floats,
+    Grammar,
+    Highlighter,
+    Region,
+    Renderer,
+    Sign,
+    SignColumn,
+    StatusInfo,
+    StatusLine,
+    TestRenderer,
+    Theme,
+    View,
+    complete::{
+        Candidate,
+        Menu,
+    },
+    float::Anchor,
+};
+
+
+fn render(view: &mut View<'_>, width: usize, height: usize) -> TestRenderer {
+    let mut r = TestRenderer::new(width, height);
+    view.render(&mut r).unwrap();
+    r
+}
+
+#[test]
+fn draws_lines_and_pads_the_screen() {
+    let mut buf = Buffer::from("hello\nworld");
+    let mut view = View::new(&mut buf);
+
+    let r = render(&mut view, 10, 4);
+    assert_eq!(r.dump(), "hello\nworld\n\n\n");
+    assert_eq!(r.cursor(), (0, 0));
+}
+
+#[test]
+fn cursor_follows_edits() {
+    let mut buf = Buffer::from("hello\nworld");
+    let mut view = View::new(&mut buf);
+    view.scroll_rel(1);
+    view.insert_str("new ");
+
+    let r = render(&mut view, 10, 2);
+    assert_eq!(r.dump(), "hello\nnew world\n");
+    assert_eq!(r.cursor(), (4, 1));
+}
+
+#[test]
+fn scrolls_to_keep_the_cursor_on_screen() {
+    let text: Vec<String> = (1..=10).map(|i| i.to_string()).collect();
+    let mut buf = Buffer::from(text.join("\n").as_str());
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().scroll_off = 1;
+    view.scroll_rel(5);
+
+    let r = render(&mut view, 4, 3);
+    assert_eq!(r.dump(), "5\n6\n7\n");
+    assert_eq!(r.cursor(), (0, 1));
+}
+
+#[test]
+fn clips_long_lines_with_markers() {
+    let mut buf = Buffer::from("0123456789abcdef");
+    let mut view = View::new(&mut buf);
+    view.last_col();
+
+    let r = render(&mut view, 8, 1);
+    assert_eq!(r.dump(), "<abcdef\n");
+    assert_eq!(r.cursor(), (7, 0));
+
+    view.first_col();
+    let r = render(&mut view, 8, 1);
+    assert_eq!(r.dump(), "0123456>\n");
+}
+
+#[test]
+fn wraps_at_word_boundaries_with_indent() {
+    let mut buf = Buffer::from("  one two three four");
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().wrap = true;
+    view.view_config_mut().line_break = true;
+    view.view_config_mut().break_indent = true;
+    view.last_col();
+
+    let r = render(&mut view, 10, 3);
+    assert_eq!(r.dump(), "  one two\n  three\n  four\n");
+    assert_eq!(r.cursor(), (6, 2));
+}
+
+#[test]
+fn wide_graphemes_keep_columns_aligned() {
+    let mut buf = Buffer::from("界a\nab");
+    let mut view = View::new(&mut buf);
+    view.last_col();
+
+    let r = render(&mut view, 6, 2);
+    assert_eq!(r.dump(), "界a\nab\n");
+    assert_eq!(r.cursor(), (3, 0));
+}
+
+#[test]
+fn control_characters_drawn_in_caret_notation() {
+    let mut buf = Buffer::from("a\x1b[31mb\rc\nx");
+    let mut view = View::new(&mut buf);
+    view.last_col();
+
+    let r = render(&mut view, 12, 2);
+    assert_eq!(r.dump(), "a^[[31mb^Mc\nx\n");
+    assert_eq!(r.cursor(), (11, 0));
+    assert!(r.grid().row(0).iter().all(|c| !c.text.contains(char::is_control)));
+}
+
+#[test]
+fn resize_keeps_the_cursor() {
+    let text: Vec<String> = (1..=10).map(|i| i.to_string()).collect();
+    let mut buf = Buffer::from(text.join("\n").as_str());
+    let mut view = View::new(&mut buf);
+    view.scroll_rel(5);
+    view.last_col();
+
+    let mut r = render(&mut view, 4, 8);
+    assert_eq!(r.cursor(), (1, 5));
+
+    r.resize(4, 3);
+    r.set_xy(0, 0);
+    view.render(&mut r).unwrap();
+    assert_eq!(r.dump(), "4\n5\n6\n");
+    assert_eq!(r.cursor(), (1, 2));
+    assert_eq!(view.cursor(), (1, 5));
+}
+
+#[test]
+fn selection_is_drawn_reversed() {
+    let mut buf = Buffer::from("hello\nworld");
+    let mut view = View::new(&mut buf);
+    render(&mut view, 10, 2);
+
+    let (idx, col) = view.pos_at(3, 0);
+    view.goto(idx, col);
+    view.set_anchor();
+    let (idx, col) = view.pos_at(1, 1);
+    view.goto(idx, col);
+
+    let r = render(&mut view, 10, 2);
+    assert_eq!(r.dump_styled(), "hel<reverse>lo </>\n<reverse>wo</>rld\n");
+}
+
+#[test]
+fn pasted_text_splits_lines() {
+    let mut buf = Buffer::from("ad");
+    let mut view = View::new(&mut buf);
+    view.next_col();
+    view.insert_text("b\r\n  c");
+
+    let r = render(&mut view, 10, 3);
+    assert_eq!(r.dump(), "ab\n  cd\n\n");
+    assert_eq!(r.cursor(), (3, 1));
+}
+
+#[test]
+fn status_line_under_the_view() {
+    let mut buf = Buffer::from("hello\nworld\nagain");
+    let mut view = View::new(&mut buf);
+    view.scroll_rel(1);
+    view.last_col();
+    view.insert_str("!");
+
+    let mut r = TestRenderer::new(30, 3);
+    view.render(&mut Region::new(&mut r, 30, 2)).unwrap();
+    let cur = r.cursor();
+
+    r.set_xy(0, 2);
+    let info = StatusInfo::new(&view, "INSERT", None);
+    StatusLine::new("%m %f%M%=%l:%c %p%%").render(&mut r, &info, &Theme::default()).unwrap();
+
+    assert_eq!(r.dump_styled(),
+        "hello\nworld!\n<reverse>INSERT [No Name] [+]   2:7 66%</>\n");
+    assert_eq!(cur, (6, 1));
+    assert_eq!(r.width(), 30);
+}
+
+#[test]
+fn gutter_with_hybrid_numbers() {
+    let text: Vec<String> = (1..=4).map(|i| format!("l{}", i)).collect();
+    let mut buf = Buffer::from(text.join("\n").as_str());
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().number = true;
+    view.view_config_mut().relative_number = true;
+    view.scroll_rel(1);
+    view.last_col();
+
+    let r = render(&mut view, 10, 4);
+    assert_eq!(r.dump(), "  1 l1\n2   l2\n  1 l3\n  2 l4\n");
+    assert_eq!(r.cursor(), (6, 1));
+    assert_eq!(view.pos_at(5, 2), (2, 1));
+}
+
+#[test]
+fn signs_stay_on_their_lines() {
+    let mut buf = Buffer::from("a\nb");
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().number = true;
+
+    let r = render(&mut view, 10, 2);
+    assert_eq!(r.dump(), "  1 a\n  2 b\n");
+
+    view.add_sign(1, Sign{
+        group: "diagnostics".into(),
+        text: "E".into(),
+        highlight: "Error".into(),
+        priority: 10,
+    });
+    view.insert_line_above();
+
+    let r = render(&mut view, 10, 3);
+    assert_eq!(r.dump_styled(), "  <fg=yellow>  1 </>\n  <fg=yellow bold>  2 </>a\n\
+        <fg=white bg=red>E </><fg=yellow>  3 </>b\n");
+    assert_eq!(r.cursor(), (6, 1));
+
+    view.remove_signs("diagnostics");
+    view.view_config_mut().sign_column = SignColumn::Yes;
+    let r = render(&mut view, 10, 1);
+    assert_eq!(r.dump(), "    2 a\n");
+}
+
+#[test]
+fn highlights_after_edits() {
+    let grammar = Grammar::load("syntax/rust.syntax").unwrap();
+    let theme = Theme::parse("test", "hi Keyword bold\nhi Comment fg=blue").unwrap();
+
+    let mut buf = Buffer::from("let a;\nb\nlet c;");
+    let mut view = View::new(&mut buf);
+    view.set_highlighter(Some(Highlighter::new(Rc::new(grammar), &theme)));
+
+    let r = render(&mut view, 10, 3);
+    assert_eq!(r.dump_styled(), "<bold>let</> a;\nb\n<bold>let</> c;\n");
+
+    // a comment opened on the first line covers the rest
+    view.last_col();
+    view.insert_str(" /*");
+    let r = render(&mut view, 10, 3);
+    assert_eq!(r.dump_styled(),
+        "<bold>let</> a; <fg=blue>/*</>\n<fg=blue>b</>\n<fg=blue>let c;</>\n");
+}
+
+#[test]
+fn colorschemes_style_everything_drawn() {
+    let dir = std::env::temp_dir().join(format!("editor-colors-{}", std::process::id()));
+    fs::create_dir_all(dir.join("colors")).unwrap();
+    fs::write(dir.join("config"), "colorscheme dark\nhi link Keyword Type\n").unwrap();
+    fs::write(dir.join("colors").join("dark"), "\
+        hi Normal bg=black\n\
+        hi LineNr fg=blue\n\
+        hi link CursorLineNr LineNr\n\
+        hi Type fg=green\n").unwrap();
+
+    let (mut config, err) = Config::load(dir.join("config"));
+    assert!(err.is_none());
+    assert_eq!(config.theme.name, "dark");
+
+    let grammar = Grammar::load("syntax/rust.syntax").unwrap();
+    let mut buf = Buffer::from("let a;");
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().number = true;
+    view.set_highlighter(Some(Highlighter::new(Rc::new(grammar), &Theme::default())));
+    view.set_theme(&config.theme);
+
+    let r = render(&mut view, 12, 2);
+    assert_eq!(r.dump_styled(),
+        "<fg=blue>  1 </><fg=green bg=black>let</><bg=black> a;  </>\n\
+        <fg=blue>    </><bg=black>        </>\n");
+
+    assert_eq!(config.exec("colorscheme light").unwrap_err(), "no such colorscheme: light");
+    config.exec("colorscheme default").unwrap();
+    view.set_theme(&config.theme);
+    let r = render(&mut view, 12, 1);
+    assert_eq!(r.dump_styled(), "<fg=yellow bold>  1 </><fg=yellow>let</> a;\n");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn completion_menu_under_the_word() {
+    let mut buf = Buffer::from("let value = 1;\nlet v");
+    let mut view = View::new(&mut buf);
+    view.scroll_rel(1);
+    view.last_col();
+
+    let vertex = Candidate{
+        doc: Some("a point".into()),
+        ..Candidate::new("vertex", "lsp")
+    };
+    let mut menu = Menu::new(view.word_start(), "v", vec![Candidate::new("value", "word"), vertex]);
+    menu.select(1);
+
+    let mut floats = Floats::default();
+    let hover = floats.open(Float::new(Anchor::Screen(10, 2), "hover"));
+    let id = floats.open(menu.float(Anchor::Buffer(1, 4)));
+
+    let mut r = render(&mut view, 30, 5);
+    floats.render(r.grid_mut(), &Theme::default(), |idx, col| view.screen_pos(idx, col));
+    // drawn over the hover float
+    assert_eq!(r.dump(), "let value = 1;\nlet v\n     value   word  a point\n     vertex  lsp\n          └─────┘\n");
+    assert!(r.dump_styled().contains("<fg=white bg=blue> vertex  lsp  </>"));
+
+    floats.close(hover, r.grid_mut());
+    assert_eq!(r.dump(), "let value = 1;\nlet v\n     value   word  a point\n     vertex  lsp\n\n");
+
+    // near the bottom it goes above the word, next to the edge
+    // it's moved left
+    let mut buf = Buffer::from("let value = 1;\n\nlet v");
+    let mut view = View::new(&mut buf);
+    menu.select(-1);
+    *floats.get_mut(id).unwrap() = menu.float(Anchor::Buffer(2, 10));
+    let mut r = render(&mut view, 16, 3);
+    floats.render(r.grid_mut(), &Theme::default(), |idx, col| view.screen_pos(idx, col));
+    assert_eq!(r.dump(), "le value   word\n   vertex  lsp\nlet v\n");
+}
+
+#[test]
+fn float_over_the_view_and_closed() {
+    let mut buf = Buffer::from("one\ntwo three\nfour");
+    let mut view = View::new(&mut buf);
+    let mut r = render(&mut view, 12, 4);
+    let before = r.dump();
+
+    let mut floats = Floats::default();
+    floats.open(Float::new(Anchor::Buffer(1, 4), "hi"));
+    floats.render(r.grid_mut(), &Theme::default(), |idx, col| view.screen_pos(idx, col));
+    // there's more room under the line, so it goes there and
+    // is cut off by the bottom
+    assert_eq!(r.dump(), "one\ntwo three\nfour┌──┐\n    │hi│\n");
+
+    // off screen it isn't drawn
+    assert_eq!(view.screen_pos(5, 0), None);
+
+    floats.close_all(r.grid_mut());
+    assert!(floats.is_empty());
+    assert_eq!(r.dump(), before);
+}
+
+#[test]
+fn horizontal_scroll_only_when_the_cursor_leaves() {
+    let mut buf = Buffer::from("0123456789abcdef\nxy");
+    let mut view = View::new(&mut buf);
+    view.goto(0, 12);
+
+    let r = render(&mut view, 8, 2);
+    assert_eq!(r.dump(), "<6789ab>\n<\n");
+    assert_eq!(r.cursor(), (7, 0));
+
+    // moving back inside the screen keeps the offset
+    view.goto(0, 7);
+    let r = render(&mut view, 8, 2);
+    assert_eq!(r.dump(), "<6789ab>\n<\n");
+    assert_eq!(r.cursor(), (2, 0));
+
+    view.goto(0, 3);
+    let r = render(&mut view, 8, 2);
+    assert_eq!(r.dump(), "<456789>\n<\n");
+    assert_eq!(r.cursor(), (0, 0));
+}
+
+#[test]
+fn wide_graphemes_cut_by_the_edges() {
+    let mut buf = Buffer::from("a界界界界b");
+    let mut view = View::new(&mut buf);
+    view.goto(0, 9);
+
+    // the half of a grapheme left on screen is blank
+    let r = render(&mut view, 7, 1);
+    assert_eq!(r.dump(), "< 界界b\n");
+    assert_eq!(r.cursor(), (6, 0));
+
+    view.first_col();
+    let r = render(&mut view, 7, 1);
+    assert_eq!(r.dump(), "a界界 >\n");
+}
+
+#[test]
+fn wraps_at_grapheme_boundaries() {
+    let mut buf = Buffer::from("ae\u{301}界cd\nx");
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().wrap = true;
+
+    // the wide grapheme doesn't fit after the first two
+    let r = render(&mut view, 3, 4);
+    assert_eq!(r.dump(), "ae\u{301}\n界c\nd\nx\n");
+}
+
+#[test]
+fn row_motions_move_by_screen_rows() {
+    let mut buf = Buffer::from("0123456789\nab");
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().wrap = true;
+    view.goto(0, 1);
+    render(&mut view, 4, 4);
+
+    view.next_row();
+    assert_eq!(view.cursor(), (5, 0));
+    view.next_row();
+    assert_eq!(view.cursor(), (9, 0));
+    let r = render(&mut view, 4, 4);
+    assert_eq!(r.cursor(), (1, 2));
+
+    view.next_row();
+    assert_eq!(view.cursor(), (1, 1));
+    view.prev_row();
+    assert_eq!(view.cursor(), (9, 0));
+
+    // lines are still moved over whole
+    view.scroll_rel(1);
+    assert_eq!(view.cursor().1, 1);
+}
+
+#[test]
+fn scroll_commands_move_the_view() {
+    let text: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+    let mut buf = Buffer::from(text.join("\n").as_str());
+    let mut view = View::new(&mut buf);
+    view.view_config_mut().scroll_off = 1;
+
+    // moving inside the screen doesn't scroll
+    view.scroll_rel(2);
+    let r = render(&mut view, 4, 5);
+    assert_eq!(r.dump(), "1\n2\n3\n4\n5\n");
+    assert_eq!(r.cursor(), (0, 2));
+
+    // the cursor stays unless it would be in the scroll off
+    view.scroll_view(1);
+    assert_eq!(render(&mut view, 4, 5).dump(), "2\n3\n4\n5\n6\n");
+    assert_eq!(view.cursor().1, 2);
+    view.scroll_view(1);
+    assert_eq!(view.cursor().1, 3);
+
+    view.scroll_half_page(1);
+    assert_eq!(render(&mut view, 4, 5).dump(), "5\n6\n7\n8\n9\n");
+    assert_eq!(view.cursor().1, 5);
+
+    // two lines are kept from the last screen
+    view.scroll_page(1);
+    assert_eq!(render(&mut view, 4, 5).dump(), "8\n9\n10\n11\n12\n");
+    assert_eq!(view.cursor().1, 8);
+
+    view.scroll_cursor_center();
+    assert_eq!(render(&mut view, 4, 5).dump(), "7\n8\n9\n10\n11\n");
+    view.scroll_cursor_top();
+    assert_eq!(render(&mut view, 4, 5).dump(), "8\n9\n10\n11\n12\n");
+    view.scroll_cursor_bottom();
+    assert_eq!(render(&mut view, 4, 5).dump(), "6\n7\n8\n9\n10\n");
+    assert_eq!(view.cursor().1, 8);
+}
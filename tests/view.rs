@@ -0,0 +1,335 @@
+use std::{
+    fs,
+};
+
+use editor::{
+    Buffer,
+    complete::{
+        Candidate,
+        Menu,
+    },
+    lsp::{
+        CompletionItem,
+        Position,
+        Range,
+        TextEdit,
+    },
+    LineChange,
+    Snippet,
+    View,
+};
+
+
+#[test]
+fn recovery_copy_only_when_modified() {
+    let dir = std::env::temp_dir().join(format!("editor-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("a.txt");
+    fs::write(&path, "one\n").unwrap();
+
+    let mut buf = Buffer::open(&path).unwrap();
+    let mut view = View::new(&mut buf);
+    assert_eq!(view.write_recovery().unwrap(), None);
+
+    view.insert_str("new ");
+    assert!(view.is_modified());
+    let rec = view.write_recovery().unwrap().unwrap();
+    assert_eq!(rec, dir.join("a.txt.recover"));
+    assert_eq!(fs::read_to_string(&rec).unwrap(), "new one\n");
+
+    view.save().unwrap();
+    assert!(!view.is_modified());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_edits_leave_the_buffer_unmodified() {
+    let mut buf = Buffer::from("one\ntwo");
+    let mut view = View::new(&mut buf);
+    view.goto(1, 0);
+
+    view.insert_str("");
+    view.insert_text("");
+    view.replace((0, 2), (0, 2), "");
+    view.backspace();
+    view.reindent(0, 1);
+    assert!(!view.is_modified());
+    assert!(!view.undo());
+
+    view.insert_str("x");
+    assert!(view.is_modified());
+}
+
+#[test]
+fn filetype_from_contents_and_rules() {
+    let mut buf = Buffer::from("#!/usr/bin/env python3\nprint()\n# vim: ft=cython");
+    buf.detect_filetype(&[]);
+    assert_eq!(buf.file_config().filetype.as_deref(), Some("cython"));
+
+    let mut buf = Buffer::from("x = 1");
+    buf.file_config_mut().path = Some("setup.cfg".into());
+    buf.detect_filetype(&[("dosini".into(), "*.cfg".into())]);
+    assert_eq!(buf.file_config().filetype.as_deref(), Some("dosini"));
+}
+
+#[test]
+fn completing_replaces_the_word_in_one_edit() {
+    let mut buf = Buffer::from("x\nself.val(1)");
+    let mut view = View::new(&mut buf);
+    view.goto(1, 8);
+    assert_eq!(view.word_start(), (1, 5));
+
+    let items = vec![Candidate::new("value", "word"), Candidate::new("vertex", "word")];
+    let menu = Menu::new(view.word_start(), "val", items);
+    view.take_changes();
+    assert_eq!(menu.accept(&mut view), None);
+    assert_eq!(view.line(1), Some("self.value(1)"));
+    assert_eq!(view.cursor_byte(), (1, 10));
+    assert_eq!(view.take_changes(), Some(LineChange::new(1, 1, 1)));
+
+    // typing and the completion are undone apart
+    view.commit_undo();
+    view.insert_str(" + x");
+    view.commit_undo();
+    view.goto(1, 10);
+    view.commit_undo();
+    view.replace(view.word_start(), view.cursor_byte(), "vertex");
+    view.insert_str("s");
+    assert_eq!(view.line(1), Some("self.vertexs + x(1)"));
+    assert!(view.undo());
+    assert_eq!(view.line(1), Some("self.value + x(1)"));
+    assert_eq!(view.cursor_byte(), (1, 10));
+    assert!(view.undo());
+    assert_eq!(view.line(1), Some("self.value(1)"));
+    assert!(view.undo());
+    assert_eq!(view.line(1), Some("self.val(1)"));
+    assert!(!view.undo());
+
+    // no word before the cursor
+    view.goto(1, 11);
+    assert_eq!(view.word_start(), (1, 11));
+}
+
+#[test]
+fn completion_edits_replace_their_range() {
+    let mut buf = Buffer::from("foo.ba");
+    let mut view = View::new(&mut buf);
+    view.last_col();
+
+    // the server was asked before the `a` was typed
+    let item = CompletionItem{
+        label: "bar".into(),
+        detail: None,
+        documentation: None,
+        text: "bar".into(),
+        edit: Some(TextEdit{
+            range: Range{
+                start: Position{line: 0, character: 3},
+                end: Position{line: 0, character: 5},
+            },
+            text: "->bar()".into(),
+        }),
+        snippet: false,
+    };
+    let c = Candidate::from_lsp(&item, Some(((0, 3), (0, 5))));
+    let menu = Menu::new(view.word_start(), "ba", vec![c]);
+    assert_eq!(menu.accept(&mut view), None);
+    assert_eq!(view.line(0), Some("foo->bar()"));
+    assert_eq!(view.cursor_byte(), (0, 10));
+}
+
+#[test]
+fn undo_takes_back_split_and_added_lines() {
+    let mut buf = Buffer::from("one two\nend");
+    let mut view = View::new(&mut buf);
+    view.goto(0, 3);
+    view.newline();
+    view.open_below();
+    view.insert_str("x");
+    view.commit_undo();
+    view.replace((0, 0), (2, 1), "");
+    assert_eq!(view.lines().collect::<Vec<_>>(), vec!["", "end"]);
+
+    assert!(view.undo());
+    assert_eq!(view.lines().collect::<Vec<_>>(), vec!["one", "two", "x", "end"]);
+    assert!(view.undo());
+    assert_eq!(view.lines().collect::<Vec<_>>(), vec!["one two", "end"]);
+    assert_eq!(view.cursor(), (3, 0));
+    assert!(view.is_modified());
+}
+
+#[test]
+fn snippet_tabstops_and_mirrors() {
+    let mut buf = Buffer::from("  \nend");
+    let mut view = View::new(&mut buf);
+    view.line_config_mut().expand_tab = true;
+    view.line_config_mut().indent_size = 4;
+    view.goto(0, 2);
+
+    let snippet = Snippet::parse("fn ${1:name}(${2:arg}: ${3|i32,u8|}) {\n\t$0\n}\n// $1");
+    let mut session = snippet.expand(&mut view).unwrap();
+    let lines: Vec<&str> = view.lines().collect();
+    assert_eq!(lines, vec!["  fn name(arg: i32) {", "      ", "  }", "  // name", "end"]);
+
+    // the placeholder is selected and typing replaces it, the
+    // mirror follows
+    assert_eq!(view.cursor_byte(), (0, 5));
+    assert_eq!(view.selection_bytes(), Some(((0, 5), (0, 9))));
+    session.clear_placeholder(&mut view);
+    view.insert_str("run");
+    assert!(session.update(&mut view));
+    assert_eq!(view.line(0), Some("  fn run(arg: i32) {"));
+    assert_eq!(view.line(3), Some("  // run"));
+
+    assert!(session.jump(&mut view, 1));
+    assert_eq!(view.cursor_byte(), (0, 9));
+    assert!(session.jump(&mut view, 1));
+    assert_eq!(session.choices(), ["i32", "u8"]);
+    assert_eq!(session.start(), (0, 14));
+    assert!(session.jump(&mut view, -1));
+    assert_eq!(view.cursor_byte(), (0, 9));
+
+    // leaving the snippet ends it
+    view.goto(4, 0);
+    assert!(!session.update(&mut view));
+
+    // at $0 it's over
+    let mut session = snippet.expand(&mut view).unwrap();
+    assert!(session.jump(&mut view, 1));
+    assert!(session.jump(&mut view, 1));
+    assert!(!session.jump(&mut view, 1));
+    assert_eq!(view.cursor_byte(), (5, 4));
+    assert_eq!(view.selection(), None);
+}
+
+#[test]
+fn new_lines_follow_the_brackets() {
+    let mut buf = Buffer::from("fn main() {\n    let x = (1,\n}");
+    let mut view = View::new(&mut buf);
+    view.line_config_mut().expand_tab = true;
+    view.line_config_mut().indent_size = 4;
+    view.line_config_mut().indent_brackets = "(){}[]";
+
+    // enter splits the line, the rest is indented a level in
+    view.goto(0, 11);
+    view.newline();
+    assert_eq!(view.line(1), Some("    "));
+    assert_eq!(view.cursor(), (4, 1));
+
+    view.goto(2, 14);
+    view.open_below();
+    view.insert_str("2);");
+    view.open_below();
+    assert_eq!(view.cursor(), (4, 4));
+    view.open_above();
+    assert_eq!(view.cursor(), (4, 4));
+
+    // a closing bracket typed first takes the line back
+    view.goto(5, 4);
+    view.insert_str("]");
+    view.reindent_closer();
+    assert_eq!(view.line(5), Some("]"));
+
+    let lines: Vec<&str> = view.lines().collect();
+    assert_eq!(lines, vec![
+        "fn main() {", "    ", "    let x = (1,", "        2);", "    ", "]", "}",
+    ]);
+}
+
+#[test]
+fn reindenting_lines() {
+    let mut buf = Buffer::from("if x {\nfoo(\na,\n  b)\n  \n      } else {\n\tc\n}");
+    let mut view = View::new(&mut buf);
+    view.line_config_mut().tab_width = 4;
+    view.line_config_mut().indent_brackets = "(){}[]";
+    view.goto(6, 4);
+
+    view.reindent(0, 7);
+    let lines: Vec<&str> = view.lines().collect();
+    assert_eq!(lines, vec!["if x {", "\tfoo(", "\t\ta,", "\t\tb)", "", "} else {", "\tc", "}"]);
+    assert_eq!(view.cursor_byte(), (6, 1));
+
+    // without brackets the indent is copied
+    view.line_config_mut().indent_brackets = "";
+    view.reindent(2, 2);
+    assert_eq!(view.line(2), Some("\ta,"));
+}
+
+#[test]
+fn first_non_space_skips_the_indent() {
+    let mut buf = Buffer::from("    foo\n\t bar\nbaz\n   ");
+    let mut view = View::new(&mut buf);
+    view.line_config_mut().tab_width = 4;
+
+    view.goto(0, 6);
+    view.first_non_space_col();
+    assert_eq!(view.cursor(), (4, 0));
+
+    // tabs count their width
+    view.goto(1, 0);
+    view.first_non_space_col();
+    assert_eq!(view.cursor(), (5, 1));
+
+    view.goto(2, 2);
+    view.first_non_space_col();
+    assert_eq!(view.cursor(), (0, 2));
+
+    // a blank line goes to its end
+    view.goto(3, 0);
+    view.first_non_space_col();
+    assert_eq!(view.cursor(), (3, 3));
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn structural_motions_follow_the_tree() {
+    let mut buf = Buffer::from("fn a(x: u8, y: u8) {\n}\n\nfn b() {}");
+    let mut view = View::new(&mut buf);
+    let tree = editor::SyntaxTree::for_filetype("rust", None).unwrap();
+    view.set_syntax_tree(tree);
+
+    assert!(view.goto_object("function.outer", true));
+    assert_eq!(view.cursor(), (0, 3));
+    assert!(!view.goto_object("function.outer", true));
+
+    // an edit moves the function
+    view.insert_line_above();
+    assert!(view.goto_object("function.outer", false));
+    assert_eq!(view.cursor(), (0, 0));
+    assert!(view.goto_object("function.outer", true));
+    assert_eq!(view.cursor(), (0, 4));
+
+    view.goto(0, 12);
+    assert!(view.select_object("parameter.outer"));
+    assert_eq!(view.selection(), Some(((0, 12), (0, 16))));
+    assert!(view.expand_selection());
+    assert_eq!(view.selection(), Some(((0, 4), (0, 17))));
+}
+
+#[cfg(feature = "tree-sitter")]
+#[test]
+fn grammar_replaces_the_tree_when_the_filetype_changes() {
+    let dir = std::env::temp_dir().join(format!("editor-test-hl-{}", std::process::id()));
+    fs::create_dir_all(dir.join("syntax")).unwrap();
+    fs::copy("syntax/rust.syntax", dir.join("syntax/c.syntax")).unwrap();
+    let config = editor::Config{
+        path: Some(dir.join("config")),
+        ..editor::Config::default()
+    };
+
+    let mut buf = Buffer::from("fn a() {}");
+    let mut view = View::new(&mut buf);
+    view.file_config_mut().filetype = Some("rust".into());
+    config.load_highlighter(&mut view).unwrap();
+    assert!(view.syntax_tree_mut().is_some());
+    assert!(view.highlighter_mut().is_none());
+
+    view.file_config_mut().filetype = Some("c".into());
+    config.load_highlighter(&mut view).unwrap();
+    assert!(view.syntax_tree_mut().is_none());
+    assert!(view.highlighter_mut().is_some());
+    assert!(!view.goto_object("function.outer", true));
+
+    fs::remove_dir_all(&dir).unwrap();
+}